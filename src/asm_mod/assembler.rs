@@ -0,0 +1,735 @@
+//!
+//! Code related to turning assembly source into ROMs.
+//!
+//! The assembler understands the statements written by Octo, the most
+//! widely used Chip 8 assembler, along with its ```:const```, ```:alias```,
+//! ```:macro```, ```:include```, ```:org```, ```:byte``` and ```:call``` directives.
+//! Octo's structured control flow (```loop```, ```while```, ```if ... begin```)
+//! and ```:calc``` expressions are not supported; use labels and ```jump``` instead.
+//!
+//! Like in Octo, a bare number is written into the ROM as a single byte,
+//! a bare name that isn't a macro is a call to the label with that name,
+//! and the program starts running at the label ```main```.
+
+use chip8_mod::cpu::PROGRAM_START;
+use chip8_mod::instruction::Instruction;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+///
+/// Limits to stop recursive includes and macros from running forever.
+const MAX_INCLUDES : usize = 256;
+const MAX_MACRO_EXPANSIONS : usize = 65536;
+
+///
+/// An error in the assembly source, and where it was found.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AsmError {
+    pub file : String,
+    pub line : usize,
+    pub message : String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl Error for AsmError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+///
+/// Maps an address in the ROM back to the source line it was assembled from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SourceLine {
+    pub addr : u16,
+    pub file : usize,
+    pub line : usize,
+}
+
+///
+/// The result of assembling a program.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Assembly {
+
+    ///
+    /// The assembled bytes, to be loaded at ```PROGRAM_START```.
+    pub rom : Vec<u8>,
+
+    ///
+    /// The address of every label.
+    pub labels : BTreeMap<String, u16>,
+
+    ///
+    /// The source line of every instruction, in the order they were assembled.
+    pub lines : Vec<SourceLine>,
+
+    ///
+    /// The names of the source files, indexed by ```SourceLine::file```.
+    pub files : Vec<String>,
+}
+
+impl Assembly {
+
+    ///
+    /// Writes out the labels and line table as a symbol file, one entry per line:
+    /// ```file [index] [path]```, ```label [name] [address]```, and
+    /// ```line [address] [file index] [line]```.
+    pub fn symbol_file(&self) -> String {
+        let mut retval = String::new();
+        for (idx, file) in self.files.iter().enumerate() {
+            retval.push_str(&format!("file {} {}\n", idx, file));
+        }
+        for (name, addr) in self.labels.iter() {
+            retval.push_str(&format!("label {} {:#05X}\n", name, addr));
+        }
+        for line in self.lines.iter() {
+            retval.push_str(&format!("line {:#05X} {} {}\n", line.addr, line.file, line.line));
+        }
+        retval
+    }
+
+    ///
+    /// Reads back a symbol file written by ```symbol_file```. The ROM is left empty.
+    pub fn from_symbol_file(text : &str, path : &str) -> Result<Assembly, AsmError> {
+        let mut retval = Assembly { rom : Vec::new(), labels : BTreeMap::new(), lines : Vec::new(), files : Vec::new() };
+        for (idx, line) in text.lines().enumerate() {
+            let error = |message : &str| AsmError { file : path.to_owned(), line : idx + 1, message : message.to_owned() };
+            let parts : Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                [] => {},
+                ["file", _, name] => retval.files.push(name.to_string()),
+                ["label", name, addr] => {
+                    let addr = parse_number(addr).ok_or_else(|| error("Bad label address"))? as u16;
+                    retval.labels.insert(name.to_string(), addr);
+                },
+                ["line", addr, file, line] => {
+                    let addr = parse_number(addr).ok_or_else(|| error("Bad line address"))? as u16;
+                    let file = file.parse::<usize>().map_err(|_| error("Bad file index"))?;
+                    let line = line.parse::<usize>().map_err(|_| error("Bad line number"))?;
+                    retval.lines.push(SourceLine { addr, file, line });
+                },
+                _ => return Err(error("Unknown symbol entry")),
+            }
+        }
+        Ok(retval)
+    }
+}
+
+///
+/// Assembles the source file at ```path```. Included files are found
+/// relative to the file that includes them.
+pub fn assemble_file(path : &Path) -> Result<Assembly, AsmError> {
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|err| AsmError { file : path.display().to_string(), line : 0, message : err.to_string() })?;
+    assemble(&source, path.to_path_buf())
+}
+
+///
+/// Assembles ```source``` directly. Included files are found relative to
+/// the current directory.
+pub fn assemble_source(source : &str) -> Result<Assembly, AsmError> {
+    assemble(source, PathBuf::from("<source>"))
+}
+
+fn assemble(source : &str, path : PathBuf) -> Result<Assembly, AsmError> {
+    let assembly = Assembler::new(source, path.clone(), false).run()?;
+    match assembly.labels.get("main") {
+        Some(&main) if main != PROGRAM_START => Assembler::new(source, path, true).run(),
+        _ => Ok(assembly),
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text : String,
+    file : usize,
+    line : usize,
+}
+
+///
+/// How an address that wasn't known yet gets written once it is.
+#[derive(Clone, Copy, Debug)]
+enum FixupKind {
+    Addr12,
+    Addr16,
+}
+
+#[derive(Clone, Debug)]
+struct Fixup {
+    idx : usize,
+    kind : FixupKind,
+    label : Token,
+}
+
+///
+/// An address operand; either already known, or a label to fill in later.
+enum Target {
+    Known(u16),
+    Label(Token),
+}
+
+struct Macro {
+    args : Vec<String>,
+    body : Vec<Token>,
+}
+
+struct Assembler {
+    files : Vec<PathBuf>,
+    tokens : VecDeque<Token>,
+    rom : Vec<u8>,
+    addr : usize,
+    labels : BTreeMap<String, u16>,
+    constants : HashMap<String, i32>,
+    aliases : HashMap<String, usize>,
+    macros : HashMap<String, Macro>,
+    fixups : Vec<Fixup>,
+    lines : Vec<SourceLine>,
+    includes : usize,
+    expansions : usize,
+    jump_to_main : bool,
+}
+
+impl Assembler {
+
+    ///
+    /// With ```jump_to_main``` a ```jump main``` is placed at the start of the ROM,
+    /// for programs that don't start with their ```main``` label.
+    fn new(source : &str, path : PathBuf, jump_to_main : bool) -> Assembler {
+        let mut retval = Assembler {
+            files : vec![path],
+            tokens : VecDeque::new(),
+            rom : Vec::new(),
+            addr : PROGRAM_START as usize,
+            labels : BTreeMap::new(),
+            constants : HashMap::new(),
+            aliases : HashMap::new(),
+            macros : HashMap::new(),
+            fixups : Vec::new(),
+            lines : Vec::new(),
+            includes : 0,
+            expansions : 0,
+            jump_to_main,
+        };
+        retval.tokens = tokenize(source, 0);
+        retval
+    }
+
+    fn run(mut self) -> Result<Assembly, AsmError> {
+        if self.jump_to_main {
+            let main = Token { text : "main".to_owned(), file : 0, line : 1 };
+            self.emit(Instruction::Jump(0), &main.clone(), Some(Target::Label(main)))?;
+        }
+        while let Some(tok) = self.tokens.pop_front() {
+            self.statement(tok)?;
+        }
+        let fixups = mem::take(&mut self.fixups);
+        for fixup in fixups {
+            let addr = match self.labels.get(&fixup.label.text) {
+                Some(&addr) => addr,
+                None => return Err(self.error(&fixup.label, format!("Undefined label {}.", fixup.label.text))),
+            };
+            if let FixupKind::Addr12 = fixup.kind {
+                if addr > 0xFFF {
+                    return Err(self.error(&fixup.label, format!("Label {} is past 0xFFF; use i := long.", fixup.label.text)));
+                }
+            }
+            self.write_addr(fixup.idx, fixup.kind, addr);
+        }
+        Ok(Assembly {
+            rom : self.rom,
+            labels : self.labels,
+            lines : self.lines,
+            files : self.files.iter().map(|path| path.display().to_string()).collect(),
+        })
+    }
+
+    fn error(&self, tok : &Token, message : String) -> AsmError {
+        AsmError {
+            file : self.files[tok.file].display().to_string(),
+            line : tok.line,
+            message,
+        }
+    }
+
+    fn next_token(&mut self, after : &Token) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(tok) => Ok(tok),
+            None => Err(self.error(after, format!("Unexpected end of file after {}.", after.text))),
+        }
+    }
+
+    fn expect(&mut self, after : &Token, text : &str) -> Result<Token, AsmError> {
+        let tok = self.next_token(after)?;
+        if tok.text != text {
+            return Err(self.error(&tok, format!("Expected {} but found {}.", text, tok.text)));
+        }
+        Ok(tok)
+    }
+
+    fn peek_is(&self, text : &str) -> bool {
+        self.tokens.front().map(|tok| tok.text == text).unwrap_or(false)
+    }
+
+    fn statement(&mut self, tok : Token) -> Result<(), AsmError> {
+        match tok.text.as_ref() {
+            ":" => {
+                let name = self.next_token(&tok)?;
+                if self.labels.contains_key(&name.text) {
+                    return Err(self.error(&name, format!("Label {} is already defined.", name.text)));
+                }
+                self.labels.insert(name.text, self.addr as u16);
+            },
+            ":const" => {
+                let name = self.next_token(&tok)?;
+                let value = self.next_token(&name)?;
+                let value = self.number(&value)?;
+                self.constants.insert(name.text, value);
+            },
+            ":alias" => {
+                let name = self.next_token(&tok)?;
+                let reg = self.next_token(&name)?;
+                let reg = self.register(&reg)?;
+                self.aliases.insert(name.text, reg);
+            },
+            ":macro" => self.define_macro(&tok)?,
+            ":include" => self.include(&tok)?,
+            ":org" => {
+                let addr = self.next_token(&tok)?;
+                let value = self.number(&addr)?;
+                if value < PROGRAM_START as i32 || value > 0xFFFF {
+                    return Err(self.error(&addr, format!("Can't assemble code at {:#X}.", value)));
+                }
+                self.addr = value as usize;
+            },
+            ":byte" => {
+                let value = self.next_token(&tok)?;
+                let byte = self.byte(&value)?;
+                self.emit_bytes(&[byte]);
+            },
+            ":call" => {
+                let target = self.address(&tok)?;
+                self.emit(Instruction::Call(0), &tok, Some(target))?;
+            },
+            _ if parse_number(&tok.text).is_some() || self.constants.contains_key(&tok.text) => {
+                let byte = self.byte(&tok)?;
+                self.emit_bytes(&[byte]);
+            },
+            _ if self.macros.contains_key(&tok.text) => self.expand_macro(tok)?,
+            _ => self.instruction(tok)?,
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self, tok : &Token) -> Result<(), AsmError> {
+        let name = self.next_token(tok)?;
+        let mut args = Vec::new();
+        loop {
+            let arg = self.next_token(&name)?;
+            if arg.text == "{" {
+                break;
+            }
+            args.push(arg.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let body_tok = self.next_token(&name)?;
+            if body_tok.text == "{" {
+                depth += 1;
+            }
+            else if body_tok.text == "}" {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            body.push(body_tok);
+        }
+        self.macros.insert(name.text, Macro { args, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, tok : Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(self.error(&tok, format!("Too many macro expansions; is {} recursive?", tok.text)));
+        }
+        let arg_count = self.macros[&tok.text].args.len();
+        let mut values = HashMap::new();
+        for idx in 0 .. arg_count {
+            let value = self.next_token(&tok)?;
+            values.insert(self.macros[&tok.text].args[idx].clone(), value.text);
+        }
+        // Expanded tokens are reported at the line the macro was used on
+        let expanded : Vec<Token> = self.macros[&tok.text].body.iter().map(|body_tok| Token {
+            text : values.get(&body_tok.text).cloned().unwrap_or_else(|| body_tok.text.clone()),
+            file : tok.file,
+            line : tok.line,
+        }).collect();
+        for body_tok in expanded.into_iter().rev() {
+            self.tokens.push_front(body_tok);
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, tok : &Token) -> Result<(), AsmError> {
+        let name = self.next_token(tok)?;
+        if !name.text.starts_with('"') || !name.text.ends_with('"') || name.text.len() < 2 {
+            return Err(self.error(&name, format!("Expected a quoted file name but found {}.", name.text)));
+        }
+        self.includes += 1;
+        if self.includes > MAX_INCLUDES {
+            return Err(self.error(&name, "Too many includes; is a file including itself?".to_owned()));
+        }
+        let dir = self.files[tok.file].parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        let path = dir.join(&name.text[1 .. name.text.len() - 1]);
+        let mut source = String::new();
+        File::open(&path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| self.error(&name, format!("Can't include {}: {}", path.display(), err)))?;
+        self.files.push(path);
+        let included = tokenize(&source, self.files.len() - 1);
+        for inc_tok in included.into_iter().rev() {
+            self.tokens.push_front(inc_tok);
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, tok : Token) -> Result<(), AsmError> {
+        let instr = match tok.text.as_ref() {
+            "clear" => Instruction::ClearScreen,
+            "return" => Instruction::Return,
+            "exit" => Instruction::Exit,
+            "lores" => Instruction::LowRes,
+            "hires" => Instruction::HighRes,
+            "scroll-left" => Instruction::ScrollLeft,
+            "scroll-right" => Instruction::ScrollRight,
+            "scroll-down" => Instruction::ScrollDown(self.nibble_operand(&tok)?),
+            "audio" => Instruction::LoadAudioPattern,
+            "plane" => Instruction::SelectPlanes(self.nibble_operand(&tok)?),
+            "jump" => {
+                let target = self.address(&tok)?;
+                return self.emit(Instruction::Jump(0), &tok, Some(target));
+            },
+            "jump0" => {
+                let target = self.address(&tok)?;
+                return self.emit(Instruction::AddJumpV0(0), &tok, Some(target));
+            },
+            "bcd" => Instruction::StoreDigits(self.register_operand(&tok)?),
+            "saveflags" => Instruction::SaveFlags(self.register_operand(&tok)?),
+            "loadflags" => Instruction::RestoreFlags(self.register_operand(&tok)?),
+            "save" | "load" => {
+                let start = self.register_operand(&tok)?;
+                let is_save = tok.text == "save";
+                if self.peek_is("-") {
+                    let dash = self.next_token(&tok)?;
+                    let end = self.register_operand(&dash)?;
+                    if is_save { Instruction::SaveRegisterRange(start, end) } else { Instruction::RestoreRegisterRange(start, end) }
+                }
+                else if is_save {
+                    Instruction::SaveRegisters(start)
+                }
+                else {
+                    Instruction::RestoreRegisters(start)
+                }
+            },
+            "sprite" => {
+                let x = self.register_operand(&tok)?;
+                let y = self.register_operand(&tok)?;
+                match self.nibble_operand(&tok)? {
+                    0 => Instruction::DrawLargeSprite(x, y),
+                    rows => Instruction::DrawSprite(x, y, rows),
+                }
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(&tok, ":=")?;
+                let reg = self.register_operand(&tok)?;
+                match tok.text.as_ref() {
+                    "delay" => Instruction::SetTimer(reg),
+                    "buzzer" => Instruction::SetAudio(reg),
+                    _ => Instruction::SetPitch(reg),
+                }
+            },
+            "i" => return self.i_statement(tok),
+            "if" => self.if_statement(&tok)?,
+            _ if self.is_register(&tok.text) => self.register_statement(&tok)?,
+            _ => {
+                // A bare name is a call to the label with that name
+                return self.emit(Instruction::Call(0), &tok.clone(), Some(Target::Label(tok)));
+            },
+        };
+        self.emit(instr, &tok, None)
+    }
+
+    fn i_statement(&mut self, tok : Token) -> Result<(), AsmError> {
+        let op = self.next_token(&tok)?;
+        let instr = match op.text.as_ref() {
+            "+=" => Instruction::AddAddrReg(self.register_operand(&op)?),
+            ":=" if self.peek_is("hex") => {
+                let hex = self.next_token(&op)?;
+                Instruction::SetAddrToChar(self.register_operand(&hex)?)
+            },
+            ":=" if self.peek_is("bighex") => {
+                let hex = self.next_token(&op)?;
+                Instruction::SetAddrToBigChar(self.register_operand(&hex)?)
+            },
+            ":=" if self.peek_is("long") => {
+                let long = self.next_token(&op)?;
+                let target = self.address(&long)?;
+                self.emit(Instruction::LoadLongAddr, &tok, None)?;
+                let idx = self.emit_bytes(&[0, 0]);
+                return self.resolve(idx, FixupKind::Addr16, target);
+            },
+            ":=" => {
+                let target = self.address(&op)?;
+                return self.emit(Instruction::LoadAddrConst(0), &tok, Some(target));
+            },
+            _ => return Err(self.error(&op, format!("Unknown operation i {}.", op.text))),
+        };
+        self.emit(instr, &tok, None)
+    }
+
+    ///
+    /// Octo's ```if [condition] then``` runs the next statement only if the
+    /// condition holds, so it assembles to the skip for the opposite condition.
+    fn if_statement(&mut self, tok : &Token) -> Result<Instruction, AsmError> {
+        let x = self.register_operand(tok)?;
+        let op = self.next_token(tok)?;
+        let instr = match op.text.as_ref() {
+            "key" => Instruction::SkipIfKeyNotPressed(x),
+            "-key" => Instruction::SkipIfKeyPressed(x),
+            "==" | "!=" => {
+                let operand = self.next_token(&op)?;
+                let equal = op.text == "==";
+                if self.is_register(&operand.text) {
+                    let y = self.register(&operand)?;
+                    if equal { Instruction::SkipIfUnequalReg(x, y) } else { Instruction::SkipIfEqualReg(x, y) }
+                }
+                else {
+                    let byte = self.byte(&operand)?;
+                    if equal { Instruction::SkipIfUnequalConst(x, byte) } else { Instruction::SkipIfEqualConst(x, byte) }
+                }
+            },
+            _ => return Err(self.error(&op, format!("Unknown condition {}.", op.text))),
+        };
+        self.expect(&op, "then")?;
+        Ok(instr)
+    }
+
+    fn register_statement(&mut self, tok : &Token) -> Result<Instruction, AsmError> {
+        let x = self.register(tok)?;
+        let op = self.next_token(tok)?;
+        let operand = self.next_token(&op)?;
+        let is_reg = self.is_register(&operand.text);
+        let instr = match op.text.as_ref() {
+            ":=" if is_reg => Instruction::LoadRegister(x, self.register(&operand)?),
+            ":=" if operand.text == "random" => {
+                let mask = self.next_token(&operand)?;
+                Instruction::Randomize(x, self.byte(&mask)?)
+            },
+            ":=" if operand.text == "delay" => Instruction::LoadTimer(x),
+            ":=" if operand.text == "key" => Instruction::WaitForKey(x),
+            ":=" => Instruction::LoadConst(x, self.byte(&operand)?),
+            "+=" if is_reg => Instruction::AddRegister(x, self.register(&operand)?),
+            "+=" => Instruction::AddConst(x, self.byte(&operand)?),
+            "-=" if is_reg => Instruction::SubRegister(x, self.register(&operand)?),
+            "-=" => Instruction::AddConst(x, self.byte(&operand)?.wrapping_neg()),
+            "|=" => Instruction::OrRegister(x, self.register(&operand)?),
+            "&=" => Instruction::AndRegister(x, self.register(&operand)?),
+            "^=" => Instruction::XorRegister(x, self.register(&operand)?),
+            "=-" => Instruction::RevSubRegister(x, self.register(&operand)?),
+            ">>=" => Instruction::RightShiftRegister(x, self.register(&operand)?),
+            "<<=" => Instruction::LeftShiftRegister(x, self.register(&operand)?),
+            _ => return Err(self.error(&op, format!("Unknown operation {} {}.", tok.text, op.text))),
+        };
+        Ok(instr)
+    }
+
+    ///
+    /// Writes an instruction into the ROM, recording the line of ```tok```
+    /// as where it came from. If ```target``` is given it is written into
+    /// the low 12 bits of the opcode.
+    fn emit(&mut self, instr : Instruction, tok : &Token, target : Option<Target>) -> Result<(), AsmError> {
+        if let Some(Target::Known(addr)) = target {
+            if addr > 0xFFF {
+                return Err(self.error(tok, format!("Address {:#X} is past 0xFFF; use i := long.", addr)));
+            }
+        }
+        let op = instr.encode();
+        self.lines.push(SourceLine { addr : self.addr as u16, file : tok.file, line : tok.line });
+        let idx = self.emit_bytes(&[(op >> 8) as u8, op as u8]);
+        match target {
+            Some(target) => self.resolve(idx, FixupKind::Addr12, target),
+            None => Ok(()),
+        }
+    }
+
+    ///
+    /// Writes ```bytes``` at the current address, returning their index in the ROM.
+    fn emit_bytes(&mut self, bytes : &[u8]) -> usize {
+        let idx = self.addr - PROGRAM_START as usize;
+        if self.rom.len() < idx + bytes.len() {
+            self.rom.resize(idx + bytes.len(), 0);
+        }
+        self.rom[idx .. idx + bytes.len()].copy_from_slice(bytes);
+        self.addr += bytes.len();
+        idx
+    }
+
+    ///
+    /// Fills in the address at ```idx``` in the ROM now if it's known,
+    /// or once the whole program has been assembled otherwise.
+    fn resolve(&mut self, idx : usize, kind : FixupKind, target : Target) -> Result<(), AsmError> {
+        match target {
+            Target::Known(addr) => {
+                self.write_addr(idx, kind, addr);
+                Ok(())
+            },
+            Target::Label(label) => {
+                self.fixups.push(Fixup { idx, kind, label });
+                Ok(())
+            },
+        }
+    }
+
+    fn write_addr(&mut self, idx : usize, kind : FixupKind, addr : u16) {
+        match kind {
+            FixupKind::Addr12 => self.rom[idx] |= (addr >> 8) as u8,
+            FixupKind::Addr16 => self.rom[idx] = (addr >> 8) as u8,
+        }
+        self.rom[idx + 1] = addr as u8;
+    }
+
+    ///
+    /// Reads an address operand: a number, a constant, or a label.
+    fn address(&mut self, after : &Token) -> Result<Target, AsmError> {
+        let tok = self.next_token(after)?;
+        if parse_number(&tok.text).is_some() || self.constants.contains_key(&tok.text) {
+            let value = self.number(&tok)?;
+            if !(0 ..= 0xFFFF).contains(&value) {
+                return Err(self.error(&tok, format!("Address {} is out of range.", value)));
+            }
+            return Ok(Target::Known(value as u16));
+        }
+        Ok(Target::Label(tok))
+    }
+
+    fn register_operand(&mut self, after : &Token) -> Result<usize, AsmError> {
+        let tok = self.next_token(after)?;
+        self.register(&tok)
+    }
+
+    fn nibble_operand(&mut self, after : &Token) -> Result<u8, AsmError> {
+        let tok = self.next_token(after)?;
+        let value = self.number(&tok)?;
+        if !(0 ..= 0xF).contains(&value) {
+            return Err(self.error(&tok, format!("Expected a value from 0 to 15 but found {}.", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn is_register(&self, text : &str) -> bool {
+        parse_register(text).is_some() || self.aliases.contains_key(text)
+    }
+
+    fn register(&self, tok : &Token) -> Result<usize, AsmError> {
+        parse_register(&tok.text)
+            .or_else(|| self.aliases.get(&tok.text).cloned())
+            .ok_or_else(|| self.error(tok, format!("Expected a register but found {}.", tok.text)))
+    }
+
+    fn number(&self, tok : &Token) -> Result<i32, AsmError> {
+        parse_number(&tok.text)
+            .or_else(|| self.constants.get(&tok.text).cloned())
+            .ok_or_else(|| self.error(tok, format!("Expected a number but found {}.", tok.text)))
+    }
+
+    fn byte(&self, tok : &Token) -> Result<u8, AsmError> {
+        let value = self.number(tok)?;
+        if !(-128 ..= 255).contains(&value) {
+            return Err(self.error(tok, format!("{} doesn't fit in a byte.", value)));
+        }
+        Ok(value as u8)
+    }
+}
+
+///
+/// Splits source into whitespace-separated tokens, dropping ```#``` comments.
+/// Quoted strings are kept together as one token, quotes included.
+fn tokenize(source : &str, file : usize) -> VecDeque<Token> {
+    let mut retval = VecDeque::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            if c == '#' {
+                break;
+            }
+            let mut text = String::new();
+            if c == '"' {
+                text.push(c);
+                chars.next();
+                for c in chars.by_ref() {
+                    text.push(c);
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            else {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    text.push(c);
+                    chars.next();
+                }
+            }
+            retval.push_back(Token { text, file, line : line_idx + 1 });
+        }
+    }
+    retval
+}
+
+fn parse_register(text : &str) -> Option<usize> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|reg| reg as usize),
+        _ => None,
+    }
+}
+
+///
+/// Parses a decimal, ```0x``` hex, or ```0b``` binary number, as written in Octo.
+pub fn parse_number(text : &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') { Some(digits) => (true, digits), None => (false, text) };
+    let value = if digits.starts_with("0x") || digits.starts_with("0X") {
+        i32::from_str_radix(&digits[2 ..], 16).ok()
+    }
+    else if digits.starts_with("0b") || digits.starts_with("0B") {
+        i32::from_str_radix(&digits[2 ..], 2).ok()
+    }
+    else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse::<i32>().ok()
+    }
+    else {
+        None
+    };
+    value.map(|value| if negative { -value } else { value })
+}
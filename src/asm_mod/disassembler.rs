@@ -0,0 +1,255 @@
+//!
+//! Code related to turning ROMs back into assembly.
+//!
+//! A ROM is just bytes; nothing marks which of them are instructions and
+//! which are sprites or other data. The disassembler finds the code by
+//! following every path the program can take from ```PROGRAM_START```,
+//! and treats every byte it never reaches as data.
+
+use chip8_mod::cpu::PROGRAM_START;
+use chip8_mod::instruction::Instruction;
+use chip8_mod::platform::Platform;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+///
+/// The number of data bytes written on each line of output.
+const BYTES_PER_LINE : usize = 8;
+
+///
+/// A ROM split into code and data, with labels for every address the
+/// code refers to.
+pub struct Disassembly {
+    rom : Vec<u8>,
+    code : Vec<bool>,
+    instructions : BTreeMap<u16, Instruction>,
+    labels : BTreeMap<u16, String>,
+}
+
+impl Disassembly {
+
+    ///
+    /// Disassembles a ROM loaded at ```PROGRAM_START```. Opcodes that
+    /// ```platform``` doesn't have end the path they are found on, the
+    /// same as they would halt the CPU.
+    pub fn new(rom : &[u8], platform : Platform) -> Disassembly {
+        let mut retval = Disassembly {
+            rom : rom.to_vec(),
+            code : vec![false ; rom.len()],
+            instructions : BTreeMap::new(),
+            labels : BTreeMap::new(),
+        };
+        retval.labels.insert(PROGRAM_START, "main".to_owned());
+        retval.analyze(platform);
+        retval
+    }
+
+    ///
+    /// Gets the instruction starting at ```addr```, if the address was found to be code.
+    pub fn instruction_at(&self, addr : u16) -> Option<&Instruction> {
+        self.instructions.get(&addr)
+    }
+
+    ///
+    /// Gets the name of the label at ```addr```, if the code refers to it.
+    pub fn label_at(&self, addr : u16) -> Option<&str> {
+        self.labels.get(&addr).map(|label| label.as_ref())
+    }
+
+    ///
+    /// Writes the ROM out as Octo assembly, which assembles back into the same bytes.
+    pub fn to_octo(&self) -> String {
+        let mut retval = String::new();
+        let mut addr = PROGRAM_START as usize;
+        while addr < self.end() {
+            if let Some(label) = self.printable_label(addr) {
+                writeln!(retval, ": {}", label).unwrap();
+            }
+            if let Some(instr) = self.instructions.get(&(addr as u16)) {
+                let text = self.format_instruction(addr, instr);
+                writeln!(retval, "\t{:<28}# {:#05X}", text, addr).unwrap();
+                addr += instr.size();
+            }
+            else {
+                let data = self.data_run(addr);
+                let bytes : Vec<String> = data.iter().map(|byte| format!("{:#04X}", byte)).collect();
+                writeln!(retval, "\t{:<28}# {:#05X}", bytes.join(" "), addr).unwrap();
+                addr += data.len();
+            }
+        }
+        if let Some(label) = self.printable_label(addr) {
+            writeln!(retval, ": {}", label).unwrap();
+        }
+        retval
+    }
+
+    ///
+    /// Writes the ROM out as a listing of each address, the raw bytes there,
+    /// and the instruction they decode to.
+    pub fn to_hex_listing(&self) -> String {
+        let mut retval = String::new();
+        let mut addr = PROGRAM_START as usize;
+        while addr < self.end() {
+            if let Some(label) = self.printable_label(addr) {
+                writeln!(retval, "{:<4}  {:<24}: {}", "", "", label).unwrap();
+            }
+            let (length, text) = match self.instructions.get(&(addr as u16)) {
+                Some(instr) => (instr.size(), self.format_instruction(addr, instr)),
+                None => (self.data_run(addr).len(), String::new()),
+            };
+            let idx = addr - PROGRAM_START as usize;
+            let bytes : Vec<String> = self.rom[idx .. idx + length].iter().map(|byte| format!("{:02X}", byte)).collect();
+            let line = format!("{:04X}  {:<24}{}", addr, bytes.join(" "), text);
+            writeln!(retval, "{}", line.trim_end()).unwrap();
+            addr += length;
+        }
+        retval
+    }
+
+    ///
+    /// Follows every path through the program, marking what it reaches as code.
+    fn analyze(&mut self, platform : Platform) {
+        let mut pending = vec![PROGRAM_START as usize];
+        while let Some(addr) = pending.pop() {
+            if self.instructions.contains_key(&(addr as u16)) {
+                continue;
+            }
+            let instr = match self.decode_at(addr, platform) {
+                Some(instr) => instr,
+                None => continue,
+            };
+            // Don't decode instructions that overlap ones found on another path
+            let idx = addr - PROGRAM_START as usize;
+            if self.code[idx .. idx + instr.size()].iter().any(|&is_code| is_code) {
+                continue;
+            }
+            for is_code in self.code[idx .. idx + instr.size()].iter_mut() {
+                *is_code = true;
+            }
+            self.instructions.insert(addr as u16, instr);
+
+            let next = addr + instr.size();
+            match instr {
+                Instruction::Jump(target) => {
+                    self.add_label(target as usize, "label");
+                    pending.push(target as usize);
+                },
+                Instruction::AddJumpV0(target) => {
+                    // Only the start of a jump table is known for certain
+                    self.add_label(target as usize, "table");
+                    pending.push(target as usize);
+                },
+                Instruction::Call(target) => {
+                    self.add_label(target as usize, "sub");
+                    pending.push(target as usize);
+                    pending.push(next);
+                },
+                Instruction::LoadAddrConst(target) => {
+                    self.add_label(target as usize, "data");
+                    pending.push(next);
+                },
+                Instruction::LoadLongAddr => {
+                    if let Some(target) = self.opcode_at(addr + 2) {
+                        self.add_label(target as usize, "data");
+                    }
+                    pending.push(next);
+                },
+                Instruction::Return | Instruction::Exit => {},
+                _ if instr.is_skip() => {
+                    let skipped_size = self.decode_at(next, platform).map(|skipped| skipped.size()).unwrap_or(2);
+                    pending.push(next + skipped_size);
+                    pending.push(next);
+                },
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    ///
+    /// The address just past the end of the ROM.
+    fn end(&self) -> usize {
+        PROGRAM_START as usize + self.rom.len()
+    }
+
+    fn opcode_at(&self, addr : usize) -> Option<u16> {
+        if addr < PROGRAM_START as usize || addr + 2 > self.end() {
+            return None;
+        }
+        let idx = addr - PROGRAM_START as usize;
+        Some((self.rom[idx] as u16) << 8 | self.rom[idx + 1] as u16)
+    }
+
+    ///
+    /// Decodes the instruction at ```addr```, if there is a valid one
+    /// that fits in the ROM. Zeroed memory is never treated as code.
+    fn decode_at(&self, addr : usize, platform : Platform) -> Option<Instruction> {
+        let op = self.opcode_at(addr)?;
+        if op == 0 {
+            return None;
+        }
+        let instr = Instruction::decode(op).ok()?;
+        if !platform.supports(&instr) || addr + instr.size() > self.end() {
+            return None;
+        }
+        Some(instr)
+    }
+
+    fn add_label(&mut self, addr : usize, kind : &str) {
+        if addr >= PROGRAM_START as usize && addr <= self.end() {
+            self.labels.entry(addr as u16).or_insert_with(|| format!("{}_{:03X}", kind, addr));
+        }
+    }
+
+    ///
+    /// Gets the label at ```addr``` if it can be written out; labels pointing
+    /// into the middle of an instruction can't, and are referred to by address instead.
+    fn printable_label(&self, addr : usize) -> Option<&str> {
+        let label = self.labels.get(&(addr as u16))?;
+        let idx = addr - PROGRAM_START as usize;
+        if addr == self.end() || self.instructions.contains_key(&(addr as u16)) || !self.code[idx] {
+            Some(label)
+        }
+        else {
+            None
+        }
+    }
+
+    ///
+    /// Gets the data bytes starting at ```addr``` that belong on one line,
+    /// stopping before any code or label.
+    fn data_run(&self, addr : usize) -> &[u8] {
+        let start = addr - PROGRAM_START as usize;
+        let mut stop = start + 1;
+        while stop < self.rom.len() && stop - start < BYTES_PER_LINE && !self.code[stop]
+            && !self.labels.contains_key(&((PROGRAM_START as usize + stop) as u16)) {
+            stop += 1;
+        }
+        &self.rom[start .. stop]
+    }
+
+    ///
+    /// Formats an instruction, using labels in place of addresses where possible.
+    fn format_instruction(&self, addr : usize, instr : &Instruction) -> String {
+        let operand = |target : usize| -> String {
+            match self.printable_label(target) {
+                Some(label) => label.to_owned(),
+                None => format!("{:#05X}", target),
+            }
+        };
+        match *instr {
+            Instruction::Jump(addr) => format!("jump {}", operand(addr as usize)),
+            Instruction::AddJumpV0(addr) => format!("jump0 {}", operand(addr as usize)),
+            Instruction::LoadAddrConst(addr) => format!("i := {}", operand(addr as usize)),
+            Instruction::Call(addr) => match self.printable_label(addr as usize) {
+                Some(label) => label.to_owned(),
+                None => instr.to_string(),
+            },
+            Instruction::LoadLongAddr => {
+                let long_addr = self.opcode_at(addr + 2).unwrap_or(0);
+                format!("i := long {}", operand(long_addr as usize))
+            },
+            _ => instr.to_string(),
+        }
+    }
+}
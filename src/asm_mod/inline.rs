@@ -0,0 +1,278 @@
+//!
+//! Code related to writing Chip 8 programs inline in Rust code.
+//!
+//! The ```chip8_asm!``` macro turns a list of statements in the "CowGod"
+//! mnemonics into the bytes of a ROM, resolving labels along the way:
+//!
+//! ```ignore
+//! let rom : Vec<u8> = chip8_asm! {
+//!         ld v0, 0x0E;
+//!         ld f, v0;
+//!     loop:
+//!         jp loop
+//! };
+//! ```
+//!
+//! Statements are separated by ```;```, operands by ```,```, and a label is
+//! a name followed by ```:```. Each operand has to be a single token, so
+//! negative numbers and constant expressions go in parentheses, like ```(-1)```
+//! or ```(SPEED * 2)```. Along with the CowGod mnemonics, the macro understands:
+//!
+//! * The SUPER-CHIP ```scd n```, ```scr```, ```scl```, ```exit```, ```low```, ```high```,
+//!   ```ld hf, vx```, ```ld r, vx```, and ```ld vx, r```.
+//! * The XO-CHIP ```save vx, vy```, ```load vx, vy```, ```plane n```, ```audio```,
+//!   ```pitch vx```, and ```ld i, long, addr```.
+//! * ```db``` followed by any number of bytes, which are written as they are.
+//!
+//! Labels can't be named after the special operands ```i```, ```dt```, ```st```,
+//! ```k```, ```f```, ```hf```, ```b```, ```r```, or ```long```.
+//!
+//! Mistakes in the program are only found when the macro runs, and panic.
+//! Each statement nests one macro call deeper, so very long programs may
+//! need a higher ```#![recursion_limit]```.
+
+use chip8_mod::cpu::PROGRAM_START;
+use chip8_mod::instruction::Instruction;
+
+use std::collections::HashMap;
+
+///
+/// A single operand of an inline statement.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand {
+
+    ///
+    /// A register, a special operand like ```dt```, or a label.
+    Name(&'static str),
+
+    ///
+    /// A number.
+    Value(i64),
+}
+
+///
+/// Builds up a ROM one statement at a time; the backend of ```chip8_asm!```.
+pub struct InlineAssembler {
+    rom : Vec<u8>,
+    labels : HashMap<&'static str, u16>,
+    fixups : Vec<(usize, &'static str, bool)>,
+}
+
+impl InlineAssembler {
+
+    pub fn new() -> InlineAssembler {
+        InlineAssembler {
+            rom : Vec::new(),
+            labels : HashMap::new(),
+            fixups : Vec::new(),
+        }
+    }
+
+    ///
+    /// Defines the label ```name``` at the current address.
+    pub fn label(&mut self, name : &'static str) {
+        let addr = PROGRAM_START + self.rom.len() as u16;
+        if self.labels.insert(name, addr).is_some() {
+            panic!("Label {} is defined twice.", name);
+        }
+    }
+
+    ///
+    /// Assembles a single statement.
+    pub fn op(&mut self, mnemonic : &str, operands : &[Operand]) {
+        use self::Instruction::*;
+        use self::Operand::*;
+
+        if mnemonic == "db" {
+            for operand in operands.iter() {
+                let byte = to_byte(operand);
+                self.rom.push(byte);
+            }
+            return;
+        }
+        let regs : Vec<Option<usize>> = operands.iter().map(to_register).collect();
+        let reg = |idx : usize| regs[idx].unwrap_or_else(|| panic!("Expected a register in {} {:?}.", mnemonic, operands));
+        let (instr, target) = match (mnemonic, operands) {
+            ("cls", &[]) => (ClearScreen, None),
+            ("ret", &[]) => (Return, None),
+            ("sys", &[target]) => (MachineCall(0), Some(target)),
+            ("jp", &[Name("v0"), target]) => (AddJumpV0(0), Some(target)),
+            ("jp", &[target]) => (Jump(0), Some(target)),
+            ("call", &[target]) => (Call(0), Some(target)),
+            ("se", &[_, Value(byte)]) => (SkipIfEqualConst(reg(0), to_byte(&Value(byte))), None),
+            ("se", &[_, _]) => (SkipIfEqualReg(reg(0), reg(1)), None),
+            ("sne", &[_, Value(byte)]) => (SkipIfUnequalConst(reg(0), to_byte(&Value(byte))), None),
+            ("sne", &[_, _]) => (SkipIfUnequalReg(reg(0), reg(1)), None),
+            ("ld", &[Name("i"), Name("long"), target]) => (LoadLongAddr, Some(target)),
+            ("ld", &[Name("i"), target]) => (LoadAddrConst(0), Some(target)),
+            ("ld", &[_, Name("dt")]) => (LoadTimer(reg(0)), None),
+            ("ld", &[_, Name("k")]) => (WaitForKey(reg(0)), None),
+            ("ld", &[_, Name("[i]")]) => (RestoreRegisters(reg(0)), None),
+            ("ld", &[_, Name("r")]) => (RestoreFlags(reg(0)), None),
+            ("ld", &[Name("dt"), _]) => (SetTimer(reg(1)), None),
+            ("ld", &[Name("st"), _]) => (SetAudio(reg(1)), None),
+            ("ld", &[Name("f"), _]) => (SetAddrToChar(reg(1)), None),
+            ("ld", &[Name("hf"), _]) => (SetAddrToBigChar(reg(1)), None),
+            ("ld", &[Name("b"), _]) => (StoreDigits(reg(1)), None),
+            ("ld", &[Name("[i]"), _]) => (SaveRegisters(reg(1)), None),
+            ("ld", &[Name("r"), _]) => (SaveFlags(reg(1)), None),
+            ("ld", &[_, Value(byte)]) => (LoadConst(reg(0), to_byte(&Value(byte))), None),
+            ("ld", &[_, _]) => (LoadRegister(reg(0), reg(1)), None),
+            ("add", &[Name("i"), _]) => (AddAddrReg(reg(1)), None),
+            ("add", &[_, Value(byte)]) => (AddConst(reg(0), to_byte(&Value(byte))), None),
+            ("add", &[_, _]) => (AddRegister(reg(0), reg(1)), None),
+            ("or", &[_, _]) => (OrRegister(reg(0), reg(1)), None),
+            ("and", &[_, _]) => (AndRegister(reg(0), reg(1)), None),
+            ("xor", &[_, _]) => (XorRegister(reg(0), reg(1)), None),
+            ("sub", &[_, _]) => (SubRegister(reg(0), reg(1)), None),
+            ("subn", &[_, _]) => (RevSubRegister(reg(0), reg(1)), None),
+            ("shr", &[_]) => (RightShiftRegister(reg(0), reg(0)), None),
+            ("shr", &[_, _]) => (RightShiftRegister(reg(0), reg(1)), None),
+            ("shl", &[_]) => (LeftShiftRegister(reg(0), reg(0)), None),
+            ("shl", &[_, _]) => (LeftShiftRegister(reg(0), reg(1)), None),
+            ("rnd", &[_, Value(mask)]) => (Randomize(reg(0), to_byte(&Value(mask))), None),
+            ("drw", &[_, _, Value(0)]) => (DrawLargeSprite(reg(0), reg(1)), None),
+            ("drw", &[_, _, Value(rows)]) => (DrawSprite(reg(0), reg(1), to_nibble(rows)), None),
+            ("skp", &[_]) => (SkipIfKeyPressed(reg(0)), None),
+            ("sknp", &[_]) => (SkipIfKeyNotPressed(reg(0)), None),
+            ("scd", &[Value(rows)]) => (ScrollDown(to_nibble(rows)), None),
+            ("scr", &[]) => (ScrollRight, None),
+            ("scl", &[]) => (ScrollLeft, None),
+            ("exit", &[]) => (Exit, None),
+            ("low", &[]) => (LowRes, None),
+            ("high", &[]) => (HighRes, None),
+            ("save", &[_, _]) => (SaveRegisterRange(reg(0), reg(1)), None),
+            ("load", &[_, _]) => (RestoreRegisterRange(reg(0), reg(1)), None),
+            ("plane", &[Value(mask)]) => (SelectPlanes(to_nibble(mask)), None),
+            ("audio", &[]) => (LoadAudioPattern, None),
+            ("pitch", &[_]) => (SetPitch(reg(0)), None),
+            _ => panic!("Unknown statement {} {:?}.", mnemonic, operands),
+        };
+
+        let op = instr.encode();
+        self.rom.push((op >> 8) as u8);
+        self.rom.push(op as u8);
+        // The long address goes in the 2 bytes after the opcode
+        let long = instr == LoadLongAddr;
+        if long {
+            self.rom.push(0);
+            self.rom.push(0);
+        }
+        match target {
+            Some(Value(addr)) => {
+                let idx = self.rom.len() - 2;
+                self.write_addr(idx, addr as u16, long);
+            },
+            Some(Name(label)) => {
+                let idx = self.rom.len() - 2;
+                self.fixups.push((idx, label, long));
+            },
+            None => {},
+        }
+    }
+
+    ///
+    /// Resolves every label and returns the finished ROM.
+    pub fn finish(mut self) -> Vec<u8> {
+        let fixups = self.fixups.clone();
+        for (idx, label, long) in fixups {
+            let addr = *self.labels.get(label).unwrap_or_else(|| panic!("Undefined label {}.", label));
+            self.write_addr(idx, addr, long);
+        }
+        self.rom
+    }
+
+    fn write_addr(&mut self, idx : usize, addr : u16, long : bool) {
+        if long {
+            self.rom[idx] = (addr >> 8) as u8;
+        }
+        else if addr > 0xFFF {
+            panic!("Address {:#X} is past 0xFFF; use ld i, long, addr.", addr);
+        }
+        else {
+            self.rom[idx] |= (addr >> 8) as u8;
+        }
+        self.rom[idx + 1] = addr as u8;
+    }
+}
+
+impl Default for InlineAssembler {
+    fn default() -> InlineAssembler {
+        InlineAssembler::new()
+    }
+}
+
+fn to_register(operand : &Operand) -> Option<usize> {
+    match *operand {
+        Operand::Name(name) if name.len() == 2 && (name.starts_with('v') || name.starts_with('V')) => {
+            name[1 ..].chars().next().and_then(|digit| digit.to_digit(16)).map(|reg| reg as usize)
+        },
+        _ => None,
+    }
+}
+
+fn to_byte(operand : &Operand) -> u8 {
+    match *operand {
+        Operand::Value(value) if (-128 ..= 255).contains(&value) => value as u8,
+        _ => panic!("Expected a byte but found {:?}.", operand),
+    }
+}
+
+fn to_nibble(value : i64) -> u8 {
+    if !(0 ..= 0xF).contains(&value) {
+        panic!("Expected a value from 0 to 15 but found {}.", value);
+    }
+    value as u8
+}
+
+///
+/// Assembles a Chip 8 program written in the "CowGod" mnemonics into a
+/// ```Vec<u8>```; see ```asm_mod::inline``` for the syntax.
+#[macro_export]
+macro_rules! chip8_asm {
+    (@stmts $asm:ident; ) => {};
+    (@stmts $asm:ident; $label:ident : $($rest:tt)*) => {
+        $asm.label(stringify!($label));
+        chip8_asm!(@stmts $asm; $($rest)*);
+    };
+    (@stmts $asm:ident; db $($byte:tt),* ; $($rest:tt)*) => {
+        chip8_asm!(@stmts $asm; db $($byte),*);
+        chip8_asm!(@stmts $asm; $($rest)*);
+    };
+    (@stmts $asm:ident; db $($byte:tt),*) => {
+        $asm.op("db", &[$(chip8_asm!(@operand $byte)),*]);
+    };
+    (@stmts $asm:ident; $mnem:ident ; $($rest:tt)*) => {
+        chip8_asm!(@stmts $asm; $mnem);
+        chip8_asm!(@stmts $asm; $($rest)*);
+    };
+    (@stmts $asm:ident; $mnem:ident $a:tt ; $($rest:tt)*) => {
+        chip8_asm!(@stmts $asm; $mnem $a);
+        chip8_asm!(@stmts $asm; $($rest)*);
+    };
+    (@stmts $asm:ident; $mnem:ident $a:tt , $b:tt ; $($rest:tt)*) => {
+        chip8_asm!(@stmts $asm; $mnem $a, $b);
+        chip8_asm!(@stmts $asm; $($rest)*);
+    };
+    (@stmts $asm:ident; $mnem:ident $a:tt , $b:tt , $c:tt ; $($rest:tt)*) => {
+        chip8_asm!(@stmts $asm; $mnem $a, $b, $c);
+        chip8_asm!(@stmts $asm; $($rest)*);
+    };
+    (@stmts $asm:ident; $mnem:ident $($operand:tt),*) => {
+        $asm.op(stringify!($mnem), &[$(chip8_asm!(@operand $operand)),*]);
+    };
+    (@operand [$inner:ident]) => {
+        $crate::asm_mod::inline::Operand::Name(concat!("[", stringify!($inner), "]"))
+    };
+    (@operand $name:ident) => {
+        $crate::asm_mod::inline::Operand::Name(stringify!($name))
+    };
+    (@operand $value:tt) => {
+        $crate::asm_mod::inline::Operand::Value($value as i64)
+    };
+    ($($body:tt)*) => {{
+        let mut asm = $crate::asm_mod::inline::InlineAssembler::new();
+        chip8_asm!(@stmts asm; $($body)*);
+        asm.finish()
+    }};
+}
@@ -0,0 +1,134 @@
+pub mod assembler;
+pub mod disassembler;
+#[macro_use]
+pub mod inline;
+
+#[cfg(test)]
+mod tests {
+    use super::assembler;
+    use super::disassembler::Disassembly;
+    use chip8_mod::instruction::Instruction;
+    use chip8_mod::platform::Platform;
+
+    const TEST_ROM : [u8 ; 14] = [
+        0x60, 0x0A, // v0 := 0x0A
+        0xA2, 0x0C, // i := sprite
+        0x22, 0x0A, // call the subroutine
+        0x12, 0x06, // loop forever
+        0xFF, 0xFF, // never reached
+        0x00, 0xEE, // the subroutine
+        0x3C, 0x42, // the sprite
+    ];
+
+    #[test]
+    fn test_disassembler() {
+        let disassembly = Disassembly::new(&TEST_ROM, Platform::XoChip);
+        assert_eq!(disassembly.instruction_at(0x200), Some(&Instruction::LoadConst(0, 0x0A)));
+        assert_eq!(disassembly.instruction_at(0x208), None);
+        assert_eq!(disassembly.instruction_at(0x20A), Some(&Instruction::Return));
+        assert_eq!(disassembly.instruction_at(0x20C), None);
+        assert_eq!(disassembly.label_at(0x200), Some("main"));
+        assert_eq!(disassembly.label_at(0x206), Some("label_206"));
+        assert_eq!(disassembly.label_at(0x20A), Some("sub_20A"));
+        assert_eq!(disassembly.label_at(0x20C), Some("data_20C"));
+
+        let octo : Vec<String> = disassembly.to_octo().lines()
+            .map(|line| line.split('#').next().unwrap().trim().to_owned())
+            .collect();
+        assert_eq!(octo, vec![
+            ": main", "v0 := 0x0A", "i := data_20C", "sub_20A",
+            ": label_206", "jump label_206", "0xFF 0xFF",
+            ": sub_20A", "return",
+            ": data_20C", "0x3C 0x42",
+        ]);
+
+        let listing = disassembly.to_hex_listing();
+        assert!(listing.contains("0204  22 0A"));
+        assert!(listing.contains("020C  3C 42\n"));
+    }
+
+    #[test]
+    fn test_assembler() {
+        let source = "
+            :const SPEED 3
+            :alias counter v4
+            :macro bump reg amount { reg += amount }
+
+            : sprite
+                0x3C 0x42
+            : main
+                i := sprite
+                counter := 0
+            : loop
+                bump counter SPEED
+                if counter != 30 then
+                jump loop
+                wait
+                exit
+            : wait  # subroutines can come after they are used
+                v0 := key
+                return
+        ";
+        let assembly = assembler::assemble_source(source).unwrap();
+        assert_eq!(assembly.rom, vec![
+            0x12, 0x04, // jump main
+            0x3C, 0x42,
+            0xA2, 0x02,
+            0x64, 0x00,
+            0x74, 0x03,
+            0x34, 0x1E,
+            0x12, 0x08,
+            0x22, 0x12,
+            0x00, 0xFD,
+            0xF0, 0x0A,
+            0x00, 0xEE,
+        ]);
+        assert_eq!(assembly.labels["main"], 0x204);
+        assert_eq!(assembly.labels["wait"], 0x212);
+        assert!(assembly.symbol_file().contains("label loop 0x208\n"));
+        assert!(assembly.symbol_file().contains("line 0x208 0 12\n"));
+
+        let error = assembler::assemble_source(": main\n jump nowhere").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(assembler::assemble_source(": main\n v0 := 256").is_err());
+    }
+
+    #[test]
+    fn test_disassembler_round_trip() {
+        let disassembly = Disassembly::new(&TEST_ROM, Platform::XoChip);
+        let assembly = assembler::assemble_source(&disassembly.to_octo()).unwrap();
+        assert_eq!(assembly.rom, TEST_ROM.to_vec());
+    }
+
+    #[test]
+    fn test_inline_assembler() {
+        let rom = chip8_asm! {
+                ld v0, 0x0E;
+                ld f, v0;
+            loop:
+                drw v0, v1, 5;
+                ld [i], v3;
+                add i, v3;
+                sne v2, v3;
+                jp v0, table;
+                add v4, (-1);
+                jp loop;
+            table:
+                db 0x3C, 0x42, 0x42, 0x3C;
+                ld i, long, table
+        };
+        assert_eq!(rom, vec![
+            0x60, 0x0E,
+            0xF0, 0x29,
+            0xD0, 0x15,
+            0xF3, 0x55,
+            0xF3, 0x1E,
+            0x92, 0x30,
+            0xB2, 0x12,
+            0x74, 0xFF,
+            0x12, 0x04,
+            0x3C, 0x42, 0x42, 0x3C,
+            0xF0, 0x00, 0x02, 0x12,
+        ]);
+    }
+}
@@ -0,0 +1,57 @@
+extern crate chip8_emu;
+
+use chip8_emu::asm_mod::assembler;
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+///
+/// Assembles Octo source into a ROM and a symbol file.
+///
+/// Usage: ```chip8-asm [-o output.ch8] [--symbols output.sym] source.8o```
+///
+/// By default both files are written next to the source, with the
+/// ```.ch8``` and ```.sym``` extensions.
+fn main() {
+    let args : Vec<String> = env::args().collect();
+    let mut srcpath : &str = "";
+    let mut outpath : Option<PathBuf> = None;
+    let mut sympath : Option<PathBuf> = None;
+
+    let mut arg_idx = 1;
+    while arg_idx < args.len() {
+        match args[arg_idx].as_ref() {
+            "-o" => {
+                arg_idx += 1;
+                outpath = Some(PathBuf::from(&args[arg_idx]));
+            },
+            "--symbols" => {
+                arg_idx += 1;
+                sympath = Some(PathBuf::from(&args[arg_idx]));
+            },
+            _ => {
+                srcpath = &args[arg_idx];
+            },
+        };
+        arg_idx += 1;
+    }
+    if srcpath.is_empty() {
+        eprintln!("Usage: chip8-asm [-o output.ch8] [--symbols output.sym] source.8o");
+        process::exit(1);
+    }
+
+    let assembly = match assembler::assemble_file(Path::new(srcpath)) {
+        Ok(assembly) => assembly,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    };
+    let outpath = outpath.unwrap_or_else(|| Path::new(srcpath).with_extension("ch8"));
+    let sympath = sympath.unwrap_or_else(|| Path::new(srcpath).with_extension("sym"));
+    File::create(outpath).unwrap().write_all(&assembly.rom).unwrap();
+    File::create(sympath).unwrap().write_all(assembly.symbol_file().as_bytes()).unwrap();
+}
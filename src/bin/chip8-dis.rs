@@ -0,0 +1,57 @@
+extern crate chip8_emu;
+
+use chip8_emu::asm_mod::disassembler::Disassembly;
+use chip8_emu::chip8_mod::platform::{self, Platform};
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process;
+
+///
+/// Disassembles a ROM into Octo assembly.
+///
+/// Usage: ```chip8-dis [--hex] [--platform name] [-o output] rom```
+fn main() {
+    let args : Vec<String> = env::args().collect();
+    let mut rompath : &str = "";
+    let mut outpath : Option<&str> = None;
+    let mut platform = Platform::default();
+    let mut hex_listing = false;
+
+    let mut arg_idx = 1;
+    while arg_idx < args.len() {
+        match args[arg_idx].as_ref() {
+            "--hex" => {
+                hex_listing = true;
+            },
+            "--platform" => {
+                arg_idx += 1;
+                platform = Platform::from_name(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Unknown platform {}; expected one of {:?}.", args[arg_idx], platform::PLATFORM_NAMES));
+            },
+            "-o" => {
+                arg_idx += 1;
+                outpath = Some(&args[arg_idx]);
+            },
+            _ => {
+                rompath = &args[arg_idx];
+            },
+        };
+        arg_idx += 1;
+    }
+    if rompath.is_empty() {
+        eprintln!("Usage: chip8-dis [--hex] [--platform name] [-o output] rom");
+        process::exit(1);
+    }
+
+    let mut rom = Vec::new();
+    File::open(rompath).unwrap().read_to_end(&mut rom).unwrap();
+    let disassembly = Disassembly::new(&rom, platform);
+    let output = if hex_listing { disassembly.to_hex_listing() } else { disassembly.to_octo() };
+
+    match outpath {
+        Some(path) => File::create(path).unwrap().write_all(output.as_bytes()).unwrap(),
+        None => print!("{}", output),
+    }
+}
@@ -0,0 +1,156 @@
+extern crate chip8_emu;
+
+use chip8_emu::asm_mod::assembler;
+use chip8_emu::chip8_mod::InterpretedCpu;
+use chip8_emu::chip8_mod::cpu::OpcodeExecuter;
+use chip8_emu::chip8_mod::quirks::{self, Quirks};
+use chip8_emu::chip8_mod::platform::{self, Platform};
+use chip8_emu::chip8_mod::audio::AudioTimer;
+use chip8_emu::chip8_mod::display::ScreenBuffer;
+use chip8_emu::chip8_mod::random;
+use chip8_emu::headless_mod::{self, HeadlessRunner, RunLimits};
+use chip8_emu::debug_mod::trace::{self, TraceWriter, TraceOptions, TraceFormat};
+use chip8_emu::utils_mod::logger;
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process;
+
+///
+/// Runs a ROM without a window, then prints the final screen and registers.
+///
+/// Usage: ```chip8-headless [--platform name] [--quirks preset] [--instructions N]
+/// [--frames N] [--until-pc addr] [--keys 1,a] [--seed N] [--pbm output.pbm]
+/// [--log settings] [--trace file] [--trace-format json|binary] [--trace-range start-end] [--trace-limit MiB] rom```
+///
+/// Without a limit, the ROM runs until it exits, dies, or jumps to itself.
+/// Random numbers are seeded with 0 unless ```--seed``` is given, so every run is the same.
+fn main() {
+    let args : Vec<String> = env::args().collect();
+    let mut rompath : &str = "";
+    let mut platform = Platform::default();
+    let mut quirks : Option<Quirks> = None;
+    let mut limits = RunLimits::default();
+    let mut held_keys : Vec<u8> = Vec::new();
+    let mut pbmpath : Option<&str> = None;
+    let mut trace_path : Option<&str> = None;
+    let mut trace_options = TraceOptions::default();
+    let mut log_spec : Option<&str> = None;
+    let mut seed = "0";
+
+    let mut arg_idx = 1;
+    while arg_idx < args.len() {
+        match args[arg_idx].as_ref() {
+            "--platform" => {
+                arg_idx += 1;
+                platform = Platform::from_name(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Unknown platform {}; expected one of {:?}.", args[arg_idx], platform::PLATFORM_NAMES));
+            },
+            "--quirks" => {
+                arg_idx += 1;
+                quirks = Some(Quirks::from_name(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Unknown quirks preset {}; expected one of {:?}.", args[arg_idx], quirks::PRESET_NAMES)));
+            },
+            "--instructions" => {
+                arg_idx += 1;
+                limits.max_instructions = Some(args[arg_idx].parse::<u64>().unwrap());
+            },
+            "--frames" => {
+                arg_idx += 1;
+                limits.max_frames = Some(args[arg_idx].parse::<u64>().unwrap());
+            },
+            "--until-pc" => {
+                arg_idx += 1;
+                limits.until_pc = Some(u16::from_str_radix(args[arg_idx].trim_start_matches("0x"), 16).unwrap());
+            },
+            "--keys" => {
+                arg_idx += 1;
+                held_keys = args[arg_idx].split(',').map(|key| u8::from_str_radix(key.trim(), 16).unwrap()).collect();
+            },
+            "--pbm" => {
+                arg_idx += 1;
+                pbmpath = Some(&args[arg_idx]);
+            },
+            "--seed" => {
+                arg_idx += 1;
+                seed = &args[arg_idx];
+            },
+            "--log" => {
+                arg_idx += 1;
+                log_spec = Some(&args[arg_idx]);
+            },
+            "--trace" => {
+                arg_idx += 1;
+                trace_path = Some(&args[arg_idx]);
+            },
+            "--trace-format" => {
+                arg_idx += 1;
+                trace_options.format = TraceFormat::from_name(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Unknown trace format {}; expected json or binary.", args[arg_idx]));
+            },
+            "--trace-range" => {
+                arg_idx += 1;
+                trace_options.range = Some(trace::parse_range(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Bad trace range {}; expected start-end.", args[arg_idx])));
+            },
+            "--trace-limit" => {
+                arg_idx += 1;
+                trace_options.max_bytes = Some(args[arg_idx].parse::<u64>().unwrap() * 1024 * 1024);
+            },
+            _ => {
+                rompath = &args[arg_idx];
+            },
+        };
+        arg_idx += 1;
+    }
+    if rompath.is_empty() {
+        eprintln!("Usage: chip8-headless [--platform name] [--quirks preset] [--instructions N] [--frames N] [--until-pc addr] [--keys 1,a] [--seed N] [--pbm output.pbm] [--log settings] [--trace file] [--trace-format json|binary] [--trace-range start-end] [--trace-limit MiB] rom");
+        process::exit(1);
+    }
+    logger::init(log_spec).unwrap_or_else(|err| panic!("{}", err));
+    limits.clock_hz = platform.default_clock_hz();
+
+    let mut rom = Vec::new();
+    if rompath.ends_with(".8o") {
+        rom = assembler::assemble_file(Path::new(rompath))
+            .unwrap_or_else(|err| panic!("{}", err))
+            .rom;
+    }
+    else {
+        File::open(rompath).unwrap().read_to_end(&mut rom).unwrap();
+    }
+
+    let mut runner = HeadlessRunner::new();
+    for key in held_keys {
+        runner.keys.keys[key as usize & 0xF] = true;
+    }
+    let (reason, count, registers) = {
+        let mut cpu = InterpretedCpu::new(
+            platform,
+            ScreenBuffer::new(&mut runner.video),
+            AudioTimer::new(&mut runner.audio),
+            &mut runner.keys
+        );
+        if let Some(quirks) = quirks {
+            cpu.set_quirks(quirks);
+        }
+        cpu.random = random::from_spec(seed).unwrap_or_else(|err| panic!("{}", err));
+        cpu.load_rom(&rom).unwrap_or_else(|err| panic!("{}", err));
+        let mut tracer = trace_path.map(|path| TraceWriter::create(path, trace_options)
+            .unwrap_or_else(|err| panic!("Could not create the trace file {}: {}", path, err)));
+        let (reason, count) = headless_mod::run_traced(&mut cpu, &limits, tracer.as_mut());
+        if let Some(mut tracer) = tracer {
+            tracer.flush().unwrap_or_else(|err| panic!("Could not write the trace: {}", err));
+        }
+        (reason, count, cpu.to_string())
+    };
+
+    match pbmpath {
+        Some(path) => File::create(path).unwrap().write_all(runner.video.to_pbm().as_bytes()).unwrap(),
+        None => print!("{}", runner.video.to_text()),
+    }
+    println!("Stopped after {} instructions: {}.", count, reason);
+    println!("{}", registers);
+}
@@ -1,67 +1,154 @@
+//!
+//! Code related to processing audio.
+//! 
+//! The Chip 8 handles audio using a special external timer. This timer 
+//! can be set using an opcode of the form Fx18, where x is the register
+//! containing the value to set the timer to. The timer will then 
+//! tick down to 0 by 1 every 1/60th of a second, and plays a constant
+//! note until it hits 0. The timer itself is kept in ```Timers```, alongside
+//! the delay timer.
+//! 
+//! XO-CHIP generalises the note: ```F002``` loads a 16-byte pattern of 128
+//! 1-bit samples from memory, and ```FX3A``` sets the rate at which those
+//! samples are played back.
+
+use chip8_mod::timers::TimerEvent;
+
 ///
-/// Code related to processing audio.
-/// 
-/// The Chip 8 handles audio using a special external timer. This timer 
-/// can be set using an opcode of the form Fx18, where x is the register
-/// containing the value to set the timer to. The timer will then 
-/// tick down to 0 by 1 every 1/60th of a second, and plays a constant
-/// note until it hits 0. 
+/// The number of bytes in an audio pattern.
+pub const PATTERN_SIZE : usize = 16;
+
+///
+/// The pattern played before a program loads its own: a square wave which,
+/// at the default pitch, sounds at 500 Hz. This is the tone Octo plays, and
+/// replaces the 440 Hz tone the emulator played before it had patterns, so
+/// plain Chip 8 programs sound the same here as in Octo.
+pub const DEFAULT_PATTERN : [u8 ; PATTERN_SIZE] = [0xF0 ; PATTERN_SIZE];
+
+///
+/// The pitch register value a program starts with, playing samples at 4000 Hz.
+pub const DEFAULT_PITCH : u8 = 64;
+
+///
+/// Converts a value of the pitch register into the number of pattern samples
+/// played per second, following the XO-CHIP formula ```4000 * 2 ^ ((pitch - 64) / 48)```.
+pub fn pitch_to_rate(pitch : u8) -> f32 {
+    4000.0 * 2.0f32.powf((pitch as f32 - 64.0) / 48.0)
+}
 
 ///
 /// The interface for a frontend audio output device.
 pub trait AudioOutput {
 
     ///
-    /// Called whenever the emulator needs to play the constant tone.
-    /// Note that this is called once per tick if the timer is non-zero;
-    /// IE if the timer is set to 60, then this method will be called 60 times
-    /// before the timer hits 0.
+    /// Called when the sound timer starts making a sound. The tone should keep
+    /// playing until ```stop_audio``` is called.
     fn output_audio (&mut self) ;
 
     ///
-    /// Called the frame that the timer hits 0 for the sake of cleanup.
+    /// Called when the sound timer stops making a sound, such as when it hits 0.
     fn stop_audio (&mut self) ;
+
+    ///
+    /// Called whenever the program loads a new sample pattern. Each bit of
+    /// ```pattern```, highest bit of the first byte first, is one sample.
+    fn set_pattern (&mut self, pattern : &[u8 ; PATTERN_SIZE]) ;
+
+    ///
+    /// Called whenever the program sets the pitch register;
+    /// see ```pitch_to_rate``` for how it maps to a playback rate.
+    fn set_pitch (&mut self, pitch : u8) ;
+}
+
+///
+/// Lets an ```AudioTimer``` borrow its audio output instead of owning it.
+impl <A : AudioOutput + ?Sized> AudioOutput for &mut A {
+    fn output_audio (&mut self) {
+        (**self).output_audio()
+    }
+
+    fn stop_audio (&mut self) {
+        (**self).stop_audio()
+    }
+
+    fn set_pattern (&mut self, pattern : &[u8 ; PATTERN_SIZE]) {
+        (**self).set_pattern(pattern)
+    }
+
+    fn set_pitch (&mut self, pitch : u8) {
+        (**self).set_pitch(pitch)
+    }
 }
 
 
 ///
-/// The audio timer.
+/// The sound output of the CPU, which plays the tone while the sound timer in
+/// ```Timers``` is running and keeps the program's pattern and pitch.
 /// 
-/// The lifetime parameter ```'a``` allows us to to store a reference
-/// to the ```AudioOutput``` device instead of having to take ownership,
-/// meaning that creation and cleanup can be handled externally.
-pub struct AudioTimer<'a> {
-    time : u8,
-    audio_output : &'a mut (AudioOutput + 'a)
+/// The ```AudioOutput``` device ```A``` is owned by the timer; passing
+/// ```&mut``` a device instead lets creation and cleanup be handled externally.
+#[derive(Clone)]
+pub struct AudioTimer<A> {
+    pub pattern : [u8 ; PATTERN_SIZE],
+    pub pitch : u8,
+    playing : bool,
+    pub audio_output : A
 }
 
-impl <'a> AudioTimer <'a> {
+impl <A : AudioOutput> AudioTimer <A> {
 
     ///
     /// Constructs a new ```AudioTimer``` from an output.
-    pub fn new(output : &'a mut AudioOutput) -> AudioTimer<'a> {
+    pub fn new(output : A) -> AudioTimer<A> {
         AudioTimer {
-            time : 0,
+            pattern : DEFAULT_PATTERN,
+            pitch : DEFAULT_PITCH,
+            playing : false,
             audio_output : output
         }
     }
 
     ///
-    /// Sets the timer to ```ntime```.
-    pub fn set_timer(&mut self, ntime : u8) {
-        self.time = ntime;
+    /// Whether the tone has been started since it was last stopped.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    ///
+    /// Starts the tone, which keeps playing until ```stop``` is called.
+    pub fn play(&mut self) {
+        self.playing = true;
+        self.audio_output.output_audio();
+    }
+
+    ///
+    /// Stops the tone immediately.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.audio_output.stop_audio();
     }
 
     ///
-    /// If the timer is non-zero, plays a tone and decrements the timer.
-    /// If the timer hits 0, stops the tone.
-    pub fn tick(&mut self) {
-        if self.time != 0 {
-            self.audio_output.output_audio();
-            self.time -= 1;
-            if self.time == 0 {
-                self.audio_output.stop_audio();
-            }
+    /// Starts or stops the tone as the timers tell it to. Frontends pass in the
+    /// events from ```Timers::take_events``` as they run the CPU.
+    pub fn handle_event(&mut self, event : TimerEvent) {
+        match event {
+            TimerEvent::SoundStarted { .. } => self.play(),
+            TimerEvent::SoundStopped { .. } => self.stop(),
         }
     }
-}
\ No newline at end of file
+
+    ///
+    /// Sets the pattern played while the sound timer is running.
+    pub fn set_pattern(&mut self, pattern : &[u8 ; PATTERN_SIZE]) {
+        self.pattern = *pattern;
+        self.audio_output.set_pattern(pattern);
+    }
+
+    ///
+    /// Sets the pitch register.
+    pub fn set_pitch(&mut self, pitch : u8) {
+        self.pitch = pitch;
+        self.audio_output.set_pitch(pitch);
+    }
+}
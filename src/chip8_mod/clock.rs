@@ -0,0 +1,85 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+///
+/// A source of time for running the emulator.
+///
+/// Times are in nanoseconds since the clock was created. ```RealClock``` follows
+/// the wall clock, while ```VirtualClock``` only moves when told to, so runs
+/// using it take the same steps every time.
+pub trait Clock {
+
+    ///
+    /// The nanoseconds passed since the clock was created.
+    fn now(&self) -> u64 ;
+
+    ///
+    /// Waits until ```now``` reaches ```time```. Returns right away if it already has.
+    fn sleep_until(&mut self, time : u64) ;
+}
+
+///
+/// Follows the wall clock, sleeping the thread to wait.
+pub struct RealClock {
+    start : Instant,
+}
+
+impl RealClock {
+    pub fn new() -> RealClock {
+        RealClock {
+            start : Instant::now(),
+        }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> RealClock {
+        RealClock::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> u64 {
+        let elapsed = self.start.elapsed();
+        elapsed.as_secs() * 1000 * 1000 * 1000 + elapsed.subsec_nanos() as u64
+    }
+
+    fn sleep_until(&mut self, time : u64) {
+        let now = self.now();
+        if time > now {
+            thread::sleep(Duration::from_nanos(time - now));
+        }
+    }
+}
+
+///
+/// A clock that only moves forward when it is advanced or slept on, which it
+/// does instantly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VirtualClock {
+    pub time : u64,
+}
+
+impl VirtualClock {
+    pub fn new() -> VirtualClock {
+        VirtualClock {
+            time : 0,
+        }
+    }
+
+    ///
+    /// Moves the clock ```ns``` nanoseconds forward.
+    pub fn advance(&mut self, ns : u64) {
+        self.time += ns;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> u64 {
+        self.time
+    }
+
+    fn sleep_until(&mut self, time : u64) {
+        self.time = self.time.max(time);
+    }
+}
@@ -1,135 +1,206 @@
+use chip8_mod::instruction::Instruction;
+use chip8_mod::error::Chip8Error;
+
+///
+/// The address programs are loaded at and start running from. Everything
+/// before it is reserved for the interpreter and its fonts.
+pub const PROGRAM_START : u16 = 0x0200;
+
 ///
 /// The general trait to process opcodes.
 /// 
 /// ## A NOTE ABOUT CERTAIN OPCODES
-/// There are actually 2 different Chip8 specifications which behave differently
-/// for certain opcodes. These specifications go by many different names, but
-/// here the more popular and well-known will be called the "CowGod" specification 
-/// (after the website from which many have come to know it) while the other 
-/// will be refered to as the "Legacy" specification.
-/// Note that in this code base the opcodes are processed via the "Cowsay"
-/// specification by default.
+/// There are actually many different Chip8 interpreters which behave differently
+/// for certain opcodes. The more popular and well-known behaviour is here
+/// called the "CowGod" specification (after the website from which many have
+/// come to know it), while the original COSMAC VIP behaviour is called the
+/// "Legacy" specification. Each difference is a separate flag of
+/// ```chip8_mod::quirks::Quirks```, all of which are off in the "CowGod" spec.
+///
+/// ## ERRORS
+/// Every instruction function returns a ```Chip8Error``` if the program asks
+/// for something the machine doesn't have, such as returning with an empty
+/// stack or drawing a sprite from past the end of memory.
 pub trait OpcodeExecuter {
 
     // Instruction process functions 
 
     ///
     /// Sets the display buffer to all 0 and redraws the screen.
-    fn clear_screen(&mut self) ;
+    fn clear_screen(&mut self) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Scrolls the contents of the screen down by ```rows``` pixels.
+    /// A SUPER-CHIP instruction.
+    fn scroll_down(&mut self, rows : u8) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Scrolls the contents of the screen right by 4 pixels.
+    /// A SUPER-CHIP instruction.
+    fn scroll_right(&mut self) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Scrolls the contents of the screen left by 4 pixels.
+    /// A SUPER-CHIP instruction.
+    fn scroll_left(&mut self) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Switches the screen to the original 64x32 resolution.
+    /// A SUPER-CHIP instruction.
+    fn low_res(&mut self) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Switches the screen to the 128x64 high resolution mode.
+    /// A SUPER-CHIP instruction.
+    fn high_res(&mut self) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Selects the bitplanes that drawing, clearing, and scrolling affect;
+    /// bit ```n``` of ```mask``` selects plane ```n```. When more than one plane is
+    /// selected, sprites hold the data for each plane one after the other.
+    /// An XO-CHIP instruction.
+    fn select_planes(&mut self, mask : u8) -> Result<(), Chip8Error> ;
 
     ///
     /// Pops the top value of the stack pointer and puts it into the program counter.
-    fn ret(&mut self) ;
+    fn ret(&mut self) -> Result<(), Chip8Error> ;
 
     ///
     /// Jumps to ```addr```.
-    fn jump(&mut self, addr : u16) ;
+    fn jump(&mut self, addr : u16) -> Result<(), Chip8Error> ;
 
     ///
     /// Stores the current program counter in the stack and jumps to ```addr```.
-    fn call(&mut self, addr : u16) ;
+    fn call(&mut self, addr : u16) -> Result<(), Chip8Error> ;
 
     ///
     /// If the value in register ```register``` is equal to ```byte```, then 
     /// the program counter is incremented, skipping over the next instruction.
-    fn skip_if_equal_const(&mut self, register : usize, byte : u8) ; 
+    /// 
+    /// All of the skip instructions step over the whole 4 bytes if the next
+    /// instruction is an XO-CHIP ```F000 NNNN```.
+    fn skip_if_equal_const(&mut self, register : usize, byte : u8) -> Result<(), Chip8Error> ; 
     
     ///
     /// If the value in register ```register``` is NOT equal to ```byte```, then 
     /// the program counter is incremented, skipping over the next instruction.
-    fn skip_if_unequal_const(&mut self, register : usize, byte : u8) ;
+    fn skip_if_unequal_const(&mut self, register : usize, byte : u8) -> Result<(), Chip8Error> ;
 
 
     ///
     /// If the value in register ```register1``` is equal to the value in register ```register2``` 
     /// then the program counter is incremented, skipping over the next instruction.
-    fn skip_if_equal_reg(&mut self, register1 : usize, register2 : usize) ; 
+    fn skip_if_equal_reg(&mut self, register1 : usize, register2 : usize) -> Result<(), Chip8Error> ; 
+
+    ///
+    /// Stores the values in registers ```start``` through ```end```, inclusive,
+    /// into memory starting at the address pointer ```I```. If ```start``` is
+    /// greater than ```end``` the registers are stored in reverse order.
+    /// ```I``` is not modified.
+    /// An XO-CHIP instruction.
+    fn save_register_range(&mut self, start : usize, end : usize) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Loads the values in registers ```start``` through ```end```, inclusive,
+    /// from memory starting at the address pointer ```I```. If ```start``` is
+    /// greater than ```end``` the registers are loaded in reverse order.
+    /// ```I``` is not modified.
+    /// An XO-CHIP instruction.
+    fn restore_register_range(&mut self, start : usize, end : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the value of register ```register``` to the value ```byte```.
-    fn load_const(&mut self, register : usize, byte : u8) ;
+    fn load_const(&mut self, register : usize, byte : u8) -> Result<(), Chip8Error> ;
     
     ///
     /// Adds the value ```byte``` to the value in register ```register``` and 
     /// stores the new value into ```register```..
-    fn add_const(&mut self, register : usize, byte : u8) ;
+    fn add_const(&mut self, register : usize, byte : u8) -> Result<(), Chip8Error> ;
 
     ///
     /// Copies the value in register ```reg``` into register ```acc```. 
-    fn load_register(&mut self, acc : usize, reg : usize) ; 
+    fn load_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ; 
 
     ///
     /// Sets the value in register ```acc``` to itself bitwise-ORed with the
     /// value in register ```reg```. 
-    fn or_register(&mut self, acc : usize, reg : usize) ; 
+    /// 
+    /// With the ```vf_reset``` quirk register ```0xF``` is then set to 0,
+    /// as are the other bitwise operations.
+    fn or_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ; 
     
     ///
     /// Sets the value in register ```acc``` to itself bitwise-ANDed with the
     /// value in register ```reg```. 
-    fn and_register(&mut self, acc : usize, reg : usize) ;
+    fn and_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ;
     
     ///
     /// Sets the value in register ```acc``` to itself bitwise-XORed with the
     /// value in register ```reg```. 
-    fn xor_register(&mut self, acc : usize, reg : usize) ;
+    fn xor_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ;
     
     /// Sets the value in register ```acc``` to itself plus the
     /// value in register ```reg```. 
     /// 
     /// Register ```0xF``` is then set to 1 if the add caused an overflow, 0 otherwise.
-    fn add_register(&mut self, acc : usize, reg : usize) ;
+    fn add_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ;
     
     /// Sets the value in register ```acc``` to itself minus the
     /// value in register ```reg```. 
     /// 
     /// Register ```0xF``` is then set to 0 if the minus caused an underflow, 1 otherwise.
-    fn sub_register(&mut self, acc : usize, reg : usize) ;
+    fn sub_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
-    /// In the "CowGod" spec the value in register ```0xF``` (15) is 
-    /// set to the lowest bit of the value of register ```acc``` and then the value
-    /// in register ```acc```is divided by 2. 
-    /// The "Legacy" spec states that instead of register ```acc```, 
-    /// the value in register ```0xF``` is set to the lowest bit of the value in
-    /// register ```reg```, the value of ```reg``` is divided by 2, and the freshly
-    /// divided number is then copied into register ```acc```.
-    fn right_shift_register(&mut self, acc : usize, reg : usize) ;
+    /// In the "CowGod" spec the value in register ```acc``` is divided by 2,
+    /// and register ```0xF``` (15) is set to the bit that was shifted out. 
+    /// With the ```shift_uses_vy``` quirk, as in the "Legacy" spec, the value
+    /// in register ```reg``` divided by 2 is stored into register ```acc``` instead,
+    /// and register ```0xF``` is set to the lowest bit of the value in ```reg```.
+    fn right_shift_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ;
     
     /// Sets the value in register ```acc``` to 
     /// value in register ```reg``` minus itself.
     /// 
     /// Register ```0xF``` is then set to 0 if the minusd caused an underflow, 1 otherwise.
-    fn rev_sub_register(&mut self, acc : usize, reg : usize) ;
+    fn rev_sub_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
-    /// In the "CowGod" spec the value in register ```0xF``` (15) is 
-    /// set to 1 if the highest bit of the value of register ```acc```  is 1, and 0 if it isnt.
-    /// Then the value in register ```acc```is divided by 2. 
-    /// The "Legacy" spec states that instead of register ```acc```, 
-    /// the value in register ```0xF``` is set to 1 if the highest bit of the value in
-    /// register ```reg``` is 1 and 0 otherwise. The value of ```reg``` is then 
-    /// divided by 2, and the freshly divided number is then copied into register ```acc```.
-    fn left_shift_register(&mut self, acc : usize, reg : usize) ;
+    /// In the "CowGod" spec the value in register ```acc``` is multiplied by 2,
+    /// and register ```0xF``` (15) is set to the bit that was shifted out. 
+    /// With the ```shift_uses_vy``` quirk, as in the "Legacy" spec, the value
+    /// in register ```reg``` multiplied by 2 is stored into register ```acc``` instead,
+    /// and register ```0xF``` is set to the highest bit of the value in ```reg```.
+    fn left_shift_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// If the value in register ```register1``` is NOT equal to the value in register ```register2``` 
     /// then the program counter is incremented, skipping over the next instruction.
-    fn skip_if_unequal_reg(&mut self, register1 : usize, register2 : usize) ;
+    fn skip_if_unequal_reg(&mut self, register1 : usize, register2 : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the value in the address pointer ```I```to the constant value
     /// ```addr```.
-    fn load_addr_const(&mut self, addr : u16) ;
+    fn load_addr_const(&mut self, addr : u16) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Sets the address pointer ```I``` to the full 16-bit value stored in
+    /// the 2 bytes directly after this instruction, then steps over them;
+    /// this makes ```F000 NNNN``` the only 4-byte instruction.
+    /// An XO-CHIP instruction.
+    fn load_long_addr(&mut self) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the program counter to the value in register ```0x0```
     /// plus the value ```addr```.
-    fn add_jump_v0(&mut self, addr_offset : u16) ;
+    /// With the ```jump_uses_vx``` quirk the value in the register given by
+    /// the highest nibble of ```addr``` is used instead of register ```0x0```.
+    fn add_jump_v0(&mut self, addr_offset : u16) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the value stored in register ```reg``` to a pseudo-random number
     /// which is then bitwise-ANDed with ```mask```. 
-    fn randomize(&mut self, reg : usize, mask : u8) ;
+    fn randomize(&mut self, reg : usize, mask : u8) -> Result<(), Chip8Error> ;
 
     ///
     /// Draws a sprite whose top left corner starts offset from the left
@@ -145,39 +216,60 @@ pub trait OpcodeExecuter {
     /// in ```yreg``` to 26 (31 - 5 for the height), and length to 5. We would 
     /// then make sure that ```self.I[0 .. 5]``` is equal to ```[0xFF, 0x81, 0x81, 0x81, 0xFF]```,
     /// since ```0xFF``` has all bits set to 1 and ```0x81``` has only the outer bits set to 1. 
-    fn draw_sprite(&mut self, xreg : usize, yreg : usize, length : u8);
+    /// 
+    /// Sprites wrap around the edges of the screen unless the ```clip_sprites```
+    /// quirk is set. With the ```display_wait``` quirk, only one sprite can be
+    /// drawn per 60 Hz frame; later draws are retried on the next frame.
+    fn draw_sprite(&mut self, xreg : usize, yreg : usize, length : u8) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Draws a 16x16 sprite at the position stored in ```xreg``` and ```yreg```,
+    /// reading 32 bytes starting at ```I```; each row is 2 bytes, left half first.
+    /// A SUPER-CHIP instruction.
+    fn draw_large_sprite(&mut self, xreg : usize, yreg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Skips the next instruciton if the key whose value is stored in ```reg``` is pressed;
     /// otherwise do nothing.
-    fn skip_if_key_pressed(&mut self, reg : usize) ;
+    fn skip_if_key_pressed(&mut self, reg : usize) -> Result<(), Chip8Error> ;
     
     ///
     /// Skips the next instruciton if the key whose value is stored in ```reg``` is NOT pressed;
     /// otherwise do nothing.
-    fn skip_if_key_not_pressed(&mut self, reg : usize) ;
+    fn skip_if_key_not_pressed(&mut self, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the value in register ```reg``` to the current number stored in the 
     /// external, non-audio timer.
-    fn load_timer(&mut self, reg : usize) ;
+    fn load_timer(&mut self, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Pauses all CPU instructions until a key is pressed. The value of this
     /// key is then stored into register ```reg```.
-    fn wait_for_key(&mut self, reg : usize) ;
+    fn wait_for_key(&mut self, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the value in the external timer to the value stored in register ```reg```.
-    fn set_timer (&mut self, reg : usize) ;
+    fn set_timer (&mut self, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the audio timer to the value stored in register ```reg```.
-    fn set_audio(&mut self, reg : usize) ; 
+    fn set_audio(&mut self, reg : usize) -> Result<(), Chip8Error> ; 
+
+    ///
+    /// Loads the 16 bytes starting at the address pointer ```I``` as the
+    /// audio pattern to play while the audio timer is running.
+    /// An XO-CHIP instruction.
+    fn load_audio_pattern(&mut self) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Sets the audio pitch register to the value stored in register ```reg```.
+    /// An XO-CHIP instruction.
+    fn set_pitch(&mut self, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Adds the value stored in register ```reg``` to the address pointer ```I```.
-    fn add_addr_reg(&mut self, reg : usize) ;
+    fn add_addr_reg(&mut self, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the address pointer ```I``` to 5 * the value stored in register ```reg```.
@@ -185,44 +277,74 @@ pub trait OpcodeExecuter {
     /// characters ```0 - F```, in order, at memory locations ```0x0000 - 0x2000`, 
     /// meaning after this instruction ```I``` will point to the sprite coresponding
     /// to the value stored in ```reg```. 
-    fn set_addr_to_char(&mut self, reg : usize) ;
+    fn set_addr_to_char(&mut self, reg : usize) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Sets the address pointer ```I``` to the 8x10 sprite in the large font
+    /// corresponding to the value stored in ```reg```.
+    /// A SUPER-CHIP instruction.
+    fn set_addr_to_big_char(&mut self, reg : usize) -> Result<(), Chip8Error> ;
     
     ///
-    /// Loads the value at the memory location ```self.I + x``` into register
-    /// ```x```, for all ```x``` between ```0``` and ```reg```, inclusive.
-    /// 
-    /// In the ```LEGACY``` spec ```I``` is then incremented by ```reg + 1```,
-    /// which is the first byte *after* the loaded values.
-    fn store_digits(&mut self, reg : usize) ;
+    /// Stores the hundreds, tens, and ones digits of the value in register
+    /// ```reg``` into the memory locations ```self.I```, ```self.I + 1```,
+    /// and ```self.I + 2```, respectively.
+    fn store_digits(&mut self, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Stores the values currently stored in registers ```0``` to ```reg```,
     /// inclusive, at ```reg```-sized block of memory starting at the address
     /// pointer ```I```. 
     /// 
-    /// In the ```LEGACY``` spec ```I``` is then incremented by ```reg + 1```,
-    /// which is the first byte *after* the stored values.
-    fn save_registers(&mut self, reg : usize) ;
+    /// With the ```increment_i_on_load_store``` quirk ```I``` is then incremented
+    /// by ```reg + 1```, which is the first byte *after* the stored values.
+    fn save_registers(&mut self, reg : usize) -> Result<(), Chip8Error> ;
 
     ///
     /// Sets the values in registers ```0``` to ```reg``` to the values at
     /// memory location ```self.I + 0```, ```self.I + 1```, ... ```self.I + reg```, 
     /// where ```I``` is the CPU's 16-bit address pointer. 
     /// 
-    /// In the "Legacy" spec, the address pointer is then increased by ```reg + 1```. 
-    fn restore_registers(&mut self, reg : usize) ; 
+    /// With the ```increment_i_on_load_store``` quirk, the address pointer is
+    /// then increased by ```reg + 1```. 
+    fn restore_registers(&mut self, reg : usize) -> Result<(), Chip8Error> ; 
+
+    ///
+    /// Copies the values in registers ```0``` to ```reg```, inclusive,
+    /// into the RPL user flags, which persist between runs.
+    /// A SUPER-CHIP instruction.
+    fn save_flags(&mut self, reg : usize) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Copies the RPL user flags ```0``` to ```reg```, inclusive,
+    /// into the registers ```0``` to ```reg```.
+    /// A SUPER-CHIP instruction.
+    fn restore_flags(&mut self, reg : usize) -> Result<(), Chip8Error> ;
+
+    ///
+    /// Exits the interpreter.
+    /// A SUPER-CHIP instruction.
+    fn exit(&mut self) -> Result<(), Chip8Error> ;
 
     //Once per frame helper functions
 
     ///
-    /// If necessary, decrements the values in the audio timer and other timer
-    /// registers. 
+    /// Lets ```delta_t_in_ns``` nanoseconds pass, counting the timers down once
+    /// for every 60th of a second passed, even when several pass at once.
     fn tick(&mut self, delta_t_in_ns : u64) ;
 
+    ///
+    /// Counts the delay and sound timers down once, as happens 60 times a second.
+    fn tick_timers(&mut self) ;
+
     ///
     /// Gets the value of the next instruction to be passed to ```process_instruction```.
     /// Runs at the beginning of the clock cycle.
-    fn get_next_instr(&self) -> u16 ; 
+    fn get_next_instr(&self) -> Result<u16, Chip8Error> ;
+
+    ///
+    /// The address of the instruction currently being run.
+    fn program_counter(&self) -> u16 ;
     
     ///
     /// Ran at the end of the clock cycle. Does things like increment the program
@@ -237,6 +359,11 @@ pub trait OpcodeExecuter {
     /// this may be called in the case of a bad opcode value being read.
     fn die(&mut self) ;
 
+    ///
+    /// Checks if the instruction ```instr``` exists on the machine being emulated.
+    /// Unsupported instructions are treated as invalid rather than executed.
+    fn is_instruction_supported(&self, instr : &Instruction) -> bool ;
+
     ///
     /// Checks if the CPU has had the method ```die()``` called.
     /// Not used in the virtual Chip8 itself, but by the emulator to close
@@ -247,7 +374,8 @@ pub trait OpcodeExecuter {
     /// Loads a ROM into memory. The rom will be places into memory offset 
     /// by ```0x200```, or 512, due to the default fontset; in effect, this means
     /// that ```self.memory[0x200] = rom[0]```, ```self.memory[0x200 + 1] = rom[1]```, etc.
-    fn load_rom(&mut self, rom : &[u8]) ;  
+    /// ROMs too large to fit are rejected without loading any of them.
+    fn load_rom(&mut self, rom : &[u8]) -> Result<(), Chip8Error> ;
 
     ///
     /// Resets the CPU to its initial state. 
@@ -256,192 +384,98 @@ pub trait OpcodeExecuter {
     fn reset(&mut self) ; 
 
 
+    ///
+    /// Runs the instruction at the program counter, then moves on to the next one.
+    fn step(&mut self) -> Result<(), Chip8Error> {
+        let op = self.get_next_instr()?;
+        self.process_instruction(op)?;
+        self.end_frame();
+        Ok(())
+    }
+
     ///
     /// Decodes a raw opcode value into its constituent parts,
     /// figures out what function call and parameters that corresponds to,
     /// and calls the function with the correct parameters. 
-    fn process_instruction(&mut self, op : u16) { 
+    /// 
+    /// If the opcode is invalid or can't be run, the CPU dies and the reason
+    /// is returned.
+    fn process_instruction(&mut self, op : u16) -> Result<(), Chip8Error> { 
 
         if op == 0 {
-            return;
-        }
-        else if op == 0x00E0 { 
-            self.clear_screen() 
-        } 
-
-        else if op == 0x00EE { 
-            self.ret() 
-        } 
-
-        else if op & 0xF000 == 0 {
-            error_log!("GOT A ZERO OP: {:#X}", op);
-        }
-
-        else if op & 0xF000 == 0x1000 {
-            self.jump(addr(op))
-        }
-
-        else if op & 0xF000 == 0x2000 {
-            self.call(addr(op))
-        }
-
-        else if op & 0xF000 == 0x3000 {
-            self.skip_if_equal_const(acc(op), num(op))
-        }
-
-        else if op & 0xF000 == 0x4000 {
-            self.skip_if_unequal_const(acc(op), num(op))
-        }
-
-        else if op & 0xF000 == 0x5000 {
-            self.skip_if_equal_reg(acc(op), reg(op))
-        }
-
-        else if op & 0xF000 == 0x6000 {
-            self.load_const(acc(op), num(op))
-        }
-
-        else if op & 0xF000 == 0x7000 {
-            self.add_const(acc(op), num(op))
-        }
-
-        else if op & 0xF00F == 0x8000 {
-            self.load_register(acc(op), reg(op))
-        }
-        
-        else if op & 0xF00F == 0x8001 {
-            self.or_register(acc(op), reg(op))
-        }
-        
-        else if op & 0xF00F == 0x8002 {
-            self.and_register(acc(op), reg(op))
-        }
-
-        else if op & 0xF00F == 0x8003 {
-            self.xor_register(acc(op), reg(op))
-        }
-
-        else if op & 0xF00F == 0x8004 {
-            self.add_register(acc(op), reg(op))
-        } 
-
-        else if op & 0xF00F == 0x8005 {
-            self.sub_register(acc(op), reg(op))
-        }
-
-        else if op & 0xF00F == 0x8006 {
-            self.right_shift_register(acc(op), reg(op))
-        }
-
-        else if op & 0xF00F == 0x8007 {
-            self.rev_sub_register(acc(op), reg(op))
-        }
-
-        else if op & 0xF00F == 0x800E {
-            self.left_shift_register(acc(op), reg(op))
-        }
-
-        else if op & 0xF00F == 0x9000 {
-            self.skip_if_unequal_reg(acc(op), reg(op))
-        }
-
-        else if op & 0xF000 == 0xA000 {
-            self.load_addr_const(addr(op))
-        }
-
-        else if op & 0xF000 == 0xB000 {
-            self.add_jump_v0(addr(op))
-        }
-
-        else if op & 0xF000 == 0xC000 {
-            self.randomize(acc(op), num(op))
-        }
-
-        else if op & 0xF000 == 0xD000 {
-            let rows = (op & 0x000F) as u8;
-            self.draw_sprite(acc(op), reg(op), rows)
-        }
-
-        else if op & 0xF0FF == 0xE09E {
-            self.skip_if_key_pressed(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xE0A1 {
-            self.skip_if_key_not_pressed(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xF007 {
-            self.load_timer(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xF00A {
-            self.wait_for_key(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xF015 {
-            self.set_timer(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xF018 {
-            self.set_audio(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xF01E {
-            self.add_addr_reg(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xF029 {
-            self.set_addr_to_char(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xF033 {
-            self.store_digits(acc(op))
-        }
-
-        else if op & 0xF0FF == 0xF055 {
-            self.save_registers(acc(op))
-        }        
-
-        else if op & 0xF0FF == 0xF065 {
-            self.restore_registers(acc(op))
-        }
-
-        else {
-            error_log!("BAD OPCODE: {:#X}", op);
+            return Ok(());
+        }
+        let addr = self.program_counter();
+        let res = match Instruction::decode(op) {
+            Err(_) => Err(Chip8Error::BadOpcode { addr, opcode : op }),
+            Ok(ref instr) if !self.is_instruction_supported(instr) => Err(Chip8Error::UnsupportedOpcode { addr, opcode : op }),
+            Ok(instr) => self.execute_instruction(instr),
+        };
+        if let Err(ref e) = res {
+            error!(target : "cpu", "{}", e);
             self.die();
         }
+        res
     }
-}
-
-///
-/// An enum to set the CPU's instruction set between COWGOD, which is more
-/// popular, and LEGACY, which is official. 
-pub enum InstructionSet {
-    LEGACY,
-    COWGOD,
-}
-
 
-#[inline(always)]
-fn addr(instruction : u16) -> u16 {
-    if (instruction & 0x0FFF) % 2 == 1 {
-        debug_log!("Got odd jump!");
+    ///
+    /// Calls the function for an already decoded instruction.
+    fn execute_instruction(&mut self, instr : Instruction) -> Result<(), Chip8Error> {
+        match instr {
+            Instruction::MachineCall(addr) => {
+                warn!(target : "cpu", "GOT A ZERO OP: {:#X}", addr);
+                Ok(())
+            },
+            Instruction::ClearScreen => self.clear_screen(),
+            Instruction::Return => self.ret(),
+            Instruction::ScrollDown(rows) => self.scroll_down(rows),
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Exit => self.exit(),
+            Instruction::LowRes => self.low_res(),
+            Instruction::HighRes => self.high_res(),
+            Instruction::Jump(addr) => self.jump(addr),
+            Instruction::Call(addr) => self.call(addr),
+            Instruction::SkipIfEqualConst(x, byte) => self.skip_if_equal_const(x, byte),
+            Instruction::SkipIfUnequalConst(x, byte) => self.skip_if_unequal_const(x, byte),
+            Instruction::SkipIfEqualReg(x, y) => self.skip_if_equal_reg(x, y),
+            Instruction::SaveRegisterRange(x, y) => self.save_register_range(x, y),
+            Instruction::RestoreRegisterRange(x, y) => self.restore_register_range(x, y),
+            Instruction::LoadConst(x, byte) => self.load_const(x, byte),
+            Instruction::AddConst(x, byte) => self.add_const(x, byte),
+            Instruction::LoadRegister(x, y) => self.load_register(x, y),
+            Instruction::OrRegister(x, y) => self.or_register(x, y),
+            Instruction::AndRegister(x, y) => self.and_register(x, y),
+            Instruction::XorRegister(x, y) => self.xor_register(x, y),
+            Instruction::AddRegister(x, y) => self.add_register(x, y),
+            Instruction::SubRegister(x, y) => self.sub_register(x, y),
+            Instruction::RightShiftRegister(x, y) => self.right_shift_register(x, y),
+            Instruction::RevSubRegister(x, y) => self.rev_sub_register(x, y),
+            Instruction::LeftShiftRegister(x, y) => self.left_shift_register(x, y),
+            Instruction::SkipIfUnequalReg(x, y) => self.skip_if_unequal_reg(x, y),
+            Instruction::LoadAddrConst(addr) => self.load_addr_const(addr),
+            Instruction::LoadLongAddr => self.load_long_addr(),
+            Instruction::AddJumpV0(addr) => self.add_jump_v0(addr),
+            Instruction::Randomize(x, mask) => self.randomize(x, mask),
+            Instruction::DrawSprite(x, y, rows) => self.draw_sprite(x, y, rows),
+            Instruction::DrawLargeSprite(x, y) => self.draw_large_sprite(x, y),
+            Instruction::SkipIfKeyPressed(x) => self.skip_if_key_pressed(x),
+            Instruction::SkipIfKeyNotPressed(x) => self.skip_if_key_not_pressed(x),
+            Instruction::SelectPlanes(mask) => self.select_planes(mask),
+            Instruction::LoadAudioPattern => self.load_audio_pattern(),
+            Instruction::LoadTimer(x) => self.load_timer(x),
+            Instruction::WaitForKey(x) => self.wait_for_key(x),
+            Instruction::SetTimer(x) => self.set_timer(x),
+            Instruction::SetAudio(x) => self.set_audio(x),
+            Instruction::AddAddrReg(x) => self.add_addr_reg(x),
+            Instruction::SetAddrToChar(x) => self.set_addr_to_char(x),
+            Instruction::SetAddrToBigChar(x) => self.set_addr_to_big_char(x),
+            Instruction::StoreDigits(x) => self.store_digits(x),
+            Instruction::SetPitch(x) => self.set_pitch(x),
+            Instruction::SaveRegisters(x) => self.save_registers(x),
+            Instruction::RestoreRegisters(x) => self.restore_registers(x),
+            Instruction::SaveFlags(x) => self.save_flags(x),
+            Instruction::RestoreFlags(x) => self.restore_flags(x),
+        }
     }
-    instruction & 0x0FFF
-}
-
-#[inline(always)]
-fn acc(instruction : u16) -> usize {
-    ((instruction & 0x0F00) >> 8) as usize
 }
-
-#[inline(always)]
-fn reg(instruction : u16) -> usize {
-    ((instruction & 0x00F0) >> 4) as usize
-}
-
-
-#[inline(always)]
-fn num(instruction : u16) -> u8 {
-    (instruction & 0x00FF) as u8
-}
\ No newline at end of file
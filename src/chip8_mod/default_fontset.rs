@@ -1,3 +1,11 @@
+///
+/// Where in memory the small 4x5 font is loaded.
+pub const FONT_START : usize = 0x0000;
+
+///
+/// Where in memory the large 8x10 font is loaded, directly after the small font.
+pub const BIG_FONT_START : usize = FONT_START + 0x10 * 5;
+
 
 
 pub const RAW_0 : [u8 ; 5] = [
@@ -151,4 +159,239 @@ pub fn get_raw_char(inp : u8) -> [u8; 5] {
 
         _ => [0 ; 5]
     }
+}
+
+// The SUPER-CHIP large font, 8 pixels wide and 10 tall, used by the FX30 opcode.
+
+pub const RAW_BIG_0 : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_1 : [u8 ; 10] = [
+    0b00011000,
+    0b01111000,
+    0b01111000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_2 : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_3 : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_4 : [u8 ; 10] = [
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b00000011,
+    0b00000011
+];
+
+pub const RAW_BIG_5 : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_6 : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_7 : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b00000110,
+    0b00001100,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000
+];
+
+pub const RAW_BIG_8 : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_9 : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_A : [u8 ; 10] = [
+    0b01111110,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11000011
+];
+
+pub const RAW_BIG_B : [u8 ; 10] = [
+    0b11111100,
+    0b11111100,
+    0b11000011,
+    0b11000011,
+    0b11111100,
+    0b11111100,
+    0b11000011,
+    0b11000011,
+    0b11111100,
+    0b11111100
+];
+
+pub const RAW_BIG_C : [u8 ; 10] = [
+    0b00111100,
+    0b11111111,
+    0b11000011,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000011,
+    0b11111111,
+    0b00111100
+];
+
+pub const RAW_BIG_D : [u8 ; 10] = [
+    0b11111100,
+    0b11111110,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111110,
+    0b11111100
+];
+
+pub const RAW_BIG_E : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111
+];
+
+pub const RAW_BIG_F : [u8 ; 10] = [
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000000
+];
+
+#[inline(always)]
+pub fn get_raw_big_char(inp : u8) -> [u8; 10] {
+    match inp {
+        0x00 => RAW_BIG_0,
+        0x01 => RAW_BIG_1,
+        0x02 => RAW_BIG_2,
+        0x03 => RAW_BIG_3,
+        0x04 => RAW_BIG_4,
+        0x05 => RAW_BIG_5,
+        0x06 => RAW_BIG_6,
+        0x07 => RAW_BIG_7,
+        0x08 => RAW_BIG_8,
+        0x09 => RAW_BIG_9,
+
+        0x0A => RAW_BIG_A,
+        0x0B => RAW_BIG_B,
+        0x0C => RAW_BIG_C,
+        0x0D => RAW_BIG_D,
+        0x0E => RAW_BIG_E,
+        0x0F => RAW_BIG_F,
+
+        _ => [0 ; 10]
+    }
 }
\ No newline at end of file
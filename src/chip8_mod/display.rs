@@ -1,113 +1,405 @@
-
+use log::Level;
 
 
 pub const SCREEN_WIDTH : usize = 64;
 pub const SCREEN_HEIGHT : usize = 32;
 
+pub const HIRES_SCREEN_WIDTH : usize = 128;
+pub const HIRES_SCREEN_HEIGHT : usize = 64;
+
+///
+/// The number of bitplanes in the buffer. The original Chip 8 and the SUPER-CHIP
+/// only ever draw to the first; XO-CHIP can select either or both via ```FN01```.
+pub const PLANE_COUNT : usize = 2;
+
+///
+/// The colours a pixel can take, indexed by the pixel's plane bits:
+/// bit 0 is set if the pixel is on in the first plane, bit 1 if it is on in the second.
+/// Each colour is stored as ```0xRRGGBB```.
+pub type Palette = [u32 ; 1 << PLANE_COUNT];
+
+///
+/// Black on white for the first plane, as the emulator has always drawn,
+/// with greys for the second plane and the overlap of both.
+pub const DEFAULT_PALETTE : Palette = [0xFFFFFF, 0x000000, 0xAAAAAA, 0x555555];
+
+///
+/// The resolution the screen is currently drawn at.
+///
+/// The original Chip 8 only has the 64x32 ```Low``` mode; the SUPER-CHIP
+/// adds the 128x64 ```High``` mode, toggled via the ```00FF``` and ```00FE``` opcodes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resolution {
+    Low,
+    High,
+}
+
+impl Resolution {
+    pub fn width(&self) -> usize {
+        match *self {
+            Resolution::Low => SCREEN_WIDTH,
+            Resolution::High => HIRES_SCREEN_WIDTH,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match *self {
+            Resolution::Low => SCREEN_HEIGHT,
+            Resolution::High => HIRES_SCREEN_HEIGHT,
+        }
+    }
+
+    ///
+    /// The number of bytes needed to store a full plane of packed pixels.
+    pub fn buffer_size(&self) -> usize {
+        self.width()/8 * self.height()
+    }
+}
 
 pub trait DisplayOutput {
-    fn display_buffer (&mut self, buffer : &[u8 ; SCREEN_WIDTH/8 * SCREEN_HEIGHT]) ;
+
+    ///
+    /// Draws the screen. ```pixels``` holds one entry per pixel, row by row,
+    /// so it is ```resolution.width() * resolution.height()``` long. Each entry
+    /// is the index into ```palette``` of that pixel's colour.
+    fn display_buffer (&mut self, pixels : &[u8], resolution : Resolution, palette : &Palette) ;
 }
 
-pub struct ScreenBuffer <'a> {
-    pub packed_pixels : [u8 ; SCREEN_WIDTH/8 * SCREEN_HEIGHT],
-    pub display_output : &'a mut (DisplayOutput + 'a)
+///
+/// Lets a ```ScreenBuffer``` borrow its display output instead of owning it.
+impl <D : DisplayOutput + ?Sized> DisplayOutput for &mut D {
+    fn display_buffer (&mut self, pixels : &[u8], resolution : Resolution, palette : &Palette) {
+        (**self).display_buffer(pixels, resolution, palette)
+    }
 }
 
-impl <'a> ScreenBuffer <'a> {
+///
+/// The screen's contents, which are sent to the display output ```D``` when redrawn.
+#[derive(Clone)]
+pub struct ScreenBuffer <D> {
+
+    ///
+    /// The packed pixels of each plane, with each byte holding 8
+    /// horizontally-adjacent pixels, highest bit first.
+    pub planes : [Vec<u8> ; PLANE_COUNT],
 
-    pub fn new (disp : &'a  mut DisplayOutput) -> ScreenBuffer<'a> {
+    ///
+    /// A bitmask of the planes that drawing, clearing, and scrolling affect.
+    pub selected_planes : u8,
+    pub resolution : Resolution,
+    pub palette : Palette,
+
+    ///
+    /// If set, sprites are cut off at the edges of the screen instead of
+    /// wrapping around. Sprites always start wrapped onto the screen.
+    pub clip_sprites : bool,
+
+    ///
+    /// Whether the planes changed since they were last sent to the display output.
+    pub dirty : bool,
+    pub display_output : D
+}
+
+impl <D : DisplayOutput> ScreenBuffer <D> {
+
+    pub fn new (disp : D) -> ScreenBuffer<D> {
         ScreenBuffer {
-            packed_pixels : [0 ; SCREEN_WIDTH/ 8 * SCREEN_HEIGHT],
+            planes : [vec![0 ; Resolution::Low.buffer_size()], vec![0 ; Resolution::Low.buffer_size()]],
+            selected_planes : 1,
+            resolution : Resolution::Low,
+            palette : DEFAULT_PALETTE,
+            clip_sprites : false,
+            dirty : false,
             display_output : disp
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.resolution.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.resolution.height()
+    }
+
+    ///
+    /// Sets which planes later drawing affects; bit ```n``` of ```mask``` selects plane ```n```.
+    pub fn select_planes(&mut self, mask : u8) {
+        self.selected_planes = mask & ((1 << PLANE_COUNT) - 1);
+    }
+
+    ///
+    /// The number of planes currently selected.
+    pub fn selected_plane_count(&self) -> usize {
+        self.selected_planes.count_ones() as usize
+    }
+
+    fn is_selected(&self, plane : usize) -> bool {
+        self.selected_planes & (1 << plane) != 0
+    }
+
+    ///
+    /// Blanks the selected planes.
     pub fn clear_screen(&mut self) {
-        self.packed_pixels = [0; SCREEN_WIDTH/8 * SCREEN_HEIGHT];
-        self.display_output.display_buffer(&self.packed_pixels);
+        for plane in 0 .. PLANE_COUNT {
+            if self.is_selected(plane) {
+                self.planes[plane] = vec![0; self.resolution.buffer_size()];
+            }
+        }
+        self.dirty = true;
+    }
+
+    ///
+    /// Switches the screen to resolution ```res```, clearing every plane in the process.
+    pub fn set_resolution(&mut self, res : Resolution) {
+        self.resolution = res;
+        for plane in self.planes.iter_mut() {
+            *plane = vec![0; res.buffer_size()];
+        }
+        self.dirty = true;
+    }
+
+    ///
+    /// Moves every row of the selected planes down by ```rows``` pixels.
+    /// The rows scrolled in from the top are blank.
+    pub fn scroll_down(&mut self, rows : usize) {
+        let row_bytes = self.width()/8;
+        let shift = row_bytes * rows.min(self.height());
+        for plane in 0 .. PLANE_COUNT {
+            if !self.is_selected(plane) {
+                continue;
+            }
+            let pixels = &mut self.planes[plane];
+            let len = pixels.len();
+            for idx in (shift .. len).rev() {
+                pixels[idx] = pixels[idx - shift];
+            }
+            for pixel in pixels[.. shift].iter_mut() {
+                *pixel = 0;
+            }
+        }
+        self.dirty = true;
     }
 
+    ///
+    /// Moves every column of the selected planes right by 4 pixels.
+    pub fn scroll_right(&mut self) {
+        let row_bytes = self.width()/8;
+        for plane in 0 .. PLANE_COUNT {
+            if !self.is_selected(plane) {
+                continue;
+            }
+            for row in self.planes[plane].chunks_mut(row_bytes) {
+                for idx in (1 .. row_bytes).rev() {
+                    row[idx] = (row[idx] >> 4) | (row[idx - 1] << 4);
+                }
+                row[0] >>= 4;
+            }
+        }
+        self.dirty = true;
+    }
+
+    ///
+    /// Moves every column of the selected planes left by 4 pixels.
+    pub fn scroll_left(&mut self) {
+        let row_bytes = self.width()/8;
+        for plane in 0 .. PLANE_COUNT {
+            if !self.is_selected(plane) {
+                continue;
+            }
+            for row in self.planes[plane].chunks_mut(row_bytes) {
+                for idx in 0 .. row_bytes - 1 {
+                    row[idx] = (row[idx] << 4) | (row[idx + 1] >> 4);
+                }
+                row[row_bytes - 1] <<= 4;
+            }
+        }
+        self.dirty = true;
+    }
+
+    ///
+    /// Draws an 8 pixel wide sprite to each selected plane.
+    /// ```sprite``` holds the rows for the first selected plane, followed by
+    /// the same number of rows for the next selected plane, and so on.
     pub fn put_sprite(&mut self, x : u8,y : u8, sprite : &[u8]) -> bool {
 
-        debug_log!("Got put request at {}, {} length {}.", x, y, sprite.len());
+        trace!(target : "display", "Got put request at {}, {} length {}.", x, y, sprite.len());
+        let plane_len = sprite.len() / self.selected_plane_count().max(1);
+        let mut collided = false;
+        let mut needs_draw = false;
+        let mut data = sprite.chunks(plane_len.max(1));
+        for plane in 0 .. PLANE_COUNT {
+            if !self.is_selected(plane) {
+                continue;
+            }
+            if let Some(plane_sprite) = data.next() {
+                let (plane_collided, plane_changed) = self.xor_sprite(plane, x, y, plane_sprite);
+                collided = collided || plane_collided;
+                needs_draw = needs_draw || plane_changed;
+            }
+        }
+        if needs_draw {
+            self.dirty = true;
+        }
+        collided
+    }
+
+    ///
+    /// Draws a SUPER-CHIP 16x16 sprite to each selected plane. Each row of the
+    /// sprite is 2 bytes, left half first, so ```sprite``` should be 32 bytes long
+    /// per selected plane.
+    pub fn put_large_sprite(&mut self, x : u8, y : u8, sprite : &[u8]) -> bool {
+        trace!(target : "display", "Got large put request at {}, {} length {}.", x, y, sprite.len());
+        let mut collided = false;
+        let mut needs_draw = false;
+        let mut data = sprite.chunks(32);
+        for plane in 0 .. PLANE_COUNT {
+            if !self.is_selected(plane) {
+                continue;
+            }
+            if let Some(plane_sprite) = data.next() {
+                let left : Vec<u8> = plane_sprite.iter().step_by(2).cloned().collect();
+                let right : Vec<u8> = plane_sprite.iter().skip(1).step_by(2).cloned().collect();
+                let (left_collided, left_changed) = self.xor_sprite(plane, x, y, &left);
+                let right_clipped = self.clip_sprites && x as usize % self.width() + 8 >= self.width();
+                let (right_collided, right_changed) = if right_clipped {
+                    (false, false)
+                }
+                else {
+                    self.xor_sprite(plane, x.wrapping_add(8), y, &right)
+                };
+                collided = collided || left_collided || right_collided;
+                needs_draw = needs_draw || left_changed || right_changed;
+            }
+        }
+        if needs_draw {
+            self.dirty = true;
+        }
+        collided
+    }
+
+    ///
+    /// Combines the planes into the palette index of every pixel on the screen.
+    pub fn pixel_indices(&self) -> Vec<u8> {
+        let mut pixels = vec![0 ; self.width() * self.height()];
+        for (plane_num, plane) in self.planes.iter().enumerate() {
+            for (idx, byte) in plane.iter().enumerate() {
+                for bitnum in 0 .. 8 {
+                    if byte & (1 << (7 - bitnum)) != 0 {
+                        pixels[idx * 8 + bitnum] |= 1 << plane_num;
+                    }
+                }
+            }
+        }
+        pixels
+    }
+
+    ///
+    /// Sends the planes to the display output if they changed since they were
+    /// last sent. Frontends call this once a frame.
+    pub fn present(&mut self) {
+        if self.dirty {
+            self.draw();
+        }
+    }
+
+    ///
+    /// Sends the planes to the display output right away, such as after
+    /// setting them directly.
+    pub fn draw(&mut self) {
+        self.dirty = false;
+        let pixels = self.pixel_indices();
+        if log_enabled!(target : "display", Level::Trace) {
+            let mut frame = String::new();
+            for row in pixels.chunks(self.width()) {
+                frame.extend(row.iter().map(|pixel| (b'0' + pixel) as char));
+                frame.push('\n');
+            }
+            trace!(target : "display", "Logging frame:\n{}", frame);
+        }
+        self.display_output.display_buffer(&pixels, self.resolution, &self.palette);
+    }
+
+    ///
+    /// XORs the sprite onto the plane without redrawing the screen,
+    /// returning whether any pixels collided and whether any changed.
+    fn xor_sprite(&mut self, plane : usize, x : u8, y : u8, sprite : &[u8]) -> (bool, bool) {
         if x % 8 == 0 {
-            return self.put_sprite_simple(x, y, sprite);
+            return self.xor_sprite_simple(plane, x, y, sprite);
         }
 
+        let width = self.width();
+        let height = self.height();
+        let clip = self.clip_sprites;
+        let packed_pixels = &mut self.planes[plane];
 
         let mut collided = false;
         let mut needs_draw = false;
-        
+
+        let x = x as usize % width;
+        let y = y as usize % height;
         let offset = x % 8;
         let high_mask = 0xFF << (8 - offset);
         let low_mask = !high_mask;
         for (row_count, row_pixels) in sprite.iter().enumerate() {
-            let left_x_offset = (x as usize/8) % (SCREEN_WIDTH/8);
-            let right_x_offset = (1 + x as usize/8) % (SCREEN_WIDTH/8);
-            let y_offset = SCREEN_WIDTH/8 * ((y as usize + row_count) % SCREEN_HEIGHT);
+            if clip && y + row_count >= height {
+                break;
+            }
+            let left_x_offset = x/8;
+            let right_x_offset = (1 + x/8) % (width/8);
+            let y_offset = width/8 * ((y + row_count) % height);
+            // The right half of the row falls off the screen
+            let row_pixels = if clip && right_x_offset == 0 { row_pixels & (0xFF << offset) } else { *row_pixels };
 
             let left_pos = y_offset + left_x_offset;
             let right_pos = y_offset + right_x_offset;
 
-            let left_packet = self.packed_pixels[left_pos];
-            let right_packet = self.packed_pixels[right_pos];
+            let left_packet = packed_pixels[left_pos];
+            let right_packet = packed_pixels[right_pos];
 
             let top_bits = (left_packet & low_mask) << offset;
             let bottom_bits = (right_packet & high_mask) >> (8 - offset);
             let cur_pixels = top_bits + bottom_bits;
-            
+
             let next_pixels = cur_pixels ^ row_pixels;
 
             if cur_pixels != next_pixels {
-                self.packed_pixels[left_pos] = (next_pixels >> (offset)) | (left_packet & high_mask);
-                self.packed_pixels[right_pos] = (next_pixels << (8 - offset)) | (right_packet & low_mask);
+                packed_pixels[left_pos] = (next_pixels >> (offset)) | (left_packet & high_mask);
+                packed_pixels[right_pos] = (next_pixels << (8 - offset)) | (right_packet & low_mask);
                 collided = collided || (0 != (cur_pixels & !next_pixels));
                 needs_draw = true;
             }
 
         }
-        if needs_draw {
-            if cfg!(feature = "log_frames") {
-                println!("Logging frame:");
-                for (idx, byte) in self.packed_pixels.into_iter().enumerate() {
-                    if idx % (SCREEN_WIDTH /8) == 0 {
-                        println!();
-                    }
-                    for mask_num in 0 .. 8 {
-                        let mask = 1 << (7 - mask_num);
-                        let pval = if byte & mask != 0 { "1" } else { "0" };
-                        print!("{}", pval);
-                    }
-                }
-                println!();
-            }
-            self.display_output.display_buffer(&self.packed_pixels);
-        }
 
-        collided
+        (collided, needs_draw)
     }
 
-    fn put_sprite_simple(&mut self, x : u8, y : u8, sprite : &[u8]) -> bool {
+    fn xor_sprite_simple(&mut self, plane : usize, x : u8, y : u8, sprite : &[u8]) -> (bool, bool) {
+        let width = self.width();
+        let height = self.height();
+        let clip = self.clip_sprites;
+        let packed_pixels = &mut self.planes[plane];
         let mut collided = false;
         let mut needs_draw = false;
+        let y = y as usize % height;
         for (row_count, row_pixels) in sprite.iter().enumerate() {
-            let x_bonus = (x as usize)/8 % (SCREEN_WIDTH/8);
-            let y_bonus = (((row_count + y as usize) % SCREEN_HEIGHT) * SCREEN_WIDTH/8) as usize;
+            if clip && y + row_count >= height {
+                break;
+            }
+            let x_bonus = (x as usize)/8 % (width/8);
+            let y_bonus = ((row_count + y) % height) * width/8;
             let cur_pos = x_bonus + y_bonus;
 
-            let cur_pixels = self.packed_pixels[cur_pos];
+            let cur_pixels = packed_pixels[cur_pos];
             let next_pixels = cur_pixels ^ row_pixels;
 
             if cur_pixels != next_pixels {
-                self.packed_pixels[cur_pos] = next_pixels;
+                packed_pixels[cur_pos] = next_pixels;
                 collided = collided || (0 != (cur_pixels & !next_pixels));
                 needs_draw = true;
             }
         }
-        if needs_draw {
-            self.display_output.display_buffer(&self.packed_pixels);
-        }
-        collided
+        (collided, needs_draw)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,64 @@
+//!
+//! Code related to the ways running a program can go wrong.
+//!
+//! Programs can only do what the machine lets them, but a buggy or corrupt
+//! ROM can still ask for things the machine doesn't have: a return with an
+//! empty stack, a sprite read past the end of memory, and so on. Instead of
+//! panicking, the CPU stops and reports which of these happened, and where.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Chip8Error {
+
+    ///
+    /// The opcode at ```addr``` isn't an instruction at all.
+    BadOpcode { addr : u16, opcode : u16 },
+
+    ///
+    /// The opcode at ```addr``` is an instruction the platform doesn't have.
+    UnsupportedOpcode { addr : u16, opcode : u16 },
+
+    ///
+    /// The call at ```addr``` went deeper than the stack.
+    StackOverflow { addr : u16 },
+
+    ///
+    /// The return at ```addr``` happened with nothing on the stack.
+    StackUnderflow { addr : u16 },
+
+    ///
+    /// The instruction at ```addr``` read or wrote the ```len``` bytes starting
+    /// at ```access```, which run past the end of memory.
+    MemoryOutOfBounds { addr : u16, access : usize, len : usize },
+
+    ///
+    /// The program counter ran off the end of memory.
+    PcOutOfBounds { addr : u16 },
+
+    ///
+    /// The ROM is ```size``` bytes long, but only ```max``` bytes fit
+    /// after ```PROGRAM_START```.
+    RomTooLarge { size : usize, max : usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::BadOpcode { addr, opcode } => write!(f, "bad opcode {:#06X} at {:#X}", opcode, addr),
+            Chip8Error::UnsupportedOpcode { addr, opcode } => write!(f, "unsupported opcode {:#06X} at {:#X}", opcode, addr),
+            Chip8Error::StackOverflow { addr } => write!(f, "stack overflow at {:#X}", addr),
+            Chip8Error::StackUnderflow { addr } => write!(f, "stack underflow at {:#X}", addr),
+            Chip8Error::MemoryOutOfBounds { addr, access, len } => write!(f, "{}-byte memory access at {:#05X} is out of bounds (instruction at {:#05X})", len, access, addr),
+            Chip8Error::PcOutOfBounds { addr } => write!(f, "program counter ran out of memory at {:#X}", addr),
+            Chip8Error::RomTooLarge { size, max } => write!(f, "ROM is {} bytes, but only {} fit in memory", size, max),
+        }
+    }
+}
+
+impl Error for Chip8Error {
+    fn description(&self) -> &str {
+        "chip 8 execution error"
+    }
+}
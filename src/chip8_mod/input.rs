@@ -1,6 +1,51 @@
+///
+/// A request from the user to save or load one of the numbered save state slots.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SaveSlotRequest {
+    Save(usize),
+    Load(usize),
+}
+
 pub trait InputReciever {
     fn check_key(&mut self, key : u8) -> bool;
     fn check_any_key(&mut self) -> Option<u8>;
 
     fn check_should_die(&mut self) -> bool;
+
+    ///
+    /// Takes the most recent save slot request, if the user has made one since
+    /// the last check. Inputs without save slot hotkeys never make any.
+    fn check_save_slot_request(&mut self) -> Option<SaveSlotRequest> {
+        None
+    }
+
+    ///
+    /// Checks if the user is holding the rewind key.
+    fn check_rewind(&mut self) -> bool {
+        false
+    }
+}
+
+///
+/// Lets the CPU borrow its input instead of owning it.
+impl <I : InputReciever + ?Sized> InputReciever for &mut I {
+    fn check_key(&mut self, key : u8) -> bool {
+        (**self).check_key(key)
+    }
+
+    fn check_any_key(&mut self) -> Option<u8> {
+        (**self).check_any_key()
+    }
+
+    fn check_should_die(&mut self) -> bool {
+        (**self).check_should_die()
+    }
+
+    fn check_save_slot_request(&mut self) -> Option<SaveSlotRequest> {
+        (**self).check_save_slot_request()
+    }
+
+    fn check_rewind(&mut self) -> bool {
+        (**self).check_rewind()
+    }
 }
@@ -0,0 +1,457 @@
+//!
+//! Code related to decoding and encoding single Chip 8 instructions.
+//!
+//! Every part of the emulator that needs to know what an opcode means,
+//! whether that is the CPU, a disassembler, or a debugger, goes through
+//! ```Instruction::decode``` so that they all agree.
+
+use std::error::Error;
+use std::fmt;
+
+///
+/// A single decoded instruction. The variants mirror the methods of
+/// ```chip8_mod::cpu::OpcodeExecuter```, and so do their parameters:
+/// register numbers come first, followed by any constant values.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Instruction {
+
+    ///
+    /// ```0NNN```: runs the machine code subroutine at ```NNN```.
+    /// Only the COSMAC VIP ever had this, and it is not emulated.
+    MachineCall(u16),
+
+    /// ```00E0```
+    ClearScreen,
+
+    /// ```00EE```
+    Return,
+
+    /// ```00CN```
+    ScrollDown(u8),
+
+    /// ```00FB```
+    ScrollRight,
+
+    /// ```00FC```
+    ScrollLeft,
+
+    /// ```00FD```
+    Exit,
+
+    /// ```00FE```
+    LowRes,
+
+    /// ```00FF```
+    HighRes,
+
+    /// ```1NNN```
+    Jump(u16),
+
+    /// ```2NNN```
+    Call(u16),
+
+    /// ```3XNN```
+    SkipIfEqualConst(usize, u8),
+
+    /// ```4XNN```
+    SkipIfUnequalConst(usize, u8),
+
+    /// ```5XY0```
+    SkipIfEqualReg(usize, usize),
+
+    /// ```5XY2```
+    SaveRegisterRange(usize, usize),
+
+    /// ```5XY3```
+    RestoreRegisterRange(usize, usize),
+
+    /// ```6XNN```
+    LoadConst(usize, u8),
+
+    /// ```7XNN```
+    AddConst(usize, u8),
+
+    /// ```8XY0```
+    LoadRegister(usize, usize),
+
+    /// ```8XY1```
+    OrRegister(usize, usize),
+
+    /// ```8XY2```
+    AndRegister(usize, usize),
+
+    /// ```8XY3```
+    XorRegister(usize, usize),
+
+    /// ```8XY4```
+    AddRegister(usize, usize),
+
+    /// ```8XY5```
+    SubRegister(usize, usize),
+
+    /// ```8XY6```
+    RightShiftRegister(usize, usize),
+
+    /// ```8XY7```
+    RevSubRegister(usize, usize),
+
+    /// ```8XYE```
+    LeftShiftRegister(usize, usize),
+
+    /// ```9XY0```
+    SkipIfUnequalReg(usize, usize),
+
+    /// ```ANNN```
+    LoadAddrConst(u16),
+
+    ///
+    /// ```F000 NNNN```. The address lives in the 2 bytes after the opcode,
+    /// which the CPU reads itself, so it is not part of the decoded instruction.
+    LoadLongAddr,
+
+    /// ```BNNN```
+    AddJumpV0(u16),
+
+    /// ```CXNN```
+    Randomize(usize, u8),
+
+    /// ```DXYN```, for any ```N``` other than 0.
+    DrawSprite(usize, usize, u8),
+
+    /// ```DXY0```
+    DrawLargeSprite(usize, usize),
+
+    /// ```EX9E```
+    SkipIfKeyPressed(usize),
+
+    /// ```EXA1```
+    SkipIfKeyNotPressed(usize),
+
+    /// ```FN01```
+    SelectPlanes(u8),
+
+    /// ```F002```
+    LoadAudioPattern,
+
+    /// ```FX07```
+    LoadTimer(usize),
+
+    /// ```FX0A```
+    WaitForKey(usize),
+
+    /// ```FX15```
+    SetTimer(usize),
+
+    /// ```FX18```
+    SetAudio(usize),
+
+    /// ```FX1E```
+    AddAddrReg(usize),
+
+    /// ```FX29```
+    SetAddrToChar(usize),
+
+    /// ```FX30```
+    SetAddrToBigChar(usize),
+
+    /// ```FX33```
+    StoreDigits(usize),
+
+    /// ```FX3A```
+    SetPitch(usize),
+
+    /// ```FX55```
+    SaveRegisters(usize),
+
+    /// ```FX65```
+    RestoreRegisters(usize),
+
+    /// ```FX75```
+    SaveFlags(usize),
+
+    /// ```FX85```
+    RestoreFlags(usize),
+}
+
+///
+/// The error returned when a raw value is not the opcode of any instruction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DecodeError {
+    pub opcode : u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown opcode {:#06X}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        "unknown opcode"
+    }
+}
+
+impl Instruction {
+
+    ///
+    /// Decodes a raw opcode value into the instruction it represents.
+    pub fn decode(op : u16) -> Result<Instruction, DecodeError> {
+        let instr = match op & 0xF000 {
+            0x0000 => match op {
+                0x00E0 => Instruction::ClearScreen,
+                0x00EE => Instruction::Return,
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::LowRes,
+                0x00FF => Instruction::HighRes,
+                _ if op & 0xFFF0 == 0x00C0 => Instruction::ScrollDown(nibble(op)),
+                _ => Instruction::MachineCall(addr(op)),
+            },
+            0x1000 => Instruction::Jump(addr(op)),
+            0x2000 => Instruction::Call(addr(op)),
+            0x3000 => Instruction::SkipIfEqualConst(acc(op), num(op)),
+            0x4000 => Instruction::SkipIfUnequalConst(acc(op), num(op)),
+            0x5000 => match op & 0x000F {
+                0x0 => Instruction::SkipIfEqualReg(acc(op), reg(op)),
+                0x2 => Instruction::SaveRegisterRange(acc(op), reg(op)),
+                0x3 => Instruction::RestoreRegisterRange(acc(op), reg(op)),
+                _ => return Err(DecodeError { opcode : op }),
+            },
+            0x6000 => Instruction::LoadConst(acc(op), num(op)),
+            0x7000 => Instruction::AddConst(acc(op), num(op)),
+            0x8000 => match op & 0x000F {
+                0x0 => Instruction::LoadRegister(acc(op), reg(op)),
+                0x1 => Instruction::OrRegister(acc(op), reg(op)),
+                0x2 => Instruction::AndRegister(acc(op), reg(op)),
+                0x3 => Instruction::XorRegister(acc(op), reg(op)),
+                0x4 => Instruction::AddRegister(acc(op), reg(op)),
+                0x5 => Instruction::SubRegister(acc(op), reg(op)),
+                0x6 => Instruction::RightShiftRegister(acc(op), reg(op)),
+                0x7 => Instruction::RevSubRegister(acc(op), reg(op)),
+                0xE => Instruction::LeftShiftRegister(acc(op), reg(op)),
+                _ => return Err(DecodeError { opcode : op }),
+            },
+            0x9000 if op & 0x000F == 0 => Instruction::SkipIfUnequalReg(acc(op), reg(op)),
+            0xA000 => Instruction::LoadAddrConst(addr(op)),
+            0xB000 => Instruction::AddJumpV0(addr(op)),
+            0xC000 => Instruction::Randomize(acc(op), num(op)),
+            0xD000 if op & 0x000F == 0 => Instruction::DrawLargeSprite(acc(op), reg(op)),
+            0xD000 => Instruction::DrawSprite(acc(op), reg(op), nibble(op)),
+            0xE000 => match op & 0x00FF {
+                0x9E => Instruction::SkipIfKeyPressed(acc(op)),
+                0xA1 => Instruction::SkipIfKeyNotPressed(acc(op)),
+                _ => return Err(DecodeError { opcode : op }),
+            },
+            0xF000 => match op & 0x00FF {
+                0x00 if op == 0xF000 => Instruction::LoadLongAddr,
+                0x01 => Instruction::SelectPlanes(acc(op) as u8),
+                0x02 if op == 0xF002 => Instruction::LoadAudioPattern,
+                0x07 => Instruction::LoadTimer(acc(op)),
+                0x0A => Instruction::WaitForKey(acc(op)),
+                0x15 => Instruction::SetTimer(acc(op)),
+                0x18 => Instruction::SetAudio(acc(op)),
+                0x1E => Instruction::AddAddrReg(acc(op)),
+                0x29 => Instruction::SetAddrToChar(acc(op)),
+                0x30 => Instruction::SetAddrToBigChar(acc(op)),
+                0x33 => Instruction::StoreDigits(acc(op)),
+                0x3A => Instruction::SetPitch(acc(op)),
+                0x55 => Instruction::SaveRegisters(acc(op)),
+                0x65 => Instruction::RestoreRegisters(acc(op)),
+                0x75 => Instruction::SaveFlags(acc(op)),
+                0x85 => Instruction::RestoreFlags(acc(op)),
+                _ => return Err(DecodeError { opcode : op }),
+            },
+            _ => return Err(DecodeError { opcode : op }),
+        };
+        Ok(instr)
+    }
+
+    ///
+    /// Encodes the instruction back into its raw opcode value, so that
+    /// ```Instruction::decode(instr.encode()) == Ok(instr)```.
+    /// Operands too large for their part of the opcode are truncated.
+    pub fn encode(&self) -> u16 {
+        match *self {
+            Instruction::MachineCall(addr) => addr & 0x0FFF,
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown(rows) => 0x00C0 | (rows as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jump(addr) => 0x1000 | (addr & 0x0FFF),
+            Instruction::Call(addr) => 0x2000 | (addr & 0x0FFF),
+            Instruction::SkipIfEqualConst(x, byte) => with_const(0x3000, x, byte),
+            Instruction::SkipIfUnequalConst(x, byte) => with_const(0x4000, x, byte),
+            Instruction::SkipIfEqualReg(x, y) => with_regs(0x5000, x, y),
+            Instruction::SaveRegisterRange(x, y) => with_regs(0x5002, x, y),
+            Instruction::RestoreRegisterRange(x, y) => with_regs(0x5003, x, y),
+            Instruction::LoadConst(x, byte) => with_const(0x6000, x, byte),
+            Instruction::AddConst(x, byte) => with_const(0x7000, x, byte),
+            Instruction::LoadRegister(x, y) => with_regs(0x8000, x, y),
+            Instruction::OrRegister(x, y) => with_regs(0x8001, x, y),
+            Instruction::AndRegister(x, y) => with_regs(0x8002, x, y),
+            Instruction::XorRegister(x, y) => with_regs(0x8003, x, y),
+            Instruction::AddRegister(x, y) => with_regs(0x8004, x, y),
+            Instruction::SubRegister(x, y) => with_regs(0x8005, x, y),
+            Instruction::RightShiftRegister(x, y) => with_regs(0x8006, x, y),
+            Instruction::RevSubRegister(x, y) => with_regs(0x8007, x, y),
+            Instruction::LeftShiftRegister(x, y) => with_regs(0x800E, x, y),
+            Instruction::SkipIfUnequalReg(x, y) => with_regs(0x9000, x, y),
+            Instruction::LoadAddrConst(addr) => 0xA000 | (addr & 0x0FFF),
+            Instruction::LoadLongAddr => 0xF000,
+            Instruction::AddJumpV0(addr) => 0xB000 | (addr & 0x0FFF),
+            Instruction::Randomize(x, mask) => with_const(0xC000, x, mask),
+            Instruction::DrawSprite(x, y, rows) => with_regs(0xD000, x, y) | (rows as u16 & 0xF),
+            Instruction::DrawLargeSprite(x, y) => with_regs(0xD000, x, y),
+            Instruction::SkipIfKeyPressed(x) => with_reg(0xE09E, x),
+            Instruction::SkipIfKeyNotPressed(x) => with_reg(0xE0A1, x),
+            Instruction::SelectPlanes(mask) => with_reg(0xF001, mask as usize),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::LoadTimer(x) => with_reg(0xF007, x),
+            Instruction::WaitForKey(x) => with_reg(0xF00A, x),
+            Instruction::SetTimer(x) => with_reg(0xF015, x),
+            Instruction::SetAudio(x) => with_reg(0xF018, x),
+            Instruction::AddAddrReg(x) => with_reg(0xF01E, x),
+            Instruction::SetAddrToChar(x) => with_reg(0xF029, x),
+            Instruction::SetAddrToBigChar(x) => with_reg(0xF030, x),
+            Instruction::StoreDigits(x) => with_reg(0xF033, x),
+            Instruction::SetPitch(x) => with_reg(0xF03A, x),
+            Instruction::SaveRegisters(x) => with_reg(0xF055, x),
+            Instruction::RestoreRegisters(x) => with_reg(0xF065, x),
+            Instruction::SaveFlags(x) => with_reg(0xF075, x),
+            Instruction::RestoreFlags(x) => with_reg(0xF085, x),
+        }
+    }
+
+    ///
+    /// The number of bytes the instruction takes up in memory; 4 for
+    /// ```F000 NNNN```, and 2 for everything else.
+    pub fn size(&self) -> usize {
+        match *self {
+            Instruction::LoadLongAddr => 4,
+            _ => 2,
+        }
+    }
+
+    ///
+    /// Checks if the instruction can skip over the one after it.
+    pub fn is_skip(&self) -> bool {
+        matches!(*self,
+            Instruction::SkipIfEqualConst(..) | Instruction::SkipIfUnequalConst(..)
+            | Instruction::SkipIfEqualReg(..) | Instruction::SkipIfUnequalReg(..)
+            | Instruction::SkipIfKeyPressed(..) | Instruction::SkipIfKeyNotPressed(..))
+    }
+}
+
+///
+/// Prints the instruction using Octo's mnemonics, so that the output can be
+/// fed back into an assembler. Skips are printed as the ```if ... then```
+/// that compiles to them, and machine code calls, which Octo has no mnemonic
+/// for, are printed as their 2 raw bytes.
+/// ```F000 NNNN``` is printed as ```i := long``` without its address, since
+/// the address is not part of the instruction.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::MachineCall(addr) => write!(f, "{:#04X} {:#04X}", addr >> 8, addr & 0xFF),
+            Instruction::ClearScreen => write!(f, "clear"),
+            Instruction::Return => write!(f, "return"),
+            Instruction::ScrollDown(rows) => write!(f, "scroll-down {}", rows),
+            Instruction::ScrollRight => write!(f, "scroll-right"),
+            Instruction::ScrollLeft => write!(f, "scroll-left"),
+            Instruction::Exit => write!(f, "exit"),
+            Instruction::LowRes => write!(f, "lores"),
+            Instruction::HighRes => write!(f, "hires"),
+            Instruction::Jump(addr) => write!(f, "jump {:#05X}", addr),
+            Instruction::Call(addr) => write!(f, ":call {:#05X}", addr),
+            Instruction::SkipIfEqualConst(x, byte) => write!(f, "if v{:x} != {:#04X} then", x, byte),
+            Instruction::SkipIfUnequalConst(x, byte) => write!(f, "if v{:x} == {:#04X} then", x, byte),
+            Instruction::SkipIfEqualReg(x, y) => write!(f, "if v{:x} != v{:x} then", x, y),
+            Instruction::SaveRegisterRange(x, y) => write!(f, "save v{:x} - v{:x}", x, y),
+            Instruction::RestoreRegisterRange(x, y) => write!(f, "load v{:x} - v{:x}", x, y),
+            Instruction::LoadConst(x, byte) => write!(f, "v{:x} := {:#04X}", x, byte),
+            Instruction::AddConst(x, byte) => write!(f, "v{:x} += {:#04X}", x, byte),
+            Instruction::LoadRegister(x, y) => write!(f, "v{:x} := v{:x}", x, y),
+            Instruction::OrRegister(x, y) => write!(f, "v{:x} |= v{:x}", x, y),
+            Instruction::AndRegister(x, y) => write!(f, "v{:x} &= v{:x}", x, y),
+            Instruction::XorRegister(x, y) => write!(f, "v{:x} ^= v{:x}", x, y),
+            Instruction::AddRegister(x, y) => write!(f, "v{:x} += v{:x}", x, y),
+            Instruction::SubRegister(x, y) => write!(f, "v{:x} -= v{:x}", x, y),
+            Instruction::RightShiftRegister(x, y) => write!(f, "v{:x} >>= v{:x}", x, y),
+            Instruction::RevSubRegister(x, y) => write!(f, "v{:x} =- v{:x}", x, y),
+            Instruction::LeftShiftRegister(x, y) => write!(f, "v{:x} <<= v{:x}", x, y),
+            Instruction::SkipIfUnequalReg(x, y) => write!(f, "if v{:x} == v{:x} then", x, y),
+            Instruction::LoadAddrConst(addr) => write!(f, "i := {:#05X}", addr),
+            Instruction::LoadLongAddr => write!(f, "i := long"),
+            Instruction::AddJumpV0(addr) => write!(f, "jump0 {:#05X}", addr),
+            Instruction::Randomize(x, mask) => write!(f, "v{:x} := random {:#04X}", x, mask),
+            Instruction::DrawSprite(x, y, rows) => write!(f, "sprite v{:x} v{:x} {}", x, y, rows),
+            Instruction::DrawLargeSprite(x, y) => write!(f, "sprite v{:x} v{:x} 0", x, y),
+            Instruction::SkipIfKeyPressed(x) => write!(f, "if v{:x} -key then", x),
+            Instruction::SkipIfKeyNotPressed(x) => write!(f, "if v{:x} key then", x),
+            Instruction::SelectPlanes(mask) => write!(f, "plane {}", mask),
+            Instruction::LoadAudioPattern => write!(f, "audio"),
+            Instruction::LoadTimer(x) => write!(f, "v{:x} := delay", x),
+            Instruction::WaitForKey(x) => write!(f, "v{:x} := key", x),
+            Instruction::SetTimer(x) => write!(f, "delay := v{:x}", x),
+            Instruction::SetAudio(x) => write!(f, "buzzer := v{:x}", x),
+            Instruction::AddAddrReg(x) => write!(f, "i += v{:x}", x),
+            Instruction::SetAddrToChar(x) => write!(f, "i := hex v{:x}", x),
+            Instruction::SetAddrToBigChar(x) => write!(f, "i := bighex v{:x}", x),
+            Instruction::StoreDigits(x) => write!(f, "bcd v{:x}", x),
+            Instruction::SetPitch(x) => write!(f, "pitch := v{:x}", x),
+            Instruction::SaveRegisters(x) => write!(f, "save v{:x}", x),
+            Instruction::RestoreRegisters(x) => write!(f, "load v{:x}", x),
+            Instruction::SaveFlags(x) => write!(f, "saveflags v{:x}", x),
+            Instruction::RestoreFlags(x) => write!(f, "loadflags v{:x}", x),
+        }
+    }
+}
+
+#[inline(always)]
+fn addr(instruction : u16) -> u16 {
+    if (instruction & 0x0FFF) % 2 == 1 {
+        debug!(target : "cpu", "Got odd jump!");
+    }
+    instruction & 0x0FFF
+}
+
+#[inline(always)]
+fn acc(instruction : u16) -> usize {
+    ((instruction & 0x0F00) >> 8) as usize
+}
+
+#[inline(always)]
+fn reg(instruction : u16) -> usize {
+    ((instruction & 0x00F0) >> 4) as usize
+}
+
+#[inline(always)]
+fn num(instruction : u16) -> u8 {
+    (instruction & 0x00FF) as u8
+}
+
+#[inline(always)]
+fn nibble(instruction : u16) -> u8 {
+    (instruction & 0x000F) as u8
+}
+
+#[inline(always)]
+fn with_reg(base : u16, x : usize) -> u16 {
+    base | ((x as u16 & 0xF) << 8)
+}
+
+#[inline(always)]
+fn with_regs(base : u16, x : usize, y : usize) -> u16 {
+    with_reg(base, x) | ((y as u16 & 0xF) << 4)
+}
+
+#[inline(always)]
+fn with_const(base : u16, x : usize, byte : u8) -> u16 {
+    with_reg(base, x) | byte as u16
+}
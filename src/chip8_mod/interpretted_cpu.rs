@@ -1,62 +1,98 @@
-use chip8_mod::cpu::{OpcodeExecuter, InstructionSet};
-use chip8_mod::display::{ScreenBuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
-use chip8_mod::audio::AudioTimer;
+use chip8_mod::cpu::{OpcodeExecuter, PROGRAM_START};
+use chip8_mod::quirks::Quirks;
+use chip8_mod::platform::{Platform, PLATFORM_NAMES};
+use chip8_mod::instruction::Instruction;
+use chip8_mod::display::{DisplayOutput, ScreenBuffer, Resolution, PLANE_COUNT};
+use chip8_mod::audio::{AudioOutput, AudioTimer, PATTERN_SIZE};
 use chip8_mod::input::InputReciever;
 use chip8_mod::default_fontset::*;
+use chip8_mod::error::Chip8Error;
+use chip8_mod::save_state::{self, SaveStateError, StateReader, StateWriter};
+use chip8_mod::random::{RandomSource, ThreadRandom};
+use chip8_mod::timers::Timers;
 
 use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
-extern crate rand;
-
-const NANO_BETWEEN_TICKS : u64 = (1000 * 1000 * 1000)/60; // Equal to 60 Hz
-
-pub struct InterpretedCpu <'a>  {
+///
+/// The Chip 8 itself, drawing to the display output ```D```, playing sound
+/// through the audio output ```A```, and reading keys from the input ```I```.
+///
+/// The CPU owns its backends, so it can be sent to another thread or cloned
+/// whenever they can. A backend can also be borrowed by passing ```&mut``` it.
+#[derive(Clone)]
+pub struct InterpretedCpu <D, A, I>  {
     pub pc : u16, 
     pub registerV : [u8 ; 16],
     pub I : u16, 
 
-    pub stack : [u16 ; 24],
+    pub stack : Vec<u16>,
     pub sp : usize, 
 
-    pub timer : u8,
-    pub ns_since_last_tick : u64,
+    ///
+    /// The delay and sound timers.
+    pub timers : Timers,
 
 
-    pub memory : [u8 ; 4096],
+    pub memory : Vec<u8>,
 
-    pub display_output : ScreenBuffer<'a>,
-    pub audio_output : AudioTimer<'a>,
-    pub keyboard_input : &'a mut (InputReciever + 'a),
+    pub rpl_flags : [u8 ; 16],
+    rpl_path : Option<PathBuf>,
+
+    pub display_output : ScreenBuffer<D>,
+    pub audio_output : AudioTimer<A>,
+    pub keyboard_input : I,
     pub dead : bool,
-    instruction_set : InstructionSet,
+
+    ///
+    /// Where ```CXNN``` gets its random numbers from.
+    pub random : Box<dyn RandomSource>,
+    platform : Platform,
+    quirks : Quirks,
+
+    ///
+    /// Whether a display refresh has happened since the last sprite was drawn;
+    /// only consulted with the ```display_wait``` quirk.
+    display_ready : bool,
 }
 
-impl <'a> InterpretedCpu <'a> {
-    pub fn new(kind: InstructionSet, disp : ScreenBuffer<'a>, audp : AudioTimer<'a>, keyb : &'a mut (InputReciever + 'a)) -> InterpretedCpu<'a> {
+impl <D : DisplayOutput, A : AudioOutput, I : InputReciever> InterpretedCpu <D, A, I> {
+    ///
+    /// Creates a CPU emulating ```platform```, following the platform's quirks.
+    pub fn new(platform : Platform, disp : ScreenBuffer<D>, audp : AudioTimer<A>, keyb : I) -> InterpretedCpu<D, A, I> {
+        let quirks = platform.quirks();
         let mut rval = InterpretedCpu {
-            pc : 0x0200,
+            pc : PROGRAM_START,
             registerV : [0 ; 16],
             I : 0,
 
-            stack : [0 ; 24],
+            stack : vec![0 ; platform.stack_depth()],
             sp : 0,
 
-            timer : 0,
-            ns_since_last_tick : 0,
+            timers : Timers::new(),
+
+            memory : vec![0 ; platform.memory_size()],
 
-            memory : [0 ; 4096],
+            rpl_flags : [0 ; 16],
+            rpl_path : None,
 
             display_output : disp, 
             audio_output : audp, 
             keyboard_input : keyb,
             dead : false,
-            instruction_set : kind,
+            random : Box::new(ThreadRandom),
+            platform,
+            quirks,
+            display_ready : true,
         };
+        rval.set_quirks(quirks);
         rval.initialize_memory();
         rval
     }
     fn initialize_memory(&mut self) {
-        self.memory = [0 ; 4096];
+        self.memory = vec![0 ; self.platform.memory_size()];
         for letter in 0x00 .. 0x10  {
             let offset = letter * 5;
             let letter_bits = get_raw_char(letter as u8);
@@ -66,46 +102,279 @@ impl <'a> InterpretedCpu <'a> {
             self.memory[offset+3] = letter_bits[3];
             self.memory[offset+4] = letter_bits[4];
         }
+        if !self.platform.has_big_font() {
+            return;
+        }
+        for letter in 0x00 .. 0x10 {
+            let offset = BIG_FONT_START + letter * 10;
+            let letter_bits = get_raw_big_char(letter as u8);
+            self.memory[offset .. offset + 10].copy_from_slice(&letter_bits);
+        }
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    ///
+    /// Changes the interpreter-specific behaviours the CPU follows.
+    pub fn set_quirks(&mut self, quirks : Quirks) {
+        self.quirks = quirks;
+        self.display_output.clip_sprites = quirks.clip_sprites;
+        self.timers.silent_short_sound = quirks.silent_short_sound;
+    }
+
+    ///
+    /// Sets the file the RPL user flags are persisted to, loading any flags
+    /// previously saved there.
+    pub fn set_rpl_file<P : AsRef<Path>>(&mut self, path : P) {
+        let path = path.as_ref().to_path_buf();
+        if let Ok(mut file) = File::open(&path) {
+            let mut saved = Vec::new();
+            match file.read_to_end(&mut saved) {
+                Ok(_) => {
+                    let count = saved.len().min(self.rpl_flags.len());
+                    self.rpl_flags[.. count].copy_from_slice(&saved[.. count]);
+                },
+                Err(e) => error!(target : "cpu", "Could not read RPL flags from {}: {}", path.display(), e),
+            }
+        }
+        self.rpl_path = Some(path);
+    }
+
+    ///
+    /// Skips over the next instruction, accounting for the 4-byte ```F000 NNNN```
+    /// on platforms that have it.
+    fn skip_next_instr(&mut self) {
+        let next_pc = self.pc as usize + 2;
+        if self.platform.supports(&Instruction::LoadLongAddr) && next_pc + 1 < self.memory.len() && self.memory[next_pc] == 0xF0 && self.memory[next_pc + 1] == 0x00 {
+            self.pc = self.pc.wrapping_add(4);
+        }
+        else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    ///
+    /// With the ```display_wait``` quirk, checks if a sprite has already been
+    /// drawn this frame; if so, rewinds the program counter so the draw is retried.
+    fn must_wait_for_display(&mut self) -> bool {
+        if !self.quirks.display_wait {
+            return false;
+        }
+        if !self.display_ready {
+            self.pc = self.pc.wrapping_sub(2);
+            return true;
+        }
+        self.display_ready = false;
+        false
+    }
+
+    ///
+    /// Snapshots the whole machine; see the ```save_state``` module for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        let platform_idx = PLATFORM_NAMES.iter().position(|name| *name == self.platform.name()).unwrap();
+        writer.write_u8(platform_idx as u8);
+        writer.write_u8(save_state::quirks_to_bits(&self.quirks));
+
+        writer.write_u16(self.pc);
+        writer.write_u16(self.I);
+        writer.write_bytes(&self.registerV);
+        writer.write_u8(self.stack.len() as u8);
+        writer.write_u8(self.sp as u8);
+        for addr in self.stack.iter() {
+            writer.write_u16(*addr);
+        }
+
+        writer.write_u8(self.timers.delay());
+        writer.write_u8(self.timers.sound());
+        writer.write_u64(self.timers.ns_since_last_tick);
+        writer.write_u8((self.dead as u8) | (self.display_ready as u8) << 1);
+        writer.write_bytes(&self.rpl_flags);
+
+        writer.write_bytes(&self.audio_output.pattern);
+        writer.write_u8(self.audio_output.pitch);
+
+        writer.write_u8(match self.display_output.resolution {
+            Resolution::Low => 0,
+            Resolution::High => 1,
+        });
+        writer.write_u8(self.display_output.selected_planes);
+        writer.write_u8(self.display_output.planes.len() as u8);
+        for plane in self.display_output.planes.iter() {
+            writer.write_block(plane);
+        }
+        writer.write_block(&self.memory);
+        writer.write_block(&self.random.state());
+        writer.data
+    }
+
+    ///
+    /// Restores a snapshot taken by ```save_state```, including the platform and
+    /// quirks it was taken with. If the snapshot can't be loaded the CPU is left untouched.
+    pub fn load_state(&mut self, data : &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(data)?;
+        let platform = PLATFORM_NAMES.get(reader.read_u8()? as usize)
+            .and_then(|name| Platform::from_name(name))
+            .ok_or(SaveStateError::BadValue("platform"))?;
+        let quirks = save_state::quirks_from_bits(reader.read_u8()?);
+
+        let pc = reader.read_u16()?;
+        let addr_reg = reader.read_u16()?;
+        let mut registers = [0 ; 16];
+        registers.copy_from_slice(reader.read_bytes(16)?);
+        let stack_depth = reader.read_u8()? as usize;
+        if stack_depth != platform.stack_depth() {
+            return Err(SaveStateError::BadValue("stack depth"));
+        }
+        let sp = reader.read_u8()? as usize;
+        if sp > stack_depth {
+            return Err(SaveStateError::BadValue("stack pointer"));
+        }
+        let mut stack = Vec::with_capacity(stack_depth);
+        for _ in 0 .. stack_depth {
+            stack.push(reader.read_u16()?);
+        }
+
+        let timer = reader.read_u8()?;
+        let sound_timer = reader.read_u8()?;
+        let ns_since_last_tick = reader.read_u64()?;
+        let status = reader.read_u8()?;
+        let mut rpl_flags = [0 ; 16];
+        rpl_flags.copy_from_slice(reader.read_bytes(16)?);
+
+        let mut pattern = [0 ; PATTERN_SIZE];
+        pattern.copy_from_slice(reader.read_bytes(PATTERN_SIZE)?);
+        let pitch = reader.read_u8()?;
+
+        let resolution = match reader.read_u8()? {
+            0 => Resolution::Low,
+            1 => Resolution::High,
+            _ => return Err(SaveStateError::BadValue("resolution")),
+        };
+        let selected_planes = reader.read_u8()?;
+        if reader.read_u8()? as usize != PLANE_COUNT {
+            return Err(SaveStateError::BadValue("plane count"));
+        }
+        let mut planes = [Vec::new(), Vec::new()];
+        for plane in planes.iter_mut() {
+            let pixels = reader.read_block()?;
+            if pixels.len() != resolution.buffer_size() {
+                return Err(SaveStateError::BadValue("plane size"));
+            }
+            *plane = pixels.to_vec();
+        }
+        let memory = reader.read_block()?;
+        if memory.len() != platform.memory_size() {
+            return Err(SaveStateError::BadValue("memory size"));
+        }
+        let mut random = self.random.clone_box();
+        if !random.restore(reader.read_block()?) {
+            return Err(SaveStateError::BadValue("random state"));
+        }
+
+        self.platform = platform;
+        self.set_quirks(quirks);
+        self.pc = pc;
+        self.I = addr_reg;
+        self.registerV = registers;
+        self.stack = stack;
+        self.sp = sp;
+        self.timers.restore(timer, sound_timer, ns_since_last_tick);
+        self.dead = status & 1 != 0;
+        self.display_ready = status & (1 << 1) != 0;
+        self.rpl_flags = rpl_flags;
+        self.memory = memory.to_vec();
+        self.random = random;
+
+        self.audio_output.set_pattern(&pattern);
+        self.audio_output.set_pitch(pitch);
+
+        self.display_output.resolution = resolution;
+        self.display_output.selected_planes = selected_planes;
+        self.display_output.planes = planes;
+        self.display_output.draw();
+        Ok(())
+    }
+
+    ///
+    /// Checks that the ```len``` bytes of memory starting at ```start``` exist.
+    fn check_memory(&self, start : usize, len : usize) -> Result<(), Chip8Error> {
+        if start + len > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds { addr : self.pc, access : start, len });
+        }
+        Ok(())
+    }
+
+    fn persist_rpl_flags(&self) {
+        if let Some(ref path) = self.rpl_path {
+            let res = File::create(path).and_then(|mut file| file.write_all(&self.rpl_flags));
+            if let Err(e) = res {
+                error!(target : "cpu", "Could not save RPL flags to {}: {}", path.display(), e);
+            }
+        }
     }
 }
 
-impl <'a> OpcodeExecuter for InterpretedCpu <'a> {
+impl <D : DisplayOutput, A : AudioOutput, I : InputReciever> OpcodeExecuter for InterpretedCpu <D, A, I> {
 
-    fn load_rom(&mut self, rom : &[u8]) {
+    fn load_rom(&mut self, rom : &[u8]) -> Result<(), Chip8Error> {
+        let max = self.memory.len() - PROGRAM_START as usize;
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge { size : rom.len(), max });
+        }
         for(idx, byte) in rom.into_iter().enumerate() {
-            debug_log!("PUTTING BYTE {:#X} INTO SLOT {:#X}", byte, 0x200 + idx);
-            self.memory[0x200 + idx] = *byte;
+            let slot = PROGRAM_START as usize + idx;
+            trace!(target : "cpu", "PUTTING BYTE {:#X} INTO SLOT {:#X}", byte, slot);
+            self.memory[slot] = *byte;
         }
+        Ok(())
     }
 
     fn tick(&mut self, ns_since_last_frame : u64) {
-        self.ns_since_last_tick += ns_since_last_frame;
-        debug_log!("NS SINCE LAST: {}", self.ns_since_last_tick);
-        if self.ns_since_last_tick >= NANO_BETWEEN_TICKS {
-            debug_log!("TICKING: {} > {}", self.ns_since_last_tick, NANO_BETWEEN_TICKS);
-            if self.timer > 0 {
-                self.timer -= 1;
-            }
-            self.audio_output.tick();
-            self.ns_since_last_tick -= NANO_BETWEEN_TICKS;
+        for _ in 0 .. self.timers.advance(ns_since_last_frame) {
+            self.tick_timers();
         }
     }
 
+    fn tick_timers(&mut self) {
+        self.timers.tick_once();
+        self.display_ready = true;
+    }
+
     fn reset(&mut self) {
-        self.pc = 0x0200;
+        self.pc = PROGRAM_START;
         self.registerV = [0 ; 16];
         self.I = 0;
-        self.stack = [0 ; 24];
+        self.stack = vec![0 ; self.platform.stack_depth()];
         self.sp = 0;
-        self.timer = 0;
-        self.ns_since_last_tick = 0;
-        self.memory = [0 ; 4096];
+        self.timers.reset();
         self.dead = false;
+        self.display_ready = true;
         self.initialize_memory();
+        self.display_output.select_planes(1);
+        self.display_output.set_resolution(Resolution::Low);
+    }
+
+    fn get_next_instr(&self) -> Result<u16, Chip8Error> {
+        let pc = self.pc as usize;
+        if pc + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds { addr : self.pc });
+        }
+        Ok((self.memory[pc] as u16) << 8 | self.memory[pc + 1] as u16)
+    }
+
+    fn program_counter(&self) -> u16 {
+        self.pc
     }
 
-    fn get_next_instr(&self) -> u16 {
-        (self.memory[self.pc as usize] as u16) << 8 | self.memory[self.pc as usize + 1] as u16
+    fn is_instruction_supported(&self, instr : &Instruction) -> bool {
+        self.platform.supports(instr)
     }
 
     fn has_died(&self) -> bool {
@@ -117,209 +386,332 @@ impl <'a> OpcodeExecuter for InterpretedCpu <'a> {
     }
 
     fn end_frame(&mut self) {
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     } 
     
-    fn clear_screen(&mut self) {
-        self.display_output.clear_screen()
+    fn clear_screen(&mut self) -> Result<(), Chip8Error> {
+        self.display_output.clear_screen();
+        Ok(())
     }
 
-    fn ret(&mut self) {
+    fn scroll_down(&mut self, rows : u8) -> Result<(), Chip8Error> {
+        self.display_output.scroll_down(rows as usize);
+        Ok(())
+    }
+    fn scroll_right(&mut self) -> Result<(), Chip8Error> {
+        self.display_output.scroll_right();
+        Ok(())
+    }
+    fn scroll_left(&mut self) -> Result<(), Chip8Error> {
+        self.display_output.scroll_left();
+        Ok(())
+    }
+    fn low_res(&mut self) -> Result<(), Chip8Error> {
+        self.display_output.set_resolution(Resolution::Low);
+        Ok(())
+    }
+    fn high_res(&mut self) -> Result<(), Chip8Error> {
+        self.display_output.set_resolution(Resolution::High);
+        Ok(())
+    }
+    fn select_planes(&mut self, mask : u8) -> Result<(), Chip8Error> {
+        self.display_output.select_planes(mask);
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<(), Chip8Error> {
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow { addr : self.pc });
+        }
         self.sp -= 1;
         self.pc = self.stack[self.sp];
+        Ok(())
     }
-    fn jump(&mut self, addr : u16) {
-        self.pc = addr - 2; //To counter the next progression
+    fn jump(&mut self, addr : u16) -> Result<(), Chip8Error> {
+        self.pc = addr.wrapping_sub(2); //To counter the next progression
+        Ok(())
     }
-    fn call(&mut self, addr : u16) {
+    fn call(&mut self, addr : u16) -> Result<(), Chip8Error> {
+        if self.sp >= self.stack.len() {
+            return Err(Chip8Error::StackOverflow { addr : self.pc });
+        }
         self.stack[self.sp] = self.pc;
         self.sp += 1;
-        self.pc = addr - 2; //To counter the next progression
+        self.jump(addr)
     }
 
-    fn skip_if_equal_const(&mut self, register : usize, byte : u8) {
+    fn skip_if_equal_const(&mut self, register : usize, byte : u8) -> Result<(), Chip8Error> {
         if self.registerV[register] == byte {
-            self.pc += 2;
+            self.skip_next_instr();
         }
+        Ok(())
     }
-    fn skip_if_unequal_const(&mut self, register : usize, byte : u8) {
+    fn skip_if_unequal_const(&mut self, register : usize, byte : u8) -> Result<(), Chip8Error> {
         if self.registerV[register] != byte {
-            self.pc += 2;
+            self.skip_next_instr();
         }
+        Ok(())
     }
-    fn skip_if_equal_reg(&mut self, register1 : usize, register2 : usize) {
+    fn skip_if_equal_reg(&mut self, register1 : usize, register2 : usize) -> Result<(), Chip8Error> {
         if self.registerV[register1] == self.registerV[register2] {
-            self.pc += 2;
+            self.skip_next_instr();
         }
+        Ok(())
     }
 
-    fn load_const(&mut self, register : usize, byte : u8) {
+    fn load_const(&mut self, register : usize, byte : u8) -> Result<(), Chip8Error> {
         self.registerV[register] = byte;
+        Ok(())
     }
 
-    fn add_const(&mut self, register : usize, byte : u8) {
-        self.registerV[register] += byte;
+    fn add_const(&mut self, register : usize, byte : u8) -> Result<(), Chip8Error> {
+        self.registerV[register] = self.registerV[register].wrapping_add(byte);
+        Ok(())
     } 
 
-    fn load_register(&mut self, acc : usize, reg : usize) {
+    fn load_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> {
         self.registerV[acc] = self.registerV[reg];
+        Ok(())
     } 
-    fn or_register(&mut self, acc : usize, reg : usize) {
+    fn or_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> {
         self.registerV[acc] |= self.registerV[reg];
+        if self.quirks.vf_reset {
+            self.registerV[0xF] = 0;
+        }
+        Ok(())
     }
-    fn and_register(&mut self, acc : usize, reg : usize) {
+    fn and_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> {
         self.registerV[acc] &= self.registerV[reg];
+        if self.quirks.vf_reset {
+            self.registerV[0xF] = 0;
+        }
+        Ok(())
     }
-    fn xor_register(&mut self, acc : usize, reg : usize) {
+    fn xor_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> {
         self.registerV[acc] ^= self.registerV[reg];
+        if self.quirks.vf_reset {
+            self.registerV[0xF] = 0;
+        }
+        Ok(())
     }
-    fn add_register(&mut self, acc : usize, reg : usize) {
+    fn add_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> {
         let (value, overflowed) = self.registerV[acc].overflowing_add(self.registerV[reg]);
         self.registerV[0xF] = if overflowed { 1 } else { 0 };
         self.registerV[acc] = value;
+        Ok(())
     }
-    fn sub_register(&mut self, acc : usize, reg : usize) {
-        let a = self.registerV[acc];
-        let b = self.registerV[reg];
-        if(a < b) {
-            self.registerV[0xF] = 0;
-            self.registerV[acc] =  0xFF - (b - a);
-        }
-        else {
-            self.registerV[0xF] = 1;
-            self.registerV[acc] = a - b;
-        }
+    fn sub_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> {
+        let (value, underflowed) = self.registerV[acc].overflowing_sub(self.registerV[reg]);
+        self.registerV[0xF] = if underflowed { 0 } else { 1 };
+        self.registerV[acc] = value;
+        Ok(())
     }
-    fn right_shift_register(&mut self, acc : usize, reg : usize) {
-        match self.instruction_set {
-            InstructionSet::COWGOD => {
-                self.registerV[0xF] = self.registerV[acc] & 1;
-                self.registerV[acc] = self.registerV[acc] >> 1;
-            }
-            InstructionSet::LEGACY => {
-                self.registerV[0xF] = self.registerV[reg] & 1;
-                self.registerV[reg] >>= 1;
-                self.registerV[acc] = self.registerV[reg];
-            }
-        }
+    fn right_shift_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> {
+        let source = if self.quirks.shift_uses_vy { self.registerV[reg] } else { self.registerV[acc] };
+        self.registerV[acc] = source >> 1;
+        self.registerV[0xF] = source & 1;
+        Ok(())
     }
-    fn rev_sub_register(&mut self, acc : usize, reg : usize) {
-        let a = self.registerV[acc];
-        let b = self.registerV[reg];
-        if(b < a) {
-            self.registerV[0xF] = 0;
-            self.registerV[acc] =  0xFF - (a - b);
-        }
-        else {
-            self.registerV[0xF] = 1;
-            self.registerV[acc] = b - a;
-        }
+    fn rev_sub_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> {
+        let (value, underflowed) = self.registerV[reg].overflowing_sub(self.registerV[acc]);
+        self.registerV[0xF] = if underflowed { 0 } else { 1 };
+        self.registerV[acc] = value;
+        Ok(())
     }
-    fn left_shift_register(&mut self, acc : usize, reg : usize) { 
-        match self.instruction_set {
-            InstructionSet::COWGOD => {
-                self.registerV[0xF] = self.registerV[acc] >> 7;
-                self.registerV[acc] = self.registerV[acc] << 1;
-            }
-            InstructionSet::LEGACY => {
-                self.registerV[0xF] = self.registerV[reg] >> 7;
-                self.registerV[reg] <<= 1;
-                self.registerV[acc] = self.registerV[reg];
-            }
-        }
+    fn left_shift_register(&mut self, acc : usize, reg : usize) -> Result<(), Chip8Error> { 
+        let source = if self.quirks.shift_uses_vy { self.registerV[reg] } else { self.registerV[acc] };
+        self.registerV[acc] = source << 1;
+        self.registerV[0xF] = source >> 7;
+        Ok(())
     }
-    fn skip_if_unequal_reg(&mut self, register1 : usize, register2 : usize) {
+    fn skip_if_unequal_reg(&mut self, register1 : usize, register2 : usize) -> Result<(), Chip8Error> {
         if self.registerV[register1] != self.registerV[register2] {
-            self.pc += 2;
+            self.skip_next_instr();
         }
+        Ok(())
     }
 
-    fn load_addr_const(&mut self, addr : u16) {
+    fn load_addr_const(&mut self, addr : u16) -> Result<(), Chip8Error> {
         self.I = addr;
+        Ok(())
     }
-    fn add_jump_v0(&mut self, addr_offset : u16) { 
-        let next_adder = self.registerV[0x00] as u16 + addr_offset;
-        self.jump(next_adder);
+    fn add_jump_v0(&mut self, addr_offset : u16) -> Result<(), Chip8Error> { 
+        let offset_reg = if self.quirks.jump_uses_vx { (addr_offset >> 8) as usize } else { 0x00 };
+        let next_adder = self.registerV[offset_reg] as u16 + addr_offset;
+        self.jump(next_adder)
     }
-    fn randomize(&mut self, reg : usize, mask : u8) { 
-        self.registerV[reg] = rand::random::<u8>() & mask;
+    fn randomize(&mut self, reg : usize, mask : u8) -> Result<(), Chip8Error> { 
+        self.registerV[reg] = self.random.next_byte() & mask;
+        Ok(())
     }
-    fn draw_sprite(&mut self, xreg : usize, yreg : usize, length : u8) {
+    fn draw_sprite(&mut self, xreg : usize, yreg : usize, length : u8) -> Result<(), Chip8Error> {
         let x = self.registerV[xreg];
         let y = self.registerV[yreg];
-        let sprite = &self.memory[self.I as usize .. (self.I + length as u16) as usize];
-        debug_log!("CPU Draw sprite using {} => {}, {} => {}, length {}.", xreg, x, yreg, y, length);
-        if x + 8 > SCREEN_WIDTH as u8 || y + length > SCREEN_HEIGHT as u8 {
-            error_log!("Bad draw dims: ({} -> {}) by ({} -> {}).\nCPU: {}", x, x+8, y, y + length, self);
+        let sprite_len = length as usize * self.display_output.selected_plane_count().max(1);
+        self.check_memory(self.I as usize, sprite_len)?;
+        if self.must_wait_for_display() {
+            return Ok(());
+        }
+        let sprite = &self.memory[self.I as usize .. self.I as usize + sprite_len];
+        debug!(target : "display", "CPU Draw sprite using {} => {}, {} => {}, length {}.", xreg, x, yreg, y, length);
+        if x as usize + 8 > self.display_output.width() || y as usize + length as usize > self.display_output.height() {
+            error!(target : "display", "Bad draw dims: ({} -> {}) by ({} -> {}).\nCPU: {}", x, x as usize + 8, y, y as usize + length as usize, self);
         }
         self.registerV[0xF] = if self.display_output.put_sprite(x, y, &sprite) { 1 } else { 0 };
+        Ok(())
     }
-    fn skip_if_key_pressed(&mut self, reg : usize) {
+    fn draw_large_sprite(&mut self, xreg : usize, yreg : usize) -> Result<(), Chip8Error> {
+        let x = self.registerV[xreg];
+        let y = self.registerV[yreg];
+        let sprite_len = 32 * self.display_output.selected_plane_count().max(1);
+        self.check_memory(self.I as usize, sprite_len)?;
+        if self.must_wait_for_display() {
+            return Ok(());
+        }
+        let sprite = &self.memory[self.I as usize .. self.I as usize + sprite_len];
+        debug!(target : "display", "CPU Draw large sprite using {} => {}, {} => {}.", xreg, x, yreg, y);
+        self.registerV[0xF] = if self.display_output.put_large_sprite(x, y, sprite) { 1 } else { 0 };
+        Ok(())
+    }
+    fn skip_if_key_pressed(&mut self, reg : usize) -> Result<(), Chip8Error> {
         let key = self.registerV[reg];
-        if self.keyboard_input.check_key(key) {
-            self.pc += 2;
+        if self.keyboard_input.check_key(key & 0x0F) {
+            self.skip_next_instr();
         }
+        Ok(())
     }
-    fn skip_if_key_not_pressed(&mut self, reg : usize) {
+    fn skip_if_key_not_pressed(&mut self, reg : usize) -> Result<(), Chip8Error> {
         let key = self.registerV[reg];
-        if !self.keyboard_input.check_key(key) {
-            self.pc += 2;
+        if !self.keyboard_input.check_key(key & 0x0F) {
+            self.skip_next_instr();
         }
+        Ok(())
     }
 
-    fn load_timer(&mut self, reg : usize) { 
-        self.registerV[reg] = self.timer;
+    fn load_timer(&mut self, reg : usize) -> Result<(), Chip8Error> { 
+        self.registerV[reg] = self.timers.delay();
+        Ok(())
     }
-    fn wait_for_key(&mut self, reg : usize) {
+    fn wait_for_key(&mut self, reg : usize) -> Result<(), Chip8Error> {
         match self.keyboard_input.check_any_key() {
             Some(key) => self.registerV[reg] = key,
-            None => self.pc -= 1
+            None => self.pc = self.pc.wrapping_sub(2) // Run this instruction again next cycle
         }
-    }
-    fn set_timer (&mut self, reg : usize) {
-        self.timer = self.registerV[reg];
-    }
-    fn set_audio(&mut self, reg : usize) {
-        self.audio_output.set_timer(self.registerV[reg]);
-    }
-    fn add_addr_reg(&mut self, reg : usize) { 
-        self.I += self.registerV[reg] as u16;
-    }
-    fn set_addr_to_char(&mut self, reg : usize) { 
-        self.I = (self.registerV[reg] * 5) as u16;
+        Ok(())
+    }
+    fn set_timer (&mut self, reg : usize) -> Result<(), Chip8Error> {
+        self.timers.set_delay(self.registerV[reg]);
+        Ok(())
+    }
+    fn set_audio(&mut self, reg : usize) -> Result<(), Chip8Error> {
+        let value = self.registerV[reg];
+        self.timers.set_sound(value);
+        Ok(())
+    }
+    fn load_audio_pattern(&mut self) -> Result<(), Chip8Error> {
+        let mut pattern = [0 ; PATTERN_SIZE];
+        let addr = self.I as usize;
+        self.check_memory(addr, PATTERN_SIZE)?;
+        pattern.copy_from_slice(&self.memory[addr .. addr + PATTERN_SIZE]);
+        self.audio_output.set_pattern(&pattern);
+        Ok(())
+    }
+    fn set_pitch(&mut self, reg : usize) -> Result<(), Chip8Error> {
+        self.audio_output.set_pitch(self.registerV[reg]);
+        Ok(())
+    }
+    fn add_addr_reg(&mut self, reg : usize) -> Result<(), Chip8Error> { 
+        self.I = self.I.wrapping_add(self.registerV[reg] as u16);
+        Ok(())
+    }
+    fn set_addr_to_char(&mut self, reg : usize) -> Result<(), Chip8Error> { 
+        self.I = (FONT_START + (self.registerV[reg] & 0x0F) as usize * 5) as u16;
+        Ok(())
      }
-    fn store_digits(&mut self, reg : usize) {
+    fn set_addr_to_big_char(&mut self, reg : usize) -> Result<(), Chip8Error> {
+        self.I = (BIG_FONT_START + (self.registerV[reg] & 0x0F) as usize * 10) as u16;
+        Ok(())
+    }
+    fn store_digits(&mut self, reg : usize) -> Result<(), Chip8Error> {
+        self.check_memory(self.I as usize, 3)?;
         self.memory[self.I as usize] = self.registerV[reg] / 100;
         self.memory[self.I as usize + 1] = (self.registerV[reg] / 10) % 10;
         self.memory[self.I as usize + 2] = self.registerV[reg] % 10;
+        Ok(())
     }
-    fn save_registers(&mut self, reg : usize) {
+    fn save_registers(&mut self, reg : usize) -> Result<(), Chip8Error> {
+        self.check_memory(self.I as usize, reg + 1)?;
         for regnum in 0 .. reg + 1 {
             self.memory[self.I as usize + regnum] = self.registerV[regnum];
         }
-        match self.instruction_set {
-            InstructionSet::LEGACY => {
-                self.I += (reg as u16 + 1);
-            }, 
-            _ => ()
+        if self.quirks.increment_i_on_load_store {
+            self.I = self.I.wrapping_add(reg as u16 + 1);
         }
+        Ok(())
     }
-    fn restore_registers(&mut self, reg : usize) {
+    fn restore_registers(&mut self, reg : usize) -> Result<(), Chip8Error> {
+        self.check_memory(self.I as usize, reg + 1)?;
         for regnum in 0 .. reg + 1 {
             self.registerV[regnum] = self.memory[self.I as usize + regnum];
         }
-        match self.instruction_set {
-            InstructionSet::LEGACY => {
-                self.I += (reg as u16 + 1);
-            }, 
-            _ => ()
+        if self.quirks.increment_i_on_load_store {
+            self.I = self.I.wrapping_add(reg as u16 + 1);
+        }
+        Ok(())
+    }
+    fn save_register_range(&mut self, start : usize, end : usize) -> Result<(), Chip8Error> {
+        let addr = self.I as usize;
+        self.check_memory(addr, register_range(start, end).count())?;
+        for (offset, regnum) in register_range(start, end).enumerate() {
+            self.memory[addr + offset] = self.registerV[regnum];
+        }
+        Ok(())
+    }
+    fn restore_register_range(&mut self, start : usize, end : usize) -> Result<(), Chip8Error> {
+        let addr = self.I as usize;
+        self.check_memory(addr, register_range(start, end).count())?;
+        for (offset, regnum) in register_range(start, end).enumerate() {
+            self.registerV[regnum] = self.memory[addr + offset];
         }
+        Ok(())
+    }
+    fn load_long_addr(&mut self) -> Result<(), Chip8Error> {
+        let operand_pc = self.pc as usize + 2;
+        self.check_memory(operand_pc, 2)?;
+        self.I = (self.memory[operand_pc] as u16) << 8 | self.memory[operand_pc + 1] as u16;
+        self.pc = self.pc.wrapping_add(2); // Step over the operand
+        Ok(())
+    }
+    fn save_flags(&mut self, reg : usize) -> Result<(), Chip8Error> {
+        self.rpl_flags[.. reg + 1].copy_from_slice(&self.registerV[.. reg + 1]);
+        self.persist_rpl_flags();
+        Ok(())
+    }
+    fn restore_flags(&mut self, reg : usize) -> Result<(), Chip8Error> {
+        self.registerV[.. reg + 1].copy_from_slice(&self.rpl_flags[.. reg + 1]);
+        Ok(())
+    }
+    fn exit(&mut self) -> Result<(), Chip8Error> {
+        info!(target : "cpu", "Got exit instruction.");
+        self.die();
+        Ok(())
     }
 
 }
 
-impl <'a> fmt::Display for InterpretedCpu<'a> {
+///
+/// The registers from ```start``` to ```end```, inclusive, in either direction.
+fn register_range(start : usize, end : usize) -> Box<dyn Iterator<Item = usize>> {
+    if start <= end {
+        Box::new(start ..= end)
+    }
+    else {
+        Box::new((end ..= start).rev())
+    }
+}
+
+impl <D, A, I> fmt::Display for InterpretedCpu<D, A, I> {
     fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result { 
-        write!(f, "{{ pc: {:#X}, I : {:#X}, reg: {:?}, timer : {}, offset : {} }}", self.pc, self.I, self.registerV, self.timer, self.ns_since_last_tick)
+        write!(f, "{{ pc: {:#X}, I : {:#X}, reg: {:?}, timer : {}, offset : {} }}", self.pc, self.I, self.registerV, self.timers.delay(), self.timers.ns_since_last_tick)
     }
 }
\ No newline at end of file
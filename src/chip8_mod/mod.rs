@@ -1,8 +1,18 @@
 pub mod display;
 pub mod cpu;
+pub mod instruction;
 pub mod audio;
 pub mod input;
 pub mod default_fontset;
+pub mod quirks;
+pub mod platform;
+pub mod error;
+pub mod save_state;
+pub mod rewind;
+pub mod scheduler;
+pub mod clock;
+pub mod random;
+pub mod timers;
 
 mod interpretted_cpu;
 pub use self::interpretted_cpu::InterpretedCpu;
@@ -11,71 +21,71 @@ pub use self::interpretted_cpu::InterpretedCpu;
 mod tests {
     use super::*;
     use super::cpu::*;
-    use std::time::SystemTime;
+    use super::clock::{Clock, VirtualClock};
+
+    ///
+    /// The time each instruction takes in the tests, as if run at 600 Hz.
+    const NANO_PER_INSTRUCTION : u64 = (1000 * 1000 * 1000) / 600;
 
     
 
+    #[derive(Clone)]
     struct TestDisplay { 
-        screen : [[bool ; display::SCREEN_WIDTH ] ; display::SCREEN_HEIGHT],
-
+        screen : Vec<Vec<u8>>,
+        resolution : display::Resolution,
     }
 
     impl TestDisplay {
         fn new() -> TestDisplay {
             TestDisplay {
-                screen : [[false ; display::SCREEN_WIDTH] ; display::SCREEN_HEIGHT]
+                screen : vec![vec![0 ; display::SCREEN_WIDTH] ; display::SCREEN_HEIGHT],
+                resolution : display::Resolution::Low,
             }
         }
-
-        fn print_screen (&mut self) {
-            print!("\n\n");
-            for row in 0 .. display::SCREEN_HEIGHT {
-                for col in 0 .. display::SCREEN_WIDTH {
-                    let sym = if self.screen[row][col] { '1' } else { '0' };
-                    print!("{}", sym);
-                }
-                print!("\n");
-            }
-            print!("\n\n");
-        }
     }
 
     impl display::DisplayOutput for TestDisplay {
-        fn display_buffer (&mut self, buffer : &[u8 ; display::SCREEN_WIDTH/8 * display::SCREEN_HEIGHT]) {
-            for row in 0 .. display::SCREEN_HEIGHT {
-                for col in 0 .. display::SCREEN_WIDTH/8 {
-                    let pos = row * display::SCREEN_WIDTH/8 + col;
-                    let packed = buffer[pos];
-                    for bitnum in 0 .. 8 {
-                        let extracted_bit = 0 != (1 << (7 - bitnum)) & packed;
-                        self.screen[row][col * 8 + bitnum] = extracted_bit;
-                    }
-                }
-            }
-
-            self.print_screen();
+        fn display_buffer (&mut self, pixels : &[u8], resolution : display::Resolution, _palette : &display::Palette) {
+            assert_eq!(pixels.len(), resolution.width() * resolution.height());
+            self.resolution = resolution;
+            self.screen = pixels.chunks(resolution.width()).map(|row| row.to_vec()).collect();
         }
     }
 
+    #[derive(Clone)]
     struct TestAudio {
-        play_count : usize
+        play_count : usize,
+        pattern : [u8 ; audio::PATTERN_SIZE],
+        pitch : u8,
     }
 
     impl TestAudio {
         fn new() -> TestAudio {
             TestAudio {
                 play_count : 0,
+                pattern : audio::DEFAULT_PATTERN,
+                pitch : audio::DEFAULT_PITCH,
             }
         }
     }
 
     impl audio::AudioOutput for TestAudio {
         fn output_audio(&mut self) {
-            println!("\nAUDIO\n");
             self.play_count += 1;
         }
+
+        fn stop_audio(&mut self) { }
+
+        fn set_pattern(&mut self, pattern : &[u8 ; audio::PATTERN_SIZE]) {
+            self.pattern = *pattern;
+        }
+
+        fn set_pitch(&mut self, pitch : u8) {
+            self.pitch = pitch;
+        }
     }
 
+    #[derive(Clone)]
     struct TestInput { }
 
 
@@ -91,15 +101,28 @@ mod tests {
         fn check_should_die(&mut self) -> bool { false }
     }
 
+    ///
+    /// Creates a CPU emulating ```platform``` on the test backends, with ```rom``` loaded.
+    fn test_cpu(platform : platform::Platform, rom : &[u8]) -> InterpretedCpu<TestDisplay, TestAudio, TestInput> {
+        let mut cpu = InterpretedCpu::new(platform, display::ScreenBuffer::new(TestDisplay::new()), audio::AudioTimer::new(TestAudio::new()), TestInput { });
+        cpu.load_rom(rom).unwrap();
+        cpu
+    }
+
+    ///
+    /// Starts and stops the test audio as the sound timer says, like the frontends do.
+    fn play_timer_events(cpu : &mut InterpretedCpu<TestDisplay, TestAudio, TestInput>) {
+        for event in cpu.timers.take_events() {
+            cpu.audio_output.handle_event(event);
+        }
+    }
+
     #[test]
     fn test_screen_buffer() {
-        println!("Starting basic print test.");
         let mut display = TestDisplay::new();
         let mut testbuffer = display::ScreenBuffer::new(&mut display);
 
-        println!("Clearing screen.");
         testbuffer.clear_screen();
-        println!("Putting sprite.");
         testbuffer.put_sprite(0, 0, &default_fontset::RAW_7);
 
         for row in 0 .. 5 {
@@ -108,16 +131,14 @@ mod tests {
                 let mask = 1 << (7 - bitnum);
                 let expected = 0 != packed_pixels & mask;
 
-                let buffer_packed_pixels = testbuffer.packed_pixels[(row * display::SCREEN_WIDTH/8) as usize];
+                let buffer_packed_pixels = testbuffer.planes[0][row * display::SCREEN_WIDTH/8];
                 let actual = 0 != buffer_packed_pixels & mask;
 
                 assert_eq!(expected, actual, "Failed at row {} bit number {} ; expected packed is {}, actual is {}. ", row, bitnum, packed_pixels, buffer_packed_pixels);
             }
         }
         
-        println!("Clearing screen.");
         testbuffer.clear_screen();
-        println!("Putting sprite.");
         testbuffer.put_sprite(1, 1, &default_fontset::RAW_7);
         for row in 0 .. 5 {
             let packed_pixels = default_fontset::RAW_7[row];
@@ -125,7 +146,7 @@ mod tests {
                 let mask = 1 << (7 - bitnum);
                 let expected = 0 != packed_pixels & mask;
 
-                let buffer_packed_pixels = testbuffer.packed_pixels[((row + 1) * display::SCREEN_WIDTH/8) as usize];
+                let buffer_packed_pixels = testbuffer.planes[0][(row + 1) * display::SCREEN_WIDTH/8];
                 let actual = 0 != buffer_packed_pixels & (mask >> 1);
 
                 assert_eq!(expected, actual, "Failed at row {} bit number {} ; expected packed is {}, actual is {}. ", row, bitnum, packed_pixels, buffer_packed_pixels);
@@ -134,37 +155,31 @@ mod tests {
 
         let collided = testbuffer.put_sprite(1, 1, &default_fontset::RAW_7);
         assert!(collided, "Did not detect collision!");
-        assert!(!testbuffer.packed_pixels.iter().any(|pixel| *pixel != 0), "Did not collide correctly!");
+        assert!(!testbuffer.planes[0].iter().any(|pixel| *pixel != 0), "Did not collide correctly!");
     }
 
     #[test]
     fn test_cpu_jumping() {
-        let mut display = TestDisplay::new();
-        let mut audio = TestAudio::new();
-        let mut inp = TestInput { };
-        
-        let testvbuffer = display::ScreenBuffer::new(&mut display);
-        let testabuffer = audio::AudioTimer::new(&mut audio);
-        let mut test_cpu = InterpretedCpu::new(testvbuffer, testabuffer, &mut inp);
-
-        let test_simple_jump = [
-            0x12, 0x08, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-        ];
-        test_cpu.load_rom(&test_simple_jump);
-
-        let mut prevtime = SystemTime::now();
-        while !test_cpu.has_died() && test_cpu.pc < 4094 {
-            let next_instr = test_cpu.get_next_instr();
-            test_cpu.process_instruction(next_instr);
-            let curtime = SystemTime::now();
-            let nsecs = curtime.duration_since(prevtime).unwrap().subsec_nanos();
-            test_cpu.tick(nsecs as u64);
+        let test_simple_jump = chip8_asm! {
+                jp third;
+                add v0, 1;
+                add v0, 1;
+                add v0, 1;
+            third:
+                add v0, 1;
+                add v0, 1;
+                add v0, 1
+        };
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &test_simple_jump);
+
+        let mut clock = VirtualClock::new();
+        let mut prevtime = clock.now();
+        while !test_cpu.has_died() && (test_cpu.pc as usize) < test_cpu.memory.len() - 2 {
+            let next_instr = test_cpu.get_next_instr().unwrap();
+            test_cpu.process_instruction(next_instr).unwrap();
+            clock.advance(NANO_PER_INSTRUCTION);
+            let curtime = clock.now();
+            test_cpu.tick(curtime - prevtime);
             prevtime = curtime;
             test_cpu.end_frame();
         }
@@ -174,62 +189,55 @@ mod tests {
         test_cpu.reset();
         assert_eq!(test_cpu.registerV[0], 0);
 
-        let test_disconnected_jump = [
-            0x12, 0x07, 
-            0x01, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-            0x70, 0x01, 
-
-        ];
-        test_cpu.load_rom(&test_disconnected_jump);
-
-        prevtime = SystemTime::now();
-        while !test_cpu.has_died() && test_cpu.pc < 4094 {
-            println!("a: {}", test_cpu.pc);
-            let next_instr = test_cpu.get_next_instr();
-            println!("b");
-            test_cpu.process_instruction(next_instr);
-            let curtime = SystemTime::now();
-            let nsecs = curtime.duration_since(prevtime).unwrap().subsec_nanos();
-            test_cpu.tick(nsecs as u64);
-            println!("c");
+        let test_disconnected_jump = chip8_asm! {
+                jp third;
+                db 0x01;
+                add v0, 1;
+                add v0, 1;
+            third:
+                add v0, 1;
+                add v0, 1;
+                add v0, 1
+        };
+        test_cpu.load_rom(&test_disconnected_jump).unwrap();
+
+        prevtime = clock.now();
+        while !test_cpu.has_died() && (test_cpu.pc as usize) < test_cpu.memory.len() - 2 {
+            let next_instr = test_cpu.get_next_instr().unwrap();
+            test_cpu.process_instruction(next_instr).unwrap();
+            clock.advance(NANO_PER_INSTRUCTION);
+            let curtime = clock.now();
+            test_cpu.tick(curtime - prevtime);
             prevtime = curtime;
             test_cpu.end_frame();
-            println!("d");
         }
 
         assert_eq!(test_cpu.registerV[0], 3);
-        
-        
+
         test_cpu.reset();
         assert_eq!(test_cpu.registerV[0], 0);
 
-        let test_conditions = [
-            0x62, 0x08,  // Load 8 into V[2]
-            0x12, 0x06,  // Skip the next instruction
-            0x75, 0x01,  // SHOULD NOT HIT
-            0x71, 0x01,  // Increment V[1]
-            0x31, 0x0A,  // Don't loop if we are at 10
-            0x12, 0x02,  // Jump back to the 2nd statement
-        ];
-        test_cpu.load_rom(&test_conditions);
-
-        prevtime = SystemTime::now();
-        while !test_cpu.has_died() && test_cpu.pc < 4094 {
-            println!("a: {}", test_cpu.pc);
-            let next_instr = test_cpu.get_next_instr();
-            println!("b");
-            test_cpu.process_instruction(next_instr);
-            let curtime = SystemTime::now();
-            let nsecs = curtime.duration_since(prevtime).unwrap().subsec_nanos();
-            test_cpu.tick(nsecs as u64);
-            println!("c");
+        let test_conditions = chip8_asm! {
+                ld v2, 8;
+            top:
+                jp increment;   // Skip the next instruction
+                add v5, 1;      // SHOULD NOT HIT
+            increment:
+                add v1, 1;
+                se v1, 10;      // Don't loop if we are at 10
+                jp top
+        };
+        test_cpu.load_rom(&test_conditions).unwrap();
+
+        prevtime = clock.now();
+        while !test_cpu.has_died() && (test_cpu.pc as usize) < test_cpu.memory.len() - 2 {
+            let next_instr = test_cpu.get_next_instr().unwrap();
+            test_cpu.process_instruction(next_instr).unwrap();
+            clock.advance(NANO_PER_INSTRUCTION);
+            let curtime = clock.now();
+            test_cpu.tick(curtime - prevtime);
             prevtime = curtime;
             test_cpu.end_frame();
-            println!("d");
         }
 
         assert_eq!(test_cpu.registerV[0], 0);
@@ -238,4 +246,582 @@ mod tests {
         assert_eq!(test_cpu.registerV[5], 0);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_hires_scrolling() {
+        let mut display = TestDisplay::new();
+        {
+            let mut testbuffer = display::ScreenBuffer::new(&mut display);
+            testbuffer.set_resolution(display::Resolution::High);
+            assert_eq!(testbuffer.planes[0].len(), display::HIRES_SCREEN_WIDTH/8 * display::HIRES_SCREEN_HEIGHT);
+
+            testbuffer.put_sprite(120, 60, &[0xFF]);
+            assert_eq!(testbuffer.planes[0][60 * display::HIRES_SCREEN_WIDTH/8 + 15], 0xFF);
+
+            testbuffer.scroll_down(2);
+            assert_eq!(testbuffer.planes[0][60 * display::HIRES_SCREEN_WIDTH/8 + 15], 0x00);
+            assert_eq!(testbuffer.planes[0][62 * display::HIRES_SCREEN_WIDTH/8 + 15], 0xFF);
+
+            testbuffer.scroll_left();
+            assert_eq!(testbuffer.planes[0][62 * display::HIRES_SCREEN_WIDTH/8 + 14], 0x0F);
+            assert_eq!(testbuffer.planes[0][62 * display::HIRES_SCREEN_WIDTH/8 + 15], 0xF0);
+
+            testbuffer.scroll_right();
+            testbuffer.scroll_right();
+            assert_eq!(testbuffer.planes[0][62 * display::HIRES_SCREEN_WIDTH/8 + 14], 0x00);
+            assert_eq!(testbuffer.planes[0][62 * display::HIRES_SCREEN_WIDTH/8 + 15], 0x0F);
+            testbuffer.present();
+        }
+        assert_eq!(display.resolution, display::Resolution::High);
+        assert_eq!(display.screen[62][127], 1);
+    }
+
+    #[test]
+    fn test_superchip_opcodes() {
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &[]);
+
+        test_cpu.process_instruction(0x00FF).unwrap(); // Hi-res
+        assert_eq!(test_cpu.display_output.resolution, display::Resolution::High);
+
+        test_cpu.process_instruction(0x6008).unwrap(); // V0 = 8
+        test_cpu.process_instruction(0xF030).unwrap(); // I = big 8
+        assert_eq!(test_cpu.I as usize, default_fontset::BIG_FONT_START + 80);
+        assert_eq!(&test_cpu.memory[test_cpu.I as usize .. test_cpu.I as usize + 10], &default_fontset::RAW_BIG_8);
+
+        test_cpu.I = 0x300;
+        for idx in 0 .. 32 {
+            test_cpu.memory[0x300 + idx] = 0xFF;
+        }
+        test_cpu.process_instruction(0x6100).unwrap(); // V1 = 0
+        test_cpu.process_instruction(0xD110).unwrap(); // 16x16 sprite at V1, V1
+        assert_eq!(test_cpu.registerV[0xF], 0);
+        let row_bytes = display::HIRES_SCREEN_WIDTH/8;
+        for row in 0 .. 16 {
+            assert_eq!(test_cpu.display_output.planes[0][row * row_bytes], 0xFF);
+            assert_eq!(test_cpu.display_output.planes[0][row * row_bytes + 1], 0xFF);
+            assert_eq!(test_cpu.display_output.planes[0][row * row_bytes + 2], 0x00);
+        }
+        test_cpu.process_instruction(0xD110).unwrap();
+        assert_eq!(test_cpu.registerV[0xF], 1);
+
+        test_cpu.process_instruction(0x6342).unwrap(); // V3 = 0x42
+        test_cpu.process_instruction(0xF375).unwrap(); // Save V0 - V3 to the flags
+        test_cpu.process_instruction(0x6300).unwrap();
+        test_cpu.process_instruction(0xF385).unwrap(); // Restore V0 - V3 from the flags
+        assert_eq!(test_cpu.registerV[3], 0x42);
+        assert_eq!(test_cpu.rpl_flags[0], 8);
+
+        assert!(!test_cpu.has_died());
+        test_cpu.process_instruction(0x00FD).unwrap();
+        assert!(test_cpu.has_died());
+    }
+
+    #[test]
+    fn test_xochip_cpu_opcodes() {
+        let test_long_ops = chip8_asm! {
+            ld i, long, 0xE000;
+            ld v1, 0x11;
+            ld v2, 0x22;
+            ld v3, 0x33;
+            save v1, v3;
+            load v3, v1;            // Restore reversed
+            se v0, 0;               // Skip the long load, since V0 == 0
+            ld i, long, 0x1234;     // SHOULD NOT HIT
+            add v0, 1;
+            db 0xFF, 0xFF           // DIE
+        };
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &test_long_ops);
+
+        while test_cpu.step().is_ok() {}
+
+        assert!(test_cpu.has_died());
+        assert_eq!(test_cpu.I, 0xE000);
+        assert_eq!(&test_cpu.memory[0xE000 .. 0xE003], &[0x11, 0x22, 0x33]);
+        assert_eq!(test_cpu.registerV[1], 0x33);
+        assert_eq!(test_cpu.registerV[2], 0x22);
+        assert_eq!(test_cpu.registerV[3], 0x11);
+        assert_eq!(test_cpu.registerV[0], 1);
+    }
+
+    #[test]
+    fn test_bitplanes() {
+        let mut display = TestDisplay::new();
+        {
+            let mut testbuffer = display::ScreenBuffer::new(&mut display);
+
+            testbuffer.select_planes(3);
+            let collided = testbuffer.put_sprite(0, 0, &[0xF0, 0xFF]);
+            assert!(!collided);
+            assert_eq!(testbuffer.planes[0][0], 0xF0);
+            assert_eq!(testbuffer.planes[1][0], 0xFF);
+
+            testbuffer.select_planes(2);
+            testbuffer.scroll_right();
+            assert_eq!(testbuffer.planes[0][0], 0xF0);
+            assert_eq!(testbuffer.planes[1][0], 0x0F);
+            assert_eq!(testbuffer.planes[1][1], 0xF0);
+
+            testbuffer.clear_screen();
+            assert_eq!(testbuffer.planes[0][0], 0xF0);
+            assert!(testbuffer.planes[1].iter().all(|byte| *byte == 0));
+
+            testbuffer.select_planes(3);
+            testbuffer.put_sprite(4, 0, &[0xFF, 0xFF]);
+            assert!(testbuffer.dirty);
+            testbuffer.present();
+            assert!(!testbuffer.dirty);
+        }
+        assert_eq!(&display.screen[0][0 .. 12], &[1, 1, 1, 1, 3, 3, 3, 3, 3, 3, 3, 3]);
+    }
+
+    #[test]
+    fn test_audio_pattern() {
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &[]);
+
+        test_cpu.I = 0x300;
+        for idx in 0 .. audio::PATTERN_SIZE {
+            test_cpu.memory[0x300 + idx] = idx as u8;
+        }
+        test_cpu.process_instruction(0xF002).unwrap(); // Load the pattern
+        test_cpu.process_instruction(0x6070).unwrap(); // V0 = 112
+        test_cpu.process_instruction(0xF03A).unwrap(); // Pitch = V0
+        test_cpu.process_instruction(0x6002).unwrap(); // V0 = 2
+        test_cpu.process_instruction(0xF018).unwrap(); // Play for 2 ticks
+        play_timer_events(&mut test_cpu);
+        assert!(test_cpu.audio_output.is_playing());
+        test_cpu.tick(1000 * 1000 * 1000);
+        play_timer_events(&mut test_cpu);
+        assert!(!test_cpu.audio_output.is_playing());
+        assert_eq!(test_cpu.audio_output.audio_output.pattern[15], 15);
+        assert_eq!(test_cpu.audio_output.audio_output.pitch, 112);
+        assert_eq!(test_cpu.audio_output.audio_output.play_count, 1);
+        assert_eq!(audio::pitch_to_rate(112), 8000.0);
+    }
+
+    #[test]
+    fn test_quirks() {
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &[]);
+        test_cpu.set_quirks(quirks::Quirks::default());
+
+        test_cpu.process_instruction(0x6003).unwrap(); // V0 = 3
+        test_cpu.process_instruction(0x6108).unwrap(); // V1 = 8
+        test_cpu.process_instruction(0x6F05).unwrap(); // VF = 5
+        test_cpu.process_instruction(0x8016).unwrap(); // V0 >>= 1
+        assert_eq!(test_cpu.registerV[0], 1);
+        assert_eq!(test_cpu.registerV[0xF], 1);
+        test_cpu.process_instruction(0x8011).unwrap(); // V0 |= V1
+        assert_eq!(test_cpu.registerV[0xF], 1);
+
+        let mut vip = quirks::Quirks::from_name("vip").unwrap();
+        vip.set("display-wait", false).unwrap();
+        test_cpu.set_quirks(vip);
+        test_cpu.process_instruction(0x8016).unwrap(); // V0 = V1 >> 1
+        assert_eq!(test_cpu.registerV[0], 4);
+        assert_eq!(test_cpu.registerV[1], 8);
+        assert_eq!(test_cpu.registerV[0xF], 0);
+        test_cpu.process_instruction(0x6F05).unwrap();
+        test_cpu.process_instruction(0x8011).unwrap(); // V0 |= V1, resetting VF
+        assert_eq!(test_cpu.registerV[0xF], 0);
+
+        test_cpu.I = 0x300;
+        test_cpu.process_instruction(0xF155).unwrap(); // Save V0 - V1
+        assert_eq!(test_cpu.I, 0x302);
+
+        test_cpu.process_instruction(0x6038).unwrap(); // V0 = 56
+        test_cpu.process_instruction(0x611E).unwrap(); // V1 = 30
+        test_cpu.I = 0x300;
+        test_cpu.memory[0x300] = 0xFF;
+        test_cpu.memory[0x301] = 0xFF;
+        test_cpu.process_instruction(0xD012).unwrap(); // Draw at the bottom right corner
+        let row_bytes = display::SCREEN_WIDTH/8;
+        assert_eq!(test_cpu.display_output.planes[0][30 * row_bytes + 7], 0xFF);
+        assert_eq!(test_cpu.display_output.planes[0][31 * row_bytes + 7], 0xFF);
+        assert_eq!(test_cpu.display_output.planes[0][7], 0x00);
+        test_cpu.process_instruction(0x603C).unwrap(); // V0 = 60
+        test_cpu.process_instruction(0xD012).unwrap(); // Draw half off the right edge
+        assert_eq!(test_cpu.display_output.planes[0][30 * row_bytes + 7], 0xF0);
+        assert_eq!(test_cpu.display_output.planes[0][30 * row_bytes], 0x00);
+
+        let mut schip = quirks::Quirks::from_name("schip").unwrap();
+        schip.apply_override("clip=off").unwrap();
+        assert!(schip.apply_override("clip").is_err());
+        test_cpu.set_quirks(schip);
+        test_cpu.process_instruction(0x6204).unwrap(); // V2 = 4
+        test_cpu.process_instruction(0xB200).unwrap(); // Jump to 0x200 + V2
+        test_cpu.end_frame();
+        assert_eq!(test_cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn test_platforms() {
+        let mut test_cpu = test_cpu(platform::Platform::CosmacVip, &[]);
+        assert_eq!(test_cpu.memory.len(), 0x1000);
+        assert_eq!(test_cpu.stack.len(), 12);
+        assert_eq!(test_cpu.quirks(), quirks::Quirks::vip());
+
+        let res = test_cpu.process_instruction(0x00FF); // Hi-res doesn't exist on the VIP
+        assert_eq!(res, Err(error::Chip8Error::UnsupportedOpcode { addr : 0x200, opcode : 0x00FF }));
+        assert!(test_cpu.has_died());
+        assert_eq!(test_cpu.display_output.resolution, display::Resolution::Low);
+
+        for plat in platform::PLATFORM_NAMES.iter() {
+            let plat = platform::Platform::from_name(plat).unwrap();
+            assert!(plat.allows_opcode(0xD015));
+            assert_eq!(plat.allows_opcode(0x00FB), plat != platform::Platform::CosmacVip && plat != platform::Platform::Chip48 && plat != platform::Platform::SuperChip10);
+            assert_eq!(plat.allows_opcode(0xF000), plat == platform::Platform::XoChip);
+            assert_eq!(plat.allows_opcode(0xF875), plat == platform::Platform::XoChip);
+        }
+    }
+
+    #[test]
+    fn test_instruction_decoding() {
+        use self::instruction::Instruction;
+
+        for op in 0 ..= 0xFFFF {
+            if let Ok(instr) = Instruction::decode(op) {
+                assert_eq!(instr.encode(), op);
+                assert_eq!(Instruction::decode(instr.encode()), Ok(instr));
+            }
+        }
+        assert!(Instruction::decode(0x5121).is_err());
+        assert!(Instruction::decode(0x8AB8).is_err());
+        assert!(Instruction::decode(0xE19F).is_err());
+        assert!(Instruction::decode(0xF100).is_err());
+
+        assert_eq!(Instruction::decode(0xD120), Ok(Instruction::DrawLargeSprite(1, 2)));
+        assert_eq!(Instruction::decode(0xF000).unwrap().size(), 4);
+        assert_eq!(Instruction::decode(0x00C5).unwrap().to_string(), "scroll-down 5");
+        assert_eq!(Instruction::decode(0x3A42).unwrap().to_string(), "if va != 0x42 then");
+        assert_eq!(Instruction::decode(0x8126).unwrap().to_string(), "v1 >>= v2");
+        assert_eq!(Instruction::decode(0xA2F0).unwrap().to_string(), "i := 0x2F0");
+        assert_eq!(Instruction::decode(0xEFA1).unwrap().to_string(), "if vf key then");
+        assert_eq!(Instruction::decode(0x0123).unwrap().to_string(), "0x01 0x23");
+    }
+
+    #[test]
+    fn test_execution_errors() {
+        use self::error::Chip8Error;
+
+        let mut test_cpu = test_cpu(platform::Platform::CosmacVip, &[]);
+
+        assert_eq!(test_cpu.load_rom(&[0 ; 0x1000]), Err(Chip8Error::RomTooLarge { size : 0x1000, max : 0xE00 }));
+        assert_eq!(test_cpu.process_instruction(0x00EE), Err(Chip8Error::StackUnderflow { addr : 0x200 }));
+        assert!(test_cpu.has_died());
+
+        test_cpu.reset();
+        test_cpu.load_rom(&chip8_asm! {
+            start:
+                call start;
+        }).unwrap();
+        let res = (0 .. 13).map(|_| test_cpu.step()).find(|res| res.is_err());
+        assert_eq!(res, Some(Err(Chip8Error::StackOverflow { addr : 0x200 })));
+        assert_eq!(res.unwrap().unwrap_err().to_string(), "stack overflow at 0x200");
+
+        test_cpu.reset();
+        test_cpu.I = 0xFFE;
+        let res = test_cpu.process_instruction(0xD005);
+        assert_eq!(res, Err(Chip8Error::MemoryOutOfBounds { addr : 0x200, access : 0xFFE, len : 5 }));
+        assert_eq!(res.unwrap_err().to_string(), "5-byte memory access at 0xFFE is out of bounds (instruction at 0x200)");
+        test_cpu.pc = 0xFFF;
+        assert_eq!(test_cpu.step(), Err(Chip8Error::PcOutOfBounds { addr : 0xFFF }));
+
+        test_cpu.reset();
+        test_cpu.process_instruction(0x70FF).unwrap();
+        test_cpu.process_instruction(0x7002).unwrap(); // Wraps around instead of overflowing
+        assert_eq!(test_cpu.registerV[0], 1);
+        test_cpu.process_instruction(0x6103).unwrap();
+        test_cpu.process_instruction(0x8015).unwrap(); // V0 -= V1, borrowing
+        assert_eq!(test_cpu.registerV[0], 0xFE);
+        assert_eq!(test_cpu.registerV[0xF], 0);
+    }
+
+    #[test]
+    fn test_address_wrapping() {
+        // At the top of XO-CHIP's 64 KiB, I and the program counter wrap around
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &[]);
+        test_cpu.I = 0xFFF0;
+        test_cpu.process_instruction(0xFF55).unwrap(); // Save V0 - VF, moving I past them
+        assert_eq!(test_cpu.I, 0);
+        test_cpu.I = 0xFFF0;
+        test_cpu.process_instruction(0xFF65).unwrap();
+        assert_eq!(test_cpu.I, 0);
+        test_cpu.pc = 0xFFFE;
+        test_cpu.process_instruction(0x3000).unwrap(); // Skip, since V0 == 0
+        assert_eq!(test_cpu.pc, 0);
+        test_cpu.pc = 0xFFFC;
+        test_cpu.memory[0xFFFC .. 0x10000].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+        test_cpu.step().unwrap(); // I = 0x1234, stepping over the operand to the start
+        assert_eq!((test_cpu.I, test_cpu.pc), (0x1234, 0));
+        let mut quirks = test_cpu.quirks();
+        quirks.display_wait = true;
+        test_cpu.set_quirks(quirks);
+        test_cpu.process_instruction(0xD001).unwrap();
+        test_cpu.process_instruction(0xD001).unwrap(); // Waits for the display by rewinding
+        assert_eq!(test_cpu.pc, 0xFFFE);
+    }
+
+    #[test]
+    fn test_save_states() {
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &chip8_asm! {
+                high;
+                ld v0, 0x0A;
+                ld f, v0;
+                drw v0, v0, 5;
+                ld v1, 30;
+                ld st, v1;
+                call 0x300;
+        });
+        for _ in 0 .. 7 {
+            let next_instr = test_cpu.get_next_instr().unwrap();
+            test_cpu.process_instruction(next_instr).unwrap();
+            test_cpu.end_frame();
+        }
+        test_cpu.timers.set_delay(12);
+        test_cpu.rpl_flags[3] = 0x33;
+        let state = test_cpu.save_state();
+
+        test_cpu.reset();
+        test_cpu.audio_output.stop();
+        test_cpu.set_quirks(quirks::Quirks::vip());
+        assert_eq!(test_cpu.display_output.resolution, display::Resolution::Low);
+
+        test_cpu.load_state(&state).unwrap();
+        assert_eq!(test_cpu.save_state(), state);
+        assert_eq!(test_cpu.pc, 0x300);
+        assert_eq!(test_cpu.sp, 1);
+        assert_eq!(test_cpu.stack[0], 0x20C);
+        assert_eq!(test_cpu.registerV[0], 0x0A);
+        assert_eq!(test_cpu.I, 50);
+        assert_eq!(test_cpu.timers.delay(), 12);
+        assert_eq!(test_cpu.timers.sound(), 30);
+        assert!(test_cpu.timers.is_sounding());
+        assert_eq!(test_cpu.rpl_flags[3], 0x33);
+        assert_eq!(test_cpu.quirks(), platform::Platform::XoChip.quirks());
+        assert_eq!(test_cpu.display_output.resolution, display::Resolution::High);
+        assert!(test_cpu.display_output.planes[0].iter().any(|byte| *byte != 0));
+
+        assert_eq!(test_cpu.load_state(&state[.. 40]), Err(save_state::SaveStateError::Truncated));
+        assert_eq!(test_cpu.load_state(b"not a state"), Err(save_state::SaveStateError::BadMagic));
+        let mut future = state.clone();
+        future[5] = 3;
+        assert_eq!(test_cpu.load_state(&future), Err(save_state::SaveStateError::UnsupportedVersion(3)));
+        let mut deep = state.clone();
+        deep[28] = 0xFF; // The stack depth, after the header, platform, quirks, pc, I and registers
+        assert_eq!(test_cpu.load_state(&deep), Err(save_state::SaveStateError::BadValue("stack depth")));
+        assert_eq!(test_cpu.pc, 0x300);
+        assert_eq!(test_cpu.display_output.display_output.resolution, display::Resolution::High);
+
+        // The random source picks up where it was when the state was saved.
+        test_cpu.random = Box::new(random::SeededRandom::new(7));
+        test_cpu.process_instruction(0xC0FF).unwrap();
+        let state = test_cpu.save_state();
+        test_cpu.process_instruction(0xC0FF).unwrap();
+        test_cpu.process_instruction(0xC1FF).unwrap();
+        let drawn = (test_cpu.registerV[0], test_cpu.registerV[1]);
+        test_cpu.load_state(&state).unwrap();
+        test_cpu.process_instruction(0xC0FF).unwrap();
+        test_cpu.process_instruction(0xC1FF).unwrap();
+        assert_eq!((test_cpu.registerV[0], test_cpu.registerV[1]), drawn);
+
+        test_cpu.random = Box::new(random::ScriptedRandom::new(vec![1, 2, 3]));
+        assert_eq!(test_cpu.load_state(&state), Err(save_state::SaveStateError::BadValue("random state")));
+        assert_eq!(test_cpu.random.next_byte(), 1);
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &chip8_asm! {
+                add v0, 1;
+                add v0, 1;
+                high;
+                add v0, 1;
+                low;
+                add v0, 1;
+        });
+
+        let mut buffer = rewind::RewindBuffer::new(rewind::DEFAULT_BUDGET);
+        let mut states = vec![test_cpu.save_state()];
+        buffer.push(&states[0]);
+        for _ in 0 .. 6 {
+            let next_instr = test_cpu.get_next_instr().unwrap();
+            test_cpu.process_instruction(next_instr).unwrap();
+            test_cpu.end_frame();
+            states.push(test_cpu.save_state());
+            buffer.push(states.last().unwrap());
+        }
+        assert_eq!(buffer.len(), 6);
+        assert!(buffer.memory_used() < states[0].len());
+
+        for expected in states.iter().rev().skip(1) {
+            let state = buffer.pop().unwrap();
+            assert_eq!(&state, expected);
+            test_cpu.load_state(&state).unwrap();
+        }
+        assert!(buffer.pop().is_none());
+        assert_eq!(test_cpu.pc, 0x200);
+        assert_eq!(test_cpu.registerV[0], 0);
+
+        let mut small_buffer = rewind::RewindBuffer::new(0);
+        for state in states.iter() {
+            small_buffer.push(state);
+        }
+        assert!(small_buffer.is_empty());
+        assert_eq!(small_buffer.memory_used(), 0);
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let mut test_cpu = test_cpu(platform::Platform::CosmacVip, &chip8_asm! {
+                ld v0, 5;
+                ld dt, v0;
+                drw v1, v1, 5;
+                add v2, 1;
+            halt:
+                jp halt;
+        });
+
+        let mut scheduler = scheduler::Scheduler::with_clock(3, Box::new(VirtualClock::new()));
+        assert_eq!(scheduler.clock_hz(), 180);
+        scheduler.run_frame(&mut test_cpu).unwrap();
+        assert_eq!(test_cpu.pc, 0x206);
+        assert_eq!(test_cpu.timers.delay(), 4);
+        assert!(!test_cpu.display_output.dirty);
+
+        scheduler.run_frame(&mut test_cpu).unwrap();
+        assert_eq!(test_cpu.registerV[2], 1);
+        assert_eq!(test_cpu.timers.delay(), 3);
+
+        scheduler.wait_for_next_frame();
+        scheduler.wait_for_next_frame();
+        assert_eq!(scheduler.now(), 2 * scheduler.frame_ns());
+
+        // Timers catch up on several 60ths of a second passing at once
+        test_cpu.timers.set_delay(10);
+        test_cpu.tick(3 * scheduler.frame_ns());
+        assert_eq!(test_cpu.timers.delay(), 7);
+        assert_eq!(test_cpu.display_output.display_output.screen[0][0 .. 5], [1, 1, 1, 1, 0]);
+        assert_eq!(scheduler::Scheduler::from_clock_hz(700).instructions_per_frame, 11);
+        assert_eq!(scheduler::Scheduler::from_clock_hz(10).instructions_per_frame, 1);
+    }
+
+    #[test]
+    fn test_random_sources() {
+        use super::random::*;
+
+        let mut test_cpu = test_cpu(platform::Platform::CosmacVip, &[]);
+        test_cpu.random = Box::new(ScriptedRandom::new(vec![0xAB, 0x0F]));
+        test_cpu.process_instruction(0xC0F0).unwrap(); // V0 = random & 0xF0
+        test_cpu.process_instruction(0xC1FF).unwrap(); // V1 = random
+        test_cpu.process_instruction(0xC2FF).unwrap(); // V2 = random
+        assert_eq!(&test_cpu.registerV[0 .. 3], &[0xA0, 0x0F, 0xAB]);
+
+        let first : Vec<u8> = (0 .. 16).scan(SeededRandom::new(7), |random, _| Some(random.next_byte())).collect();
+        let again : Vec<u8> = (0 .. 16).scan(SeededRandom::new(7), |random, _| Some(random.next_byte())).collect();
+        let other : Vec<u8> = (0 .. 16).scan(SeededRandom::new(8), |random, _| Some(random.next_byte())).collect();
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+
+        let mut page = [0 ; 0x100];
+        for (idx, byte) in page.iter_mut().enumerate() {
+            *byte = idx as u8;
+        }
+        let mut vip = VipRandom::new(page, 0);
+        assert_eq!((vip.next_byte(), vip.next_byte(), vip.next_byte()), (1, 3, 6));
+        assert_eq!(vip.seed, 0x0603);
+
+        assert!(from_spec("42").is_ok());
+        assert!(from_spec("forty-two").is_err());
+        assert!(from_spec("vip:/nonexistent/interpreter.bin").is_err());
+    }
+
+    #[test]
+    fn test_timers() {
+        use super::timers::*;
+
+        let mut timers = Timers::new();
+        timers.set_delay(2);
+        timers.set_sound(2);
+        assert!(timers.is_sounding());
+        timers.tick(NANO_BETWEEN_TICKS / 2);
+        assert_eq!(timers.sound(), 2);
+        timers.tick(NANO_BETWEEN_TICKS);
+        assert_eq!((timers.delay(), timers.sound(), timers.ticks()), (1, 1, 1));
+        timers.tick(NANO_BETWEEN_TICKS / 2);
+        assert!(!timers.is_sounding());
+        assert_eq!(timers.take_events(), vec![TimerEvent::SoundStarted { tick : 0 }, TimerEvent::SoundStopped { tick : 2 }]);
+        assert!(timers.take_events().is_empty());
+
+        // On the VIP a sound timer of 1 is silent, but 2 still sounds for both ticks
+        timers.silent_short_sound = true;
+        timers.set_sound(1);
+        assert!(!timers.is_sounding());
+        timers.tick_once();
+        assert!(timers.take_events().is_empty());
+
+        // Resetting or restoring the timers turns the sound off or on with an event
+        timers.set_sound(5);
+        timers.reset();
+        assert_eq!(timers.take_events(), vec![TimerEvent::SoundStopped { tick : 0 }]);
+        timers.restore(0, 5, 0);
+        timers.restore(0, 5, 0);
+        assert_eq!(timers.take_events(), vec![TimerEvent::SoundStarted { tick : 0 }]);
+
+        // Events nobody takes are dropped, oldest first
+        for _ in 0 .. MAX_EVENTS {
+            timers.set_sound(0);
+            timers.set_sound(5);
+        }
+        let events = timers.take_events();
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events[0], TimerEvent::SoundStopped { tick : 0 });
+
+        // The tone starts as soon as FX18 runs, and stops the tick the timer runs out
+        let mut test_cpu = test_cpu(platform::Platform::CosmacVip, &[]);
+        test_cpu.process_instruction(0x6001).unwrap(); // V0 = 1
+        test_cpu.process_instruction(0xF018).unwrap(); // Silent on the VIP
+        test_cpu.tick(NANO_BETWEEN_TICKS);
+        play_timer_events(&mut test_cpu);
+        assert!(!test_cpu.audio_output.is_playing());
+        test_cpu.process_instruction(0x6002).unwrap(); // V0 = 2
+        test_cpu.process_instruction(0xF018).unwrap(); // Play for 2 ticks
+        play_timer_events(&mut test_cpu);
+        assert!(test_cpu.audio_output.is_playing());
+        test_cpu.tick(3 * NANO_BETWEEN_TICKS);
+        assert_eq!(test_cpu.timers.take_events(), vec![TimerEvent::SoundStopped { tick : 3 }]);
+        assert_eq!(test_cpu.audio_output.audio_output.play_count, 1);
+    }
+
+    #[test]
+    fn test_owned_backends() {
+        use std::thread;
+
+        let mut test_cpu = test_cpu(platform::Platform::XoChip, &chip8_asm! {
+                rnd v0, 0xFF;
+                ld f, v0;
+                drw v1, v1, 5;
+                ld st, v0;
+        });
+        test_cpu.random = Box::new(random::ScriptedRandom::new(vec![0x0A, 0x0B]));
+
+        // A clone carries on exactly like the original, even on another thread
+        let mut snapshot = test_cpu.clone();
+        let test_cpu = thread::spawn(move || {
+            for _ in 0 .. 4 {
+                test_cpu.step().unwrap();
+            }
+            test_cpu.tick(timers::NANO_BETWEEN_TICKS);
+            test_cpu.display_output.present();
+            test_cpu
+        }).join().unwrap();
+        for _ in 0 .. 4 {
+            snapshot.step().unwrap();
+        }
+        snapshot.tick(timers::NANO_BETWEEN_TICKS);
+        snapshot.display_output.present();
+
+        assert_eq!(test_cpu.registerV[0], 0x0A);
+        assert_eq!(snapshot.save_state(), test_cpu.save_state());
+        assert_eq!(snapshot.display_output.display_output.screen, test_cpu.display_output.display_output.screen);
+        assert_eq!(test_cpu.display_output.display_output.screen[0][0 .. 5], [1, 1, 1, 1, 0]);
+        assert_eq!(snapshot.random.next_byte(), 0x0B);
+    }
+}
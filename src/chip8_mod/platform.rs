@@ -0,0 +1,151 @@
+//!
+//! Code related to the different machines Chip 8 programs were written for.
+//!
+//! Where ```Quirks``` covers single opcode behaviours, a ```Platform``` covers
+//! everything about a target machine at once: its screen, memory, stack,
+//! fonts, quirks, speed, and which opcodes exist on it at all.
+
+use chip8_mod::quirks::Quirks;
+use chip8_mod::instruction::Instruction;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Platform {
+
+    ///
+    /// The original interpreter on the RCA COSMAC VIP.
+    CosmacVip,
+
+    ///
+    /// The CHIP-48 interpreter for the HP-48 calculators.
+    Chip48,
+
+    ///
+    /// SUPER-CHIP 1.0, which adds the high resolution mode, 16x16 sprites,
+    /// the large font, the RPL flags, and the exit instruction.
+    SuperChip10,
+
+    ///
+    /// SUPER-CHIP 1.1, which adds the scrolling instructions.
+    SuperChip11,
+
+    ///
+    /// Octo's XO-CHIP extension of SUPER-CHIP 1.1, with 64 KiB of memory,
+    /// bitplanes, and programmable audio.
+    XoChip,
+}
+
+///
+/// The names accepted by ```Platform::from_name```.
+pub const PLATFORM_NAMES : [&str ; 5] = ["vip", "chip48", "schip10", "schip11", "xochip"];
+
+///
+/// The plain Chip 8 of the COSMAC VIP, which the emulator has always run.
+impl Default for Platform {
+    fn default() -> Platform {
+        Platform::CosmacVip
+    }
+}
+
+impl Platform {
+
+    ///
+    /// Looks up a platform by name; see ```PLATFORM_NAMES```.
+    pub fn from_name(name : &str) -> Option<Platform> {
+        match name.to_lowercase().as_ref() {
+            "vip" | "chip8" => Some(Platform::CosmacVip),
+            "chip48" => Some(Platform::Chip48),
+            "schip10" => Some(Platform::SuperChip10),
+            "schip11" | "schip" => Some(Platform::SuperChip11),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip10 => "schip10",
+            Platform::SuperChip11 => "schip11",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    ///
+    /// Whether the platform has the 128x64 high resolution mode.
+    pub fn supports_hires(&self) -> bool {
+        *self != Platform::CosmacVip && *self != Platform::Chip48
+    }
+
+    ///
+    /// Whether the platform has the large 8x10 font for ```FX30```.
+    pub fn has_big_font(&self) -> bool {
+        self.supports_hires()
+    }
+
+    ///
+    /// The number of bytes of addressable memory.
+    pub fn memory_size(&self) -> usize {
+        match *self {
+            Platform::XoChip => 0x10000,
+            _ => 0x1000,
+        }
+    }
+
+    ///
+    /// The number of return addresses the stack can hold.
+    pub fn stack_depth(&self) -> usize {
+        match *self {
+            Platform::CosmacVip => 12,
+            _ => 16,
+        }
+    }
+
+    ///
+    /// The quirks the platform's interpreter follows.
+    pub fn quirks(&self) -> Quirks {
+        match *self {
+            Platform::CosmacVip => Quirks::vip(),
+            Platform::Chip48 => Quirks::chip48(),
+            Platform::SuperChip10 | Platform::SuperChip11 => Quirks::superchip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+
+    ///
+    /// The number of instructions per second programs for the platform expect.
+    pub fn default_clock_hz(&self) -> u32 {
+        match *self {
+            Platform::CosmacVip => 600,
+            Platform::Chip48 => 900,
+            Platform::SuperChip10 | Platform::SuperChip11 => 1800,
+            Platform::XoChip => 60000,
+        }
+    }
+
+    ///
+    /// Checks if the opcode ```op``` exists on the platform.
+    pub fn allows_opcode(&self, op : u16) -> bool {
+        Instruction::decode(op).map(|instr| self.supports(&instr)).unwrap_or(false)
+    }
+
+    ///
+    /// Checks if the instruction ```instr``` exists on the platform.
+    pub fn supports(&self, instr : &Instruction) -> bool {
+        let schip10 = *self != Platform::CosmacVip && *self != Platform::Chip48;
+        let schip11 = schip10 && *self != Platform::SuperChip10;
+        let xochip = *self == Platform::XoChip;
+        match *instr {
+            // Machine code subroutines only ever existed on the VIP
+            Instruction::MachineCall(_) => *self == Platform::CosmacVip,
+            Instruction::ScrollDown(_) | Instruction::ScrollRight | Instruction::ScrollLeft => schip11,
+            Instruction::Exit | Instruction::LowRes | Instruction::HighRes
+            | Instruction::DrawLargeSprite(..) | Instruction::SetAddrToBigChar(_) => schip10,
+            Instruction::SaveFlags(x) | Instruction::RestoreFlags(x) => xochip || (schip10 && x < 8),
+            Instruction::SaveRegisterRange(..) | Instruction::RestoreRegisterRange(..)
+            | Instruction::LoadLongAddr | Instruction::SelectPlanes(_)
+            | Instruction::LoadAudioPattern | Instruction::SetPitch(_) => xochip,
+            _ => true,
+        }
+    }
+}
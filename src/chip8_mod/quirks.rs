@@ -0,0 +1,161 @@
+//!
+//! Code related to the behaviours that differ between Chip 8 interpreters.
+//!
+//! Over the years the Chip 8 has been reimplemented many times, and each
+//! reimplementation changed a few opcodes in small ways. Programs written for
+//! one interpreter often rely on its particular behaviour, so each behaviour
+//! can be switched on or off on its own.
+
+///
+/// The set of interpreter-specific behaviours the CPU follows.
+///
+/// The default is the "CowGod" behaviour the emulator has always used, with
+/// every quirk switched off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Quirks {
+
+    ///
+    /// If set, ```8XY6``` and ```8XYE``` shift the value in register ```Y```
+    /// and store the result in register ```X```, like the COSMAC VIP. Otherwise
+    /// register ```X``` is shifted in place.
+    pub shift_uses_vy : bool,
+
+    ///
+    /// If set, ```FX55``` and ```FX65``` leave ```I``` pointing at the first byte
+    /// after the registers they saved or loaded.
+    pub increment_i_on_load_store : bool,
+
+    ///
+    /// If set, ```8XY1```, ```8XY2``` and ```8XY3``` reset register ```0xF``` to 0.
+    pub vf_reset : bool,
+
+    ///
+    /// If set, sprites drawn past the edge of the screen are cut off.
+    /// Otherwise they wrap around to the opposite edge.
+    pub clip_sprites : bool,
+
+    ///
+    /// If set, ```BXNN``` jumps to ```XNN``` plus the value in register ```X```,
+    /// like the CHIP-48 and SUPER-CHIP. Otherwise ```BNNN``` jumps to ```NNN```
+    /// plus the value in register ```0x0```.
+    pub jump_uses_vx : bool,
+
+    ///
+    /// If set, ```DXYN``` waits for the next 60 Hz display refresh before drawing,
+    /// so at most one sprite is drawn per frame.
+    pub display_wait : bool,
+
+    ///
+    /// If set, setting the sound timer to 1 makes no sound, like the COSMAC VIP.
+    pub silent_short_sound : bool,
+}
+
+///
+/// The names of the presets accepted by ```Quirks::from_name```.
+pub const PRESET_NAMES : [&str ; 5] = ["cowgod", "vip", "chip48", "schip", "xochip"];
+
+///
+/// The names of the individual quirks accepted by ```Quirks::set```.
+pub const QUIRK_NAMES : [&str ; 7] = ["shift", "memory", "vf-reset", "clip", "jump", "display-wait", "short-sound"];
+
+impl Quirks {
+
+    ///
+    /// The behaviour most modern interpreters and documentation follow;
+    /// every quirk is off.
+    pub fn cowgod() -> Quirks {
+        Quirks::default()
+    }
+
+    ///
+    /// The original COSMAC VIP interpreter, referred to elsewhere as the "Legacy" spec.
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_uses_vy : true,
+            increment_i_on_load_store : true,
+            vf_reset : true,
+            clip_sprites : true,
+            jump_uses_vx : false,
+            display_wait : true,
+            silent_short_sound : true,
+        }
+    }
+
+    ///
+    /// The CHIP-48 interpreter for the HP-48 calculators.
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy : false,
+            increment_i_on_load_store : false,
+            vf_reset : false,
+            clip_sprites : true,
+            jump_uses_vx : true,
+            display_wait : false,
+            silent_short_sound : false,
+        }
+    }
+
+    ///
+    /// The SUPER-CHIP interpreters, which inherit the CHIP-48's behaviour.
+    pub fn superchip() -> Quirks {
+        Quirks::chip48()
+    }
+
+    ///
+    /// The XO-CHIP extension, as implemented by Octo.
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy : true,
+            increment_i_on_load_store : true,
+            vf_reset : false,
+            clip_sprites : false,
+            jump_uses_vx : false,
+            display_wait : false,
+            silent_short_sound : false,
+        }
+    }
+
+    ///
+    /// Looks up a preset by name; see ```PRESET_NAMES```.
+    /// ```legacy``` is also accepted for the COSMAC VIP.
+    pub fn from_name(name : &str) -> Option<Quirks> {
+        match name.to_lowercase().as_ref() {
+            "cowgod" => Some(Quirks::cowgod()),
+            "vip" | "legacy" => Some(Quirks::vip()),
+            "chip48" => Some(Quirks::chip48()),
+            "schip" | "superchip" => Some(Quirks::superchip()),
+            "xochip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Switches a single quirk, named as in ```QUIRK_NAMES```, on or off.
+    pub fn set(&mut self, name : &str, value : bool) -> Result<(), String> {
+        match name.to_lowercase().as_ref() {
+            "shift" => self.shift_uses_vy = value,
+            "memory" => self.increment_i_on_load_store = value,
+            "vf-reset" => self.vf_reset = value,
+            "clip" => self.clip_sprites = value,
+            "jump" => self.jump_uses_vx = value,
+            "display-wait" => self.display_wait = value,
+            "short-sound" => self.silent_short_sound = value,
+            _ => return Err(format!("Unknown quirk {}; expected one of {:?}.", name, QUIRK_NAMES)),
+        }
+        Ok(())
+    }
+
+    ///
+    /// Applies an override of the form ```name=on``` or ```name=off```,
+    /// as passed on the command line.
+    pub fn apply_override(&mut self, spec : &str) -> Result<(), String> {
+        let mut parts = spec.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let value = match parts.next().map(|val| val.to_lowercase()) {
+            Some(ref val) if val == "on" || val == "true" || val == "1" => true,
+            Some(ref val) if val == "off" || val == "false" || val == "0" => false,
+            _ => return Err(format!("Bad quirk override {}; expected name=on or name=off.", spec)),
+        };
+        self.set(name, value)
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+extern crate rand;
+
+///
+/// Where ```CXNN``` gets its random bytes from.
+pub trait RandomSource : Send {
+
+    ///
+    /// The next random byte.
+    fn next_byte(&mut self) -> u8 ;
+
+    ///
+    /// A copy of the source, which gives the same bytes from here on.
+    fn clone_box(&self) -> Box<dyn RandomSource> ;
+
+    ///
+    /// The source's position, for save states. Giving it to ```restore``` later
+    /// makes the source hand out the same bytes again from there.
+    fn state(&self) -> Vec<u8> ;
+
+    ///
+    /// Goes back to a position from ```state```. Returns false, leaving the
+    /// source as it was, if ```state``` isn't one this kind of source gives.
+    fn restore(&mut self, state : &[u8]) -> bool ;
+}
+
+impl Clone for Box<dyn RandomSource> {
+    fn clone(&self) -> Box<dyn RandomSource> {
+        self.clone_box()
+    }
+}
+
+///
+/// Random bytes from the operating system's generator, different every run.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadRandom;
+
+impl RandomSource for ThreadRandom {
+    fn next_byte(&mut self) -> u8 {
+        rand::random::<u8>()
+    }
+
+    fn clone_box(&self) -> Box<dyn RandomSource> {
+        Box::new(*self)
+    }
+
+    ///
+    /// The operating system's generator can't be rewound, so there is nothing to save.
+    fn state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn restore(&mut self, _state : &[u8]) -> bool {
+        true
+    }
+}
+
+///
+/// A xorshift generator, which gives the same bytes every run from the same seed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeededRandom {
+    state : u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed : u64) -> SeededRandom {
+        // Mix the seed so that small seeds don't start with a run of zeroes,
+        // and so that the state is never 0, which xorshift never leaves.
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        SeededRandom {
+            state : if state == 0 { 1 } else { state },
+        }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 56) as u8
+    }
+
+    fn clone_box(&self) -> Box<dyn RandomSource> {
+        Box::new(*self)
+    }
+
+    fn state(&self) -> Vec<u8> {
+        (0 .. 8).rev().map(|idx| (self.state >> (idx * 8)) as u8).collect()
+    }
+
+    fn restore(&mut self, state : &[u8]) -> bool {
+        let value = state.iter().fold(0u64, |value, byte| value << 8 | *byte as u64);
+        if state.len() != 8 || value == 0 {
+            return false;
+        }
+        self.state = value;
+        true
+    }
+}
+
+///
+/// The COSMAC VIP interpreter's own ```CXNN``` routine.
+///
+/// The VIP keeps a 16-bit seed in its ```R9``` register. Each random number
+/// steps the low byte on, reads the byte of the interpreter's code at
+/// ```0x100``` plus the low byte, and adds the high byte to it; the sum is the
+/// random number and becomes the new high byte. The numbers therefore depend
+/// on the interpreter's code, which isn't included here; ```from_file``` reads
+/// it from a dump of the VIP's first 512 bytes of memory.
+#[derive(Clone)]
+pub struct VipRandom {
+    pub seed : u16,
+    page : [u8 ; 0x100],
+}
+
+impl VipRandom {
+    ///
+    /// Creates the routine reading ```page```, the interpreter's code from ```0x100``` to ```0x1FF```.
+    pub fn new(page : [u8 ; 0x100], seed : u16) -> VipRandom {
+        VipRandom {
+            seed,
+            page,
+        }
+    }
+
+    ///
+    /// Reads the interpreter from a dump of at least the first 512 bytes of the VIP's memory.
+    pub fn from_file<P : AsRef<Path>>(path : P, seed : u16) -> io::Result<VipRandom> {
+        let mut image = Vec::new();
+        File::open(path)?.read_to_end(&mut image)?;
+        if image.len() < 0x200 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the interpreter image is shorter than 512 bytes"));
+        }
+        let mut page = [0 ; 0x100];
+        page.copy_from_slice(&image[0x100 .. 0x200]);
+        Ok(VipRandom::new(page, seed))
+    }
+}
+
+impl RandomSource for VipRandom {
+    fn next_byte(&mut self) -> u8 {
+        let low = (self.seed as u8).wrapping_add(1);
+        let value = self.page[low as usize].wrapping_add((self.seed >> 8) as u8);
+        self.seed = (value as u16) << 8 | low as u16;
+        value
+    }
+
+    fn clone_box(&self) -> Box<dyn RandomSource> {
+        Box::new(self.clone())
+    }
+
+    fn state(&self) -> Vec<u8> {
+        vec![(self.seed >> 8) as u8, self.seed as u8]
+    }
+
+    fn restore(&mut self, state : &[u8]) -> bool {
+        if state.len() != 2 {
+            return false;
+        }
+        self.seed = (state[0] as u16) << 8 | state[1] as u16;
+        true
+    }
+}
+
+///
+/// Hands out a fixed list of bytes, starting over once they run out. Meant
+/// for tests that need to know what ```CXNN``` will give.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptedRandom {
+    pub values : Vec<u8>,
+    next : usize,
+}
+
+impl ScriptedRandom {
+    pub fn new(values : Vec<u8>) -> ScriptedRandom {
+        ScriptedRandom {
+            values,
+            next : 0,
+        }
+    }
+}
+
+impl RandomSource for ScriptedRandom {
+    fn next_byte(&mut self) -> u8 {
+        if self.values.is_empty() {
+            return 0;
+        }
+        let value = self.values[self.next % self.values.len()];
+        self.next = (self.next + 1) % self.values.len();
+        value
+    }
+
+    fn clone_box(&self) -> Box<dyn RandomSource> {
+        Box::new(self.clone())
+    }
+
+    fn state(&self) -> Vec<u8> {
+        vec![(self.next >> 24) as u8, (self.next >> 16) as u8, (self.next >> 8) as u8, self.next as u8]
+    }
+
+    fn restore(&mut self, state : &[u8]) -> bool {
+        let next = state.iter().fold(0usize, |next, byte| next << 8 | *byte as usize);
+        if state.len() != 4 || next >= self.values.len().max(1) {
+            return false;
+        }
+        self.next = next;
+        true
+    }
+}
+
+///
+/// Picks a random source from the command line: a number seeds ```SeededRandom```,
+/// and ```vip:path``` uses ```VipRandom``` with the interpreter dumped at ```path```.
+pub fn from_spec(spec : &str) -> Result<Box<dyn RandomSource>, String> {
+    if let Some(path) = spec.strip_prefix("vip:") {
+        return VipRandom::from_file(path, 0)
+            .map(|random| Box::new(random) as Box<dyn RandomSource>)
+            .map_err(|err| format!("Could not read the VIP interpreter from {}: {}", path, err));
+    }
+    spec.parse::<u64>()
+        .map(|seed| Box::new(SeededRandom::new(seed)) as Box<dyn RandomSource>)
+        .map_err(|_| format!("Bad seed {}; expected a number or vip:[path to interpreter].", spec))
+}
@@ -0,0 +1,141 @@
+//!
+//! Code related to stepping the machine backwards through its recent past.
+//!
+//! The rewind buffer holds a snapshot of the machine, as made by
+//! ```InterpretedCpu::save_state```, for each frame. Consecutive snapshots
+//! barely differ, so only the newest is kept whole; every older one is kept as
+//! the run-length encoded XOR of itself with the snapshot after it.
+
+use std::collections::VecDeque;
+
+///
+/// The memory budget used when none is given, in bytes.
+pub const DEFAULT_BUDGET : usize = 8 * 1024 * 1024;
+
+///
+/// An older snapshot, stored as its difference from the next newer one.
+struct Delta {
+    len : usize,
+    encoded : Vec<u8>,
+}
+
+pub struct RewindBuffer {
+    latest : Option<Vec<u8>>,
+    deltas : VecDeque<Delta>,
+    used : usize,
+
+    ///
+    /// The most memory, in bytes, the older snapshots may take up. The oldest
+    /// are dropped first when a new snapshot goes over it.
+    pub budget : usize,
+}
+
+impl RewindBuffer {
+    pub fn new(budget : usize) -> RewindBuffer {
+        RewindBuffer {
+            latest : None,
+            deltas : VecDeque::new(),
+            used : 0,
+            budget,
+        }
+    }
+
+    ///
+    /// Records a new snapshot, dropping the oldest ones if the budget runs out.
+    pub fn push(&mut self, snapshot : &[u8]) {
+        if let Some(previous) = self.latest.take() {
+            let delta = Delta {
+                len : previous.len(),
+                encoded : encode(&xor(&previous, snapshot)),
+            };
+            self.used += delta.encoded.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(snapshot.to_vec());
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(dropped) => self.used -= dropped.encoded.len(),
+                None => break,
+            }
+        }
+    }
+
+    ///
+    /// Steps back one snapshot, returning it. The newest snapshot is the
+    /// starting point, so this returns ```None``` once only it is left.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.used -= delta.encoded.len();
+        let latest = self.latest.take().unwrap_or_default();
+        let mut previous = xor(&latest, &decode(&delta.encoded));
+        previous.truncate(delta.len);
+        self.latest = Some(previous.clone());
+        Some(previous)
+    }
+
+    ///
+    /// The number of snapshots that can be stepped back through.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    ///
+    /// The memory the older snapshots take up, in bytes.
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+}
+
+///
+/// XORs two buffers together, as if the shorter was padded with zeros.
+fn xor(a : &[u8], b : &[u8]) -> Vec<u8> {
+    let len = a.len().max(b.len());
+    (0 .. len).map(|idx| a.get(idx).unwrap_or(&0) ^ b.get(idx).unwrap_or(&0)).collect()
+}
+
+///
+/// Run-length encodes the zeros in ```data```. A zero byte is followed by the
+/// big-endian ```u16``` length of the run; every other byte stands for itself.
+fn encode(data : &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut idx = 0;
+    while idx < data.len() {
+        if data[idx] != 0 {
+            encoded.push(data[idx]);
+            idx += 1;
+            continue;
+        }
+        let run = data[idx ..].iter().take(0xFFFF).take_while(|byte| **byte == 0).count();
+        encoded.push(0);
+        encoded.push((run >> 8) as u8);
+        encoded.push(run as u8);
+        idx += run;
+    }
+    encoded
+}
+
+fn decode(encoded : &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut idx = 0;
+    while idx < encoded.len() {
+        if encoded[idx] != 0 {
+            data.push(encoded[idx]);
+            idx += 1;
+            continue;
+        }
+        let run = (encoded[idx + 1] as usize) << 8 | encoded[idx + 2] as usize;
+        data.resize(data.len() + run, 0);
+        idx += 3;
+    }
+    data
+}
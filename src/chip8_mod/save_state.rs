@@ -0,0 +1,219 @@
+//!
+//! Code related to saving and restoring the whole state of the machine.
+//!
+//! A save state is a binary snapshot of everything the program can see or
+//! that changes how it runs: the registers, stack, timers, memory, screen,
+//! audio settings, RPL flags, platform, quirks, and how far the random source
+//! has got. The keyboard, the palette, the kind of random source, and where
+//! the RPL flags are persisted are left alone when a state is loaded.
+//!
+//! ## Format, version 2
+//!
+//! All multi-byte values are big-endian.
+//!
+//! | Bytes     | Contents                                                       |
+//! |-----------|----------------------------------------------------------------|
+//! | 4         | The magic bytes ```C8ST```                                     |
+//! | 2         | The format version, currently 2                                |
+//! | 1         | The platform, as an index into ```PLATFORM_NAMES```            |
+//! | 1         | The quirks; see ```quirks_to_bits```                           |
+//! | 2         | ```pc```                                                       |
+//! | 2         | ```I```                                                        |
+//! | 16        | The registers ```V0``` to ```VF```                             |
+//! | 1         | The stack depth ```N```, which must be the platform's          |
+//! | 1         | ```sp```                                                       |
+//! | 2 * N     | The stack                                                      |
+//! | 1         | The delay timer                                                |
+//! | 1         | The sound timer                                                |
+//! | 8         | The nanoseconds since the timers last ticked                   |
+//! | 1         | Bit 0 is set if the CPU has died, bit 1 if the display is ready|
+//! | 16        | The RPL flags                                                  |
+//! | 16        | The audio pattern                                              |
+//! | 1         | The audio pitch                                                |
+//! | 1         | The resolution; 0 for low, 1 for high                          |
+//! | 1         | The selected planes                                            |
+//! | 1         | The number of planes, ```P```                                  |
+//! | P * (4+L) | For each plane, its length ```L``` and its packed pixels       |
+//! | 4 + M     | The memory size ```M```, and the memory                        |
+//! | 4 + R     | The size ```R``` of the random source's state, and the state   |
+
+use chip8_mod::quirks::Quirks;
+
+use std::error::Error;
+use std::fmt;
+
+pub const MAGIC : [u8 ; 4] = *b"C8ST";
+pub const VERSION : u16 = 2;
+
+///
+/// The reasons a save state can fail to load.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SaveStateError {
+
+    ///
+    /// The data doesn't start with ```MAGIC```, so isn't a save state at all.
+    BadMagic,
+
+    ///
+    /// The save state was written by a different version of the format.
+    UnsupportedVersion(u16),
+
+    ///
+    /// The data ended before the save state did.
+    Truncated,
+
+    ///
+    /// A field held a value that can't be loaded.
+    BadValue(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveStateError::BadMagic => write!(f, "Not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(f, "Unsupported save state version {}; expected {}", version, VERSION),
+            SaveStateError::Truncated => write!(f, "Save state is truncated"),
+            SaveStateError::BadValue(field) => write!(f, "Save state has a bad {}", field),
+        }
+    }
+}
+
+impl Error for SaveStateError {
+    fn description(&self) -> &str {
+        "bad save state"
+    }
+}
+
+///
+/// Packs the quirks into a byte, with one bit per quirk in the order of ```QUIRK_NAMES```.
+pub fn quirks_to_bits(quirks : &Quirks) -> u8 {
+    (quirks.shift_uses_vy as u8)
+        | (quirks.increment_i_on_load_store as u8) << 1
+        | (quirks.vf_reset as u8) << 2
+        | (quirks.clip_sprites as u8) << 3
+        | (quirks.jump_uses_vx as u8) << 4
+        | (quirks.display_wait as u8) << 5
+        | (quirks.silent_short_sound as u8) << 6
+}
+
+pub fn quirks_from_bits(bits : u8) -> Quirks {
+    Quirks {
+        shift_uses_vy : bits & 1 != 0,
+        increment_i_on_load_store : bits & (1 << 1) != 0,
+        vf_reset : bits & (1 << 2) != 0,
+        clip_sprites : bits & (1 << 3) != 0,
+        jump_uses_vx : bits & (1 << 4) != 0,
+        display_wait : bits & (1 << 5) != 0,
+        silent_short_sound : bits & (1 << 6) != 0,
+    }
+}
+
+///
+/// Appends big-endian values to a save state.
+pub struct StateWriter {
+    pub data : Vec<u8>,
+}
+
+impl StateWriter {
+
+    ///
+    /// Starts a save state with the magic bytes and version.
+    pub fn new() -> StateWriter {
+        let mut retval = StateWriter { data : MAGIC.to_vec() };
+        retval.write_u16(VERSION);
+        retval
+    }
+
+    pub fn write_u8(&mut self, value : u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_u16(&mut self, value : u16) {
+        self.data.push((value >> 8) as u8);
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u32(&mut self, value : u32) {
+        self.write_u16((value >> 16) as u16);
+        self.write_u16(value as u16);
+    }
+
+    pub fn write_u64(&mut self, value : u64) {
+        self.write_u32((value >> 32) as u32);
+        self.write_u32(value as u32);
+    }
+
+    pub fn write_bytes(&mut self, bytes : &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    ///
+    /// Writes the length of ```bytes``` followed by the bytes themselves.
+    pub fn write_block(&mut self, bytes : &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> StateWriter {
+        StateWriter::new()
+    }
+}
+
+///
+/// Reads big-endian values back out of a save state.
+pub struct StateReader<'b> {
+    data : &'b [u8],
+    pos : usize,
+}
+
+impl <'b> StateReader<'b> {
+
+    ///
+    /// Checks the magic bytes and version at the start of ```data```.
+    pub fn new(data : &'b [u8]) -> Result<StateReader<'b>, SaveStateError> {
+        let mut retval = StateReader { data, pos : 0 };
+        if retval.read_bytes(MAGIC.len()).map(|magic| magic != MAGIC).unwrap_or(true) {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = retval.read_u16()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        Ok(retval)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        let bytes = self.read_bytes(2)?;
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok((self.read_u16()? as u32) << 16 | self.read_u16()? as u32)
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok((self.read_u32()? as u64) << 32 | self.read_u32()? as u64)
+    }
+
+    pub fn read_bytes(&mut self, count : usize) -> Result<&'b [u8], SaveStateError> {
+        if self.data.len() - self.pos < count {
+            return Err(SaveStateError::Truncated);
+        }
+        let bytes = &self.data[self.pos .. self.pos + count];
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    ///
+    /// Reads a block written by ```StateWriter::write_block```.
+    pub fn read_block(&mut self) -> Result<&'b [u8], SaveStateError> {
+        let count = self.read_u32()? as usize;
+        self.read_bytes(count)
+    }
+}
@@ -0,0 +1,133 @@
+use chip8_mod::InterpretedCpu;
+use chip8_mod::display::DisplayOutput;
+use chip8_mod::audio::AudioOutput;
+use chip8_mod::input::InputReciever;
+use chip8_mod::cpu::OpcodeExecuter;
+use chip8_mod::clock::{Clock, RealClock};
+use chip8_mod::error::Chip8Error;
+
+pub use chip8_mod::timers::TIMER_HZ;
+
+///
+/// Runs the CPU in frames: a fixed number of instructions, then the timers are
+/// given the frame's time to count down in and the screen is redrawn.
+///
+/// The CPU clock is the number of instructions per frame times the frame rate,
+/// so either can be changed without affecting the other; the timers always
+/// count down at 60 Hz. Between frames, ```wait_for_next_frame``` waits on the
+/// scheduler's ```Clock``` until the next one is due.
+pub struct Scheduler {
+    pub instructions_per_frame : u32,
+
+    ///
+    /// The frames run per second.
+    pub frame_hz : u32,
+    clock : Box<dyn Clock>,
+
+    ///
+    /// When the next frame should start, by the clock.
+    next_frame : u64,
+
+    ///
+    /// The time of the frames dropped for falling behind, which the timers
+    /// still need to count down through.
+    missed_ns : u64,
+}
+
+impl Scheduler {
+    ///
+    /// Creates a scheduler running ```instructions_per_frame``` instructions in
+    /// every 60 Hz frame of the wall clock.
+    pub fn new(instructions_per_frame : u32) -> Scheduler {
+        Scheduler::with_clock(instructions_per_frame, Box::new(RealClock::new()))
+    }
+
+    ///
+    /// Creates a scheduler timing its frames with ```clock```.
+    pub fn with_clock(instructions_per_frame : u32, clock : Box<dyn Clock>) -> Scheduler {
+        let now = clock.now();
+        Scheduler {
+            instructions_per_frame : instructions_per_frame.max(1),
+            frame_hz : TIMER_HZ,
+            clock,
+            next_frame : now,
+            missed_ns : 0,
+        }
+    }
+
+    ///
+    /// Creates a scheduler running ```clock_hz``` instructions per second, rounded
+    /// down to a whole number of instructions per frame.
+    pub fn from_clock_hz(clock_hz : u32) -> Scheduler {
+        Scheduler::new(clock_hz / TIMER_HZ)
+    }
+
+    ///
+    /// The instructions run per second.
+    pub fn clock_hz(&self) -> u32 {
+        self.instructions_per_frame * self.frame_hz
+    }
+
+    ///
+    /// The nanoseconds each frame takes.
+    pub fn frame_ns(&self) -> u64 {
+        (1000 * 1000 * 1000) / self.frame_hz.max(1) as u64
+    }
+
+    ///
+    /// The current time by the scheduler's clock.
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    ///
+    /// Runs one whole frame: ```instructions_per_frame``` instructions, stopping
+    /// early if the CPU dies, then ```finish_frame```. Frontends that need to look
+    /// at each instruction, such as a debugger, run them themselves instead.
+    pub fn run_frame<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &mut InterpretedCpu<D, A, I>) -> Result<(), Chip8Error> {
+        for _ in 0 .. self.instructions_per_frame {
+            if cpu.has_died() {
+                break;
+            }
+            cpu.step()?;
+        }
+        self.finish_frame(cpu);
+        Ok(())
+    }
+
+    ///
+    /// Counts the timers down through the frame, and any frames dropped before
+    /// it, then sends the screen to the display if it changed.
+    pub fn finish_frame<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &mut InterpretedCpu<D, A, I>) {
+        cpu.tick(self.frame_ns() + self.missed_ns);
+        self.missed_ns = 0;
+        cpu.display_output.present();
+    }
+
+    ///
+    /// Waits until the next frame is due. If the emulator has fallen more than
+    /// a frame behind, the frames it missed are dropped rather than run back
+    /// to back to catch up; only the timers catch up on them.
+    pub fn wait_for_next_frame(&mut self) {
+        let frame = self.frame_ns();
+        let now = self.clock.now();
+        self.next_frame += frame;
+        if self.next_frame > now {
+            trace!(target : "timing", "Sleeping {} ns until the next frame.", self.next_frame - now);
+            self.clock.sleep_until(self.next_frame);
+        }
+        else if now - self.next_frame > frame {
+            debug!(target : "timing", "Fell {} ns behind; skipping ahead.", now - self.next_frame);
+            self.missed_ns += now - self.next_frame;
+            self.next_frame = now;
+        }
+    }
+
+    ///
+    /// Starts timing frames again from now, such as after the program was
+    /// paused. The time spent paused doesn't count down the timers.
+    pub fn restart(&mut self) {
+        self.next_frame = self.clock.now();
+        self.missed_ns = 0;
+    }
+}
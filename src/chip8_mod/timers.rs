@@ -0,0 +1,195 @@
+//!
+//! Code related to the delay and sound timers.
+//!
+//! Every Chip 8 has two timers which count down by 1 sixty times a second until
+//! they hit 0. The delay timer is read and written by ```FX07``` and ```FX15```,
+//! and the program uses it to wait. The sound timer is written by ```FX18```,
+//! and a tone plays for as long as it is non-zero.
+
+use std::collections::VecDeque;
+
+///
+/// The rate the delay and sound timers count down at on every Chip 8.
+pub const TIMER_HZ : u32 = 60;
+
+///
+/// The nanoseconds between two ticks of the timers.
+pub const NANO_BETWEEN_TICKS : u64 = (1000 * 1000 * 1000) / TIMER_HZ as u64;
+
+///
+/// The most events kept waiting for ```take_events```. Once this many are
+/// waiting, each new event silently drops the oldest one.
+pub const MAX_EVENTS : usize = 64;
+
+///
+/// A change in the timers that a frontend might want to react to. Each event
+/// carries the number of the tick it happened on, counted from when the timers
+/// were created or last reset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerEvent {
+
+    ///
+    /// The sound timer was set high enough to make a sound.
+    SoundStarted { tick : u64 },
+
+    ///
+    /// The sound timer ran out, or was set to a value too low to make a sound.
+    SoundStopped { tick : u64 },
+}
+
+///
+/// The delay and sound timers, counting down together at 60 Hz.
+///
+/// Time is passed in with ```tick```, in nanoseconds, and the timers count down
+/// once for every 60th of a second that has passed, carrying any remainder over
+/// to the next call.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timers {
+    delay : u8,
+    sound : u8,
+
+    ///
+    /// The nanoseconds passed since the timers last counted down.
+    pub ns_since_last_tick : u64,
+
+    ///
+    /// The ticks since the timers were created or last reset.
+    ticks : u64,
+    sounding : bool,
+
+    ///
+    /// If set, a sound timer of 1 makes no sound, like the COSMAC VIP, whose
+    /// interpreter only switched the tone on for values of 2 or more.
+    pub silent_short_sound : bool,
+    events : VecDeque<TimerEvent>,
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        Timers::default()
+    }
+
+    ///
+    /// The value of the delay timer.
+    pub fn delay(&self) -> u8 {
+        self.delay
+    }
+
+    ///
+    /// Sets the delay timer, as ```FX15``` does.
+    pub fn set_delay(&mut self, value : u8) {
+        self.delay = value;
+    }
+
+    ///
+    /// The value of the sound timer.
+    pub fn sound(&self) -> u8 {
+        self.sound
+    }
+
+    ///
+    /// Sets the sound timer, as ```FX18``` does, starting or stopping the sound.
+    pub fn set_sound(&mut self, value : u8) {
+        debug!(target : "audio", "Sound timer set to {}.", value);
+        self.sound = value;
+        let audible = if self.silent_short_sound { value > 1 } else { value > 0 };
+        self.set_sounding(audible);
+    }
+
+    ///
+    /// Whether the sound timer is currently making a sound.
+    pub fn is_sounding(&self) -> bool {
+        self.sounding
+    }
+
+    ///
+    /// The ticks since the timers were created or last reset.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    ///
+    /// Lets ```ns``` nanoseconds pass, returning how many times the timers are
+    /// now due to count down. The caller is expected to call ```tick_once```
+    /// that many times; ```tick``` does both.
+    pub fn advance(&mut self, ns : u64) -> u64 {
+        self.ns_since_last_tick += ns;
+        let due = self.ns_since_last_tick / NANO_BETWEEN_TICKS;
+        self.ns_since_last_tick %= NANO_BETWEEN_TICKS;
+        due
+    }
+
+    ///
+    /// Lets ```ns``` nanoseconds pass, counting the timers down once for every
+    /// 60th of a second passed.
+    pub fn tick(&mut self, ns : u64) {
+        for _ in 0 .. self.advance(ns) {
+            self.tick_once();
+        }
+    }
+
+    ///
+    /// Counts both timers down once, stopping the sound if the sound timer runs out.
+    pub fn tick_once(&mut self) {
+        self.ticks += 1;
+        trace!(target : "timing", "Timer tick {}: delay {}, sound {}.", self.ticks, self.delay, self.sound);
+        if self.delay > 0 {
+            self.delay -= 1;
+        }
+        if self.sound > 0 {
+            self.sound -= 1;
+            if self.sound == 0 {
+                debug!(target : "audio", "Sound timer ran out.");
+                self.set_sounding(false);
+            }
+        }
+    }
+
+    ///
+    /// Sets both timers to 0 and forgets any pending events and partial ticks.
+    /// If the sound was on, a ```SoundStopped``` is left waiting.
+    pub fn reset(&mut self) {
+        self.delay = 0;
+        self.sound = 0;
+        self.ns_since_last_tick = 0;
+        self.ticks = 0;
+        self.events.clear();
+        self.set_sounding(false);
+    }
+
+    ///
+    /// Restores the timers' values, such as from a save state. The sound is on
+    /// if ```sound``` is high enough, with an event if that changes it.
+    pub fn restore(&mut self, delay : u8, sound : u8, ns_since_last_tick : u64) {
+        self.delay = delay;
+        self.sound = sound;
+        self.ns_since_last_tick = ns_since_last_tick;
+        let audible = if self.silent_short_sound { sound > 1 } else { sound > 0 };
+        self.set_sounding(audible);
+    }
+
+    ///
+    /// Removes and returns the events that happened since the last call, oldest
+    /// first. Only the latest ```MAX_EVENTS``` are kept, so callers should take
+    /// them at least once a frame.
+    pub fn take_events(&mut self) -> Vec<TimerEvent> {
+        self.events.drain(..).collect()
+    }
+
+    fn set_sounding(&mut self, sounding : bool) {
+        if sounding == self.sounding {
+            return;
+        }
+        self.sounding = sounding;
+        let event = if sounding {
+            TimerEvent::SoundStarted { tick : self.ticks }
+        }
+        else {
+            TimerEvent::SoundStopped { tick : self.ticks }
+        };
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
@@ -0,0 +1,308 @@
+use chip8_mod::InterpretedCpu;
+use chip8_mod::display::DisplayOutput;
+use chip8_mod::audio::AudioOutput;
+use chip8_mod::input::InputReciever;
+use chip8_mod::instruction::Instruction;
+use asm_mod::assembler::{self, Assembly};
+use debug_mod::debugger::{Debugger, Breakpoint, StopReason, instruction_word};
+
+use std::fs::File;
+use std::io::{self, Read, BufRead, Write};
+
+pub const PROMPT : &str = "(chip8) ";
+
+pub const HELP : &str = "\
+step, s [n]            run n instructions (default 1)
+next, n                run one instruction, stepping over calls
+finish                 run until the current subroutine returns
+continue, c            run until a breakpoint
+break, b <where>       break at an address or label, or on an opcode class like DXYN or FX0A
+delete, d <n>          remove breakpoint n
+breakpoints, bl        list breakpoints
+regs, r                show the registers
+stack, bt              show the call stack
+x <addr> [len]         show memory
+set <reg> <value>      set v0-vf, i, pc, sp, dt, st, or stack entry sN
+write <addr> <bytes>   write bytes to memory
+dis [addr] [count]     disassemble, around the program counter by default
+symbols <file>         load labels from an Octo symbol file
+quit, q                stop the emulator";
+
+///
+/// What the frontend should do after a command.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CommandResult {
+    Output(String),
+
+    ///
+    /// The debugger has been told how to run; go back to running instructions.
+    Resume,
+    Quit,
+}
+
+///
+/// Describes why the debugger stopped, along with the instruction it stopped at.
+pub fn describe_stop<D : DisplayOutput, A : AudioOutput, I : InputReciever>(debugger : &Debugger, cpu : &InterpretedCpu<D, A, I>, reason : StopReason) -> String {
+    let header = match reason {
+        StopReason::Breakpoint(idx) => format!("Breakpoint {} ({}) hit.\n", idx, debugger.breakpoints[idx].map(|bp| bp.to_string()).unwrap_or_default()),
+        StopReason::Paused | StopReason::Step | StopReason::Returned => String::new(),
+    };
+    header + &disassemble_line(debugger, cpu, cpu.pc)
+}
+
+///
+/// Runs a single debugger command against the stopped CPU.
+pub fn execute<D : DisplayOutput, A : AudioOutput, I : InputReciever>(debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>, line : &str) -> Result<CommandResult, String> {
+    let args : Vec<&str> = line.split_whitespace().collect();
+    let command = match args.first() {
+        Some(command) => *command,
+        None => return Ok(CommandResult::Output(String::new())),
+    };
+    let args = &args[1 ..];
+    match command {
+        "step" | "s" => {
+            let count = match args.first() {
+                Some(count) => count.parse::<usize>().map_err(|_| format!("Bad step count {}", count))?,
+                None => 1,
+            };
+            debugger.step_many(cpu, count);
+            Ok(CommandResult::Resume)
+        },
+        "next" | "n" => {
+            debugger.next(cpu);
+            Ok(CommandResult::Resume)
+        },
+        "finish" => {
+            if cpu.sp == 0 {
+                return Err("Not inside a subroutine".to_owned());
+            }
+            debugger.finish(cpu);
+            Ok(CommandResult::Resume)
+        },
+        "continue" | "c" => {
+            debugger.cont(cpu);
+            Ok(CommandResult::Resume)
+        },
+        "break" | "b" => {
+            let target = args.first().ok_or("Usage: break <addr|label|opcode class>")?;
+            let breakpoint = parse_breakpoint(debugger, target)?;
+            let idx = debugger.add_breakpoint(breakpoint);
+            Ok(CommandResult::Output(format!("Breakpoint {} at {}", idx, breakpoint)))
+        },
+        "delete" | "d" => {
+            let idx = args.first().and_then(|idx| idx.parse::<usize>().ok()).ok_or("Usage: delete <n>")?;
+            match debugger.remove_breakpoint(idx) {
+                Some(breakpoint) => Ok(CommandResult::Output(format!("Deleted breakpoint {} at {}", idx, breakpoint))),
+                None => Err(format!("No breakpoint {}", idx)),
+            }
+        },
+        "breakpoints" | "bl" => {
+            let listing : Vec<String> = debugger.breakpoints.iter().enumerate()
+                .filter_map(|(idx, breakpoint)| breakpoint.map(|bp| format!("{}: {}", idx, bp)))
+                .collect();
+            Ok(CommandResult::Output(if listing.is_empty() { "No breakpoints".to_owned() } else { listing.join("\n") }))
+        },
+        "regs" | "r" => Ok(CommandResult::Output(registers(cpu))),
+        "stack" | "bt" => Ok(CommandResult::Output(stack(debugger, cpu))),
+        "x" => {
+            let addr = parse_address(debugger, args.first().ok_or("Usage: x <addr> [len]")?)?;
+            let len = match args.get(1) {
+                Some(len) => parse_value(len)? as usize,
+                None => 16,
+            };
+            Ok(CommandResult::Output(dump_memory(cpu, addr as usize, len)))
+        },
+        "set" => {
+            if args.len() != 2 {
+                return Err("Usage: set <reg> <value>".to_owned());
+            }
+            set_register(cpu, args[0], parse_value(args[1])?)?;
+            Ok(CommandResult::Output(registers(cpu)))
+        },
+        "write" => {
+            let addr = parse_address(debugger, args.first().ok_or("Usage: write <addr> <bytes>")?)? as usize;
+            let bytes = args[1 ..].iter().map(|byte| parse_value(byte)).collect::<Result<Vec<u32>, String>>()?;
+            if bytes.iter().any(|&byte| byte > 0xFF) {
+                return Err("Bytes must be at most 0xFF".to_owned());
+            }
+            if addr + bytes.len() > cpu.memory.len() {
+                return Err(format!("Write past the end of memory at {:#05X}", cpu.memory.len()));
+            }
+            for (offset, byte) in bytes.iter().enumerate() {
+                cpu.memory[addr + offset] = *byte as u8;
+            }
+            Ok(CommandResult::Output(dump_memory(cpu, addr, bytes.len())))
+        },
+        "dis" => {
+            let addr = match args.first() {
+                Some(addr) => parse_address(debugger, addr)?,
+                None => cpu.pc.saturating_sub(8),
+            };
+            let count = match args.get(1) {
+                Some(count) => parse_value(count)? as u16,
+                None => 9,
+            };
+            let lines : Vec<String> = (0 .. count)
+                .map(|idx| addr.wrapping_add(idx * 2))
+                .filter(|&addr| (addr as usize) + 1 < cpu.memory.len())
+                .map(|addr| disassemble_line(debugger, cpu, addr))
+                .collect();
+            Ok(CommandResult::Output(lines.join("\n")))
+        },
+        "symbols" => {
+            let path = args.first().ok_or("Usage: symbols <file>")?;
+            let symbols = load_symbol_file(path)?;
+            debugger.load_symbols(&symbols);
+            Ok(CommandResult::Output(format!("Loaded {} labels", debugger.labels.len())))
+        },
+        "help" | "h" | "?" => Ok(CommandResult::Output(HELP.to_owned())),
+        "quit" | "q" => Ok(CommandResult::Quit),
+        _ => Err(format!("Unknown command {}; try help", command)),
+    }
+}
+
+///
+/// Reads and runs commands from stdin until one resumes the program.
+/// Returns ```false``` if the emulator should stop instead.
+pub fn prompt<D : DisplayOutput, A : AudioOutput, I : InputReciever>(debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>) -> bool {
+    let stdin = io::stdin();
+    let mut last_command = String::new();
+    loop {
+        print!("{}", PROMPT);
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return false,
+            Ok(_) => {},
+        }
+        if line.trim().is_empty() {
+            line = last_command.clone();
+        }
+        else {
+            last_command = line.clone();
+        }
+        match execute(debugger, cpu, &line) {
+            Ok(CommandResult::Output(ref text)) if text.is_empty() => {},
+            Ok(CommandResult::Output(text)) => println!("{}", text),
+            Ok(CommandResult::Resume) => return true,
+            Ok(CommandResult::Quit) => return false,
+            Err(message) => println!("{}", message),
+        }
+    }
+}
+
+///
+/// Reads an Octo symbol file from ```path```.
+pub fn load_symbol_file(path : &str) -> Result<Assembly, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|err| format!("Could not read {}: {}", path, err))?;
+    Assembly::from_symbol_file(&text, path).map_err(|err| err.to_string())
+}
+
+///
+/// A breakpoint is an opcode class if it has no ```0x``` and is 4 characters
+/// long, and an address or label otherwise.
+fn parse_breakpoint(debugger : &Debugger, text : &str) -> Result<Breakpoint, String> {
+    if !text.starts_with("0x") && !debugger.labels.contains_key(text) {
+        if let Some(breakpoint) = Breakpoint::from_opcode_class(text) {
+            return Ok(breakpoint);
+        }
+    }
+    parse_address(debugger, text).map(Breakpoint::Address)
+}
+
+fn parse_address(debugger : &Debugger, text : &str) -> Result<u16, String> {
+    if let Some(&addr) = debugger.labels.get(text) {
+        return Ok(addr);
+    }
+    match assembler::parse_number(text) {
+        Some(addr) if (0 ..= 0xFFFF).contains(&addr) => Ok(addr as u16),
+        _ => Err(format!("Bad address or unknown label {}", text)),
+    }
+}
+
+fn parse_value(text : &str) -> Result<u32, String> {
+    match assembler::parse_number(text) {
+        Some(value) if value >= 0 => Ok(value as u32),
+        _ => Err(format!("Bad value {}", text)),
+    }
+}
+
+///
+/// Sets ```register``` by the name the ```set``` command uses, such as ```vA``` or ```dt```.
+pub fn set_register<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &mut InterpretedCpu<D, A, I>, register : &str, value : u32) -> Result<(), String> {
+    let register = register.to_lowercase();
+    let limit = |max : u32| if value <= max { Ok(value) } else { Err(format!("{} is too large for {}", value, register)) };
+    match register.as_str() {
+        "i" => cpu.I = limit(0xFFFF)? as u16,
+        "pc" => cpu.pc = limit(0xFFFF)? as u16,
+        "sp" => cpu.sp = limit(cpu.stack.len() as u32)? as usize,
+        "dt" => cpu.timers.set_delay(limit(0xFF)? as u8),
+        "st" => cpu.timers.set_sound(limit(0xFF)? as u8),
+        _ if register.starts_with('v') && register.len() == 2 => {
+            let idx = usize::from_str_radix(&register[1 ..], 16).map_err(|_| format!("Unknown register {}", register))?;
+            cpu.registerV[idx] = limit(0xFF)? as u8;
+        },
+        _ if register.starts_with('s') => {
+            let idx = register[1 ..].parse::<usize>().map_err(|_| format!("Unknown register {}", register))?;
+            if idx >= cpu.stack.len() {
+                return Err(format!("The stack only has {} entries", cpu.stack.len()));
+            }
+            cpu.stack[idx] = limit(0xFFFF)? as u16;
+        },
+        _ => return Err(format!("Unknown register {}", register)),
+    }
+    Ok(())
+}
+
+fn registers<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &InterpretedCpu<D, A, I>) -> String {
+    let mut retval = String::new();
+    for (idx, value) in cpu.registerV.iter().enumerate() {
+        retval.push_str(&format!("v{:x}={:02X}{}", idx, value, if idx % 8 == 7 { "\n" } else { " " }));
+    }
+    retval.push_str(&format!("pc={:03X} i={:03X} sp={} dt={:02X} st={:02X}", cpu.pc, cpu.I, cpu.sp, cpu.timers.delay(), cpu.timers.sound()));
+    retval
+}
+
+fn stack<D : DisplayOutput, A : AudioOutput, I : InputReciever>(debugger : &Debugger, cpu : &InterpretedCpu<D, A, I>) -> String {
+    if cpu.sp == 0 {
+        return "The stack is empty".to_owned();
+    }
+    let frames : Vec<String> = cpu.stack[.. cpu.sp].iter().enumerate().rev()
+        .map(|(idx, &addr)| {
+            let target = Instruction::decode(instruction_word(cpu, addr)).ok().and_then(|instr| match instr {
+                Instruction::Call(target) => Some(target),
+                _ => None,
+            });
+            let name = target.and_then(|target| debugger.label_at(target)).map(|name| format!(" ({})", name)).unwrap_or_default();
+            format!("s{}: called from {:#05X}{}", idx, addr, name)
+        })
+        .collect();
+    frames.join("\n")
+}
+
+fn dump_memory<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &InterpretedCpu<D, A, I>, start : usize, len : usize) -> String {
+    let end = start.saturating_add(len).min(cpu.memory.len());
+    if start >= end {
+        return format!("{:#05X} is past the end of memory", start);
+    }
+    let lines : Vec<String> = cpu.memory[start .. end].chunks(8).enumerate()
+        .map(|(idx, row)| {
+            let bytes : Vec<String> = row.iter().map(|byte| format!("{:02X}", byte)).collect();
+            format!("{:#05X}: {}", start + idx * 8, bytes.join(" "))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+fn disassemble_line<D : DisplayOutput, A : AudioOutput, I : InputReciever>(debugger : &Debugger, cpu : &InterpretedCpu<D, A, I>, addr : u16) -> String {
+    let opcode = instruction_word(cpu, addr);
+    let text = match Instruction::decode(opcode) {
+        Ok(instr) => instr.to_string(),
+        Err(_) => format!("{:#04X} {:#04X}", opcode >> 8, opcode & 0xFF),
+    };
+    let label = debugger.label_at(addr).map(|name| format!("  : {}", name)).unwrap_or_default();
+    format!("{} {:#05X}: {:04X}  {}{}", if addr == cpu.pc { "=>" } else { "  " }, addr, opcode, text, label)
+}
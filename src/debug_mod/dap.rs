@@ -0,0 +1,553 @@
+use chip8_mod::InterpretedCpu;
+use chip8_mod::display::DisplayOutput;
+use chip8_mod::audio::AudioOutput;
+use chip8_mod::input::InputReciever;
+use chip8_mod::cpu::OpcodeExecuter;
+use chip8_mod::error::Chip8Error;
+use asm_mod::assembler::{self, Assembly};
+use debug_mod::debugger::{Debugger, Breakpoint, StopReason, RemoteDebugger, RemoteAction};
+use debug_mod::cli;
+
+use serde_json::{self, Value};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+///
+/// The only thread the frontend is told about.
+const THREAD_ID : i64 = 1;
+
+///
+/// The ```variablesReference``` of each scope.
+const REGISTERS_SCOPE : i64 = 1;
+const TIMERS_SCOPE : i64 = 2;
+
+const BASE64_CHARS : &[u8 ; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+///
+/// Labels and source lines, with the directory the source file names are relative to.
+pub struct Symbols {
+    pub assembly : Assembly,
+    pub dir : PathBuf,
+}
+
+impl Symbols {
+
+    ///
+    /// Reads symbols from an Octo source file, by assembling it, or from a symbol file.
+    pub fn load(path : &Path) -> Result<Symbols, String> {
+        let assembly = if path.extension().map(|ext| ext == "8o").unwrap_or(false) {
+            assembler::assemble_file(path).map_err(|err| err.to_string())?
+        }
+        else {
+            cli::load_symbol_file(&path.to_string_lossy())?
+        };
+        Ok(Symbols {
+            assembly,
+            dir : path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        })
+    }
+
+    fn file_index(&self, path : &str) -> Option<usize> {
+        let wanted = Path::new(path);
+        let canonical = wanted.canonicalize().ok();
+        self.assembly.files.iter().position(|file| {
+            let candidates = [self.dir.join(file), PathBuf::from(file)];
+            let same_path = candidates.iter().any(|candidate| canonical.is_some() && candidate.canonicalize().ok() == canonical);
+            same_path || (canonical.is_none() && Path::new(file).file_name() == wanted.file_name())
+        })
+    }
+
+    ///
+    /// The source file and line the instruction at ```addr``` came from.
+    fn source_line(&self, addr : u16) -> Option<(String, usize)> {
+        let line = self.assembly.lines.iter().find(|line| line.addr == addr)?;
+        let file = self.assembly.files.get(line.file)?;
+        let path = self.dir.join(file);
+        let path = path.canonicalize().unwrap_or(path);
+        Some((path.to_string_lossy().into_owned(), line.line))
+    }
+
+    ///
+    /// The address of the first instruction on or after ```line``` of ```path```,
+    /// along with the line it is actually on.
+    fn line_address(&self, path : &str, line : usize) -> Option<(u16, usize)> {
+        let file = self.file_index(path)?;
+        self.assembly.lines.iter()
+            .filter(|entry| entry.file == file && entry.line >= line)
+            .min_by_key(|entry| (entry.line, entry.addr))
+            .map(|entry| (entry.addr, entry.line))
+    }
+}
+
+///
+/// The state of a Debug Adapter Protocol session, apart from the connection.
+///
+/// Requests are answered by pushing responses and events onto ```outgoing```,
+/// which the connection then sends in order.
+pub struct DapSession {
+    pub outgoing : Vec<Value>,
+    pub symbols : Option<Symbols>,
+    seq : i64,
+
+    ///
+    /// The breakpoints set in each source file, so they can be replaced together.
+    source_breakpoints : BTreeMap<String, Vec<usize>>,
+    instruction_breakpoints : Vec<usize>,
+    stop_on_entry : bool,
+
+    ///
+    /// Whether the frontend has finished setting up, and so expects stopped events.
+    configured : bool,
+}
+
+impl DapSession {
+
+    pub fn new(symbols : Option<Symbols>) -> DapSession {
+        DapSession {
+            outgoing : Vec::new(),
+            symbols,
+            seq : 0,
+            source_breakpoints : BTreeMap::new(),
+            instruction_breakpoints : Vec::new(),
+            stop_on_entry : false,
+            configured : false,
+        }
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    pub fn event(&mut self, name : &str, body : Value) {
+        let seq = self.next_seq();
+        self.outgoing.push(json!({ "seq" : seq, "type" : "event", "event" : name, "body" : body }));
+    }
+
+    fn respond(&mut self, request : &Value, body : Result<Value, String>) {
+        let seq = self.next_seq();
+        let mut response = json!({
+            "seq" : seq,
+            "type" : "response",
+            "request_seq" : request["seq"],
+            "command" : request["command"],
+            "success" : body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::from(message),
+        }
+        self.outgoing.push(response);
+    }
+
+    ///
+    /// Sends the stopped event for ```reason```, if the frontend is ready for it.
+    pub fn stopped(&mut self, debugger : &Debugger, reason : Result<StopReason, Chip8Error>) {
+        if !self.configured {
+            return;
+        }
+        let body = match reason {
+            Ok(StopReason::Paused) => json!({ "reason" : "pause" }),
+            Ok(StopReason::Step) | Ok(StopReason::Returned) => json!({ "reason" : "step" }),
+            Ok(StopReason::Breakpoint(idx)) => json!({
+                "reason" : "breakpoint",
+                "hitBreakpointIds" : [idx],
+                "description" : format!("Breakpoint at {}", debugger.breakpoints[idx].map(|bp| bp.to_string()).unwrap_or_default()),
+            }),
+            Err(err) => json!({ "reason" : "exception", "text" : err.to_string() }),
+        };
+        let mut body = body;
+        body["threadId"] = Value::from(THREAD_ID);
+        body["allThreadsStopped"] = Value::from(true);
+        self.event("stopped", body);
+    }
+
+    ///
+    /// Answers a single request. Returns what to do with the program, or
+    /// ```None``` to wait for the next request.
+    pub fn handle_request<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>, request : &Value) -> Option<RemoteAction> {
+        let args = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or("");
+        let (body, action) = match command {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest" : true,
+                    "supportsSetVariable" : true,
+                    "supportsReadMemoryRequest" : true,
+                    "supportsWriteMemoryRequest" : true,
+                    "supportsInstructionBreakpoints" : true,
+                    "supportsTerminateRequest" : true,
+                });
+                self.respond(request, Ok(capabilities));
+                self.event("initialized", json!({}));
+                return None;
+            },
+            "launch" | "attach" => (self.start(debugger, cpu, command == "launch", args), None),
+            "setBreakpoints" => (self.set_breakpoints(debugger, args), None),
+            "setInstructionBreakpoints" => (self.set_instruction_breakpoints(debugger, args), None),
+            "setExceptionBreakpoints" => (Ok(json!({ "breakpoints" : [] })), None),
+            "configurationDone" => {
+                self.configured = true;
+                self.respond(request, Ok(json!({})));
+                if self.stop_on_entry {
+                    self.event("stopped", json!({ "reason" : "entry", "threadId" : THREAD_ID, "allThreadsStopped" : true }));
+                    return None;
+                }
+                debugger.cont(cpu);
+                return Some(RemoteAction::Resume);
+            },
+            "threads" => (Ok(json!({ "threads" : [{ "id" : THREAD_ID, "name" : "CHIP-8" }] })), None),
+            "stackTrace" => (Ok(self.stack_trace(debugger, cpu, args)), None),
+            "scopes" => (Ok(json!({ "scopes" : [
+                { "name" : "Registers", "variablesReference" : REGISTERS_SCOPE, "expensive" : false },
+                { "name" : "Timers", "variablesReference" : TIMERS_SCOPE, "expensive" : false },
+            ] })), None),
+            "variables" => (Ok(json!({ "variables" : variables(cpu, args["variablesReference"].as_i64().unwrap_or(0)) })), None),
+            "setVariable" => (set_variable(cpu, args), None),
+            "readMemory" => (read_memory(cpu, args), None),
+            "writeMemory" => (write_memory(cpu, args), None),
+            "continue" => {
+                debugger.cont(cpu);
+                (Ok(json!({ "allThreadsContinued" : true })), Some(RemoteAction::Resume))
+            },
+            "next" => {
+                debugger.next(cpu);
+                (Ok(json!({})), Some(RemoteAction::Resume))
+            },
+            "stepIn" => {
+                debugger.step(cpu);
+                (Ok(json!({})), Some(RemoteAction::Resume))
+            },
+            "stepOut" if cpu.sp == 0 => (Err("Not inside a subroutine".to_owned()), None),
+            "stepOut" => {
+                debugger.finish(cpu);
+                (Ok(json!({})), Some(RemoteAction::Resume))
+            },
+            "pause" => {
+                debugger.pause();
+                (Ok(json!({})), None)
+            },
+            "disconnect" if args["terminateDebuggee"].as_bool() == Some(true) => (Ok(json!({})), Some(RemoteAction::Kill)),
+            "disconnect" => (Ok(json!({})), Some(RemoteAction::Detach)),
+            "terminate" => {
+                self.respond(request, Ok(json!({})));
+                self.event("terminated", json!({}));
+                return Some(RemoteAction::Kill);
+            },
+            _ => (Err(format!("Unsupported request {}", command)), None),
+        };
+        self.respond(request, body);
+        action
+    }
+
+    ///
+    /// Handles ```launch```, which loads the ```program``` argument if there is
+    /// one, and ```attach```, which debugs whatever is already loaded.
+    fn start<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>, launch : bool, args : &Value) -> Result<Value, String> {
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        if let (true, Some(program)) = (launch, args["program"].as_str()) {
+            let path = Path::new(program);
+            let rom = if program.ends_with(".8o") {
+                let symbols = Symbols::load(path)?;
+                let rom = symbols.assembly.rom.clone();
+                self.symbols = Some(symbols);
+                rom
+            }
+            else {
+                let mut rom = Vec::new();
+                File::open(path)
+                    .and_then(|mut file| file.read_to_end(&mut rom))
+                    .map_err(|err| format!("Could not read {}: {}", program, err))?;
+                rom
+            };
+            cpu.reset();
+            cpu.load_rom(&rom).map_err(|err| err.to_string())?;
+        }
+        if let Some(path) = args["symbols"].as_str() {
+            self.symbols = Some(Symbols::load(Path::new(path))?);
+        }
+        if let Some(ref symbols) = self.symbols {
+            debugger.load_symbols(&symbols.assembly);
+        }
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, debugger : &mut Debugger, args : &Value) -> Result<Value, String> {
+        let path = args["source"]["path"].as_str().ok_or("Breakpoints need a source path")?.to_owned();
+        for idx in self.source_breakpoints.remove(&path).unwrap_or_default() {
+            debugger.remove_breakpoint(idx);
+        }
+        let mut ids = Vec::new();
+        let mut results = Vec::new();
+        for requested in args["breakpoints"].as_array().map(|list| list.as_slice()).unwrap_or(&[]) {
+            let line = requested["line"].as_u64().unwrap_or(0) as usize;
+            let target = self.symbols.as_ref().and_then(|symbols| symbols.line_address(&path, line));
+            results.push(match target {
+                Some((addr, line)) => {
+                    let id = debugger.add_breakpoint(Breakpoint::Address(addr));
+                    ids.push(id);
+                    json!({ "id" : id, "verified" : true, "line" : line, "instructionReference" : format!("{:#X}", addr) })
+                },
+                None => json!({ "verified" : false, "line" : line, "message" : "No instruction at or after this line" }),
+            });
+        }
+        self.source_breakpoints.insert(path, ids);
+        Ok(json!({ "breakpoints" : results }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, debugger : &mut Debugger, args : &Value) -> Result<Value, String> {
+        for idx in self.instruction_breakpoints.drain(..) {
+            debugger.remove_breakpoint(idx);
+        }
+        let mut results = Vec::new();
+        for requested in args["breakpoints"].as_array().map(|list| list.as_slice()).unwrap_or(&[]) {
+            let base = requested["instructionReference"].as_str().and_then(assembler::parse_number);
+            let addr = base.map(|base| base + requested["offset"].as_i64().unwrap_or(0) as i32);
+            results.push(match addr {
+                Some(addr) if (0 ..= 0xFFFF).contains(&addr) => {
+                    let id = debugger.add_breakpoint(Breakpoint::Address(addr as u16));
+                    self.instruction_breakpoints.push(id);
+                    json!({ "id" : id, "verified" : true, "instructionReference" : format!("{:#X}", addr) })
+                },
+                _ => json!({ "verified" : false, "message" : "Bad instruction reference" }),
+            });
+        }
+        Ok(json!({ "breakpoints" : results }))
+    }
+
+    ///
+    /// The program counter, then the address of each call on the stack.
+    fn stack_trace<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&self, debugger : &Debugger, cpu : &InterpretedCpu<D, A, I>, args : &Value) -> Value {
+        let addrs : Vec<u16> = Some(cpu.pc).into_iter().chain(cpu.stack[.. cpu.sp.min(cpu.stack.len())].iter().rev().cloned()).collect();
+        let start = args["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = match args["levels"].as_u64() {
+            Some(0) | None => addrs.len(),
+            Some(levels) => levels as usize,
+        };
+        let frames : Vec<Value> = addrs.iter().enumerate().skip(start).take(levels)
+            .map(|(idx, &addr)| {
+                let name = debugger.labels.iter()
+                    .filter(|&(_, label_addr)| *label_addr <= addr)
+                    .max_by_key(|&(_, label_addr)| *label_addr)
+                    .map(|(name, label_addr)| if *label_addr == addr { name.clone() } else { format!("{}+{:#X}", name, addr - label_addr) })
+                    .unwrap_or_else(|| format!("{:#05X}", addr));
+                let mut frame = json!({
+                    "id" : idx,
+                    "name" : name,
+                    "line" : 0,
+                    "column" : 0,
+                    "instructionPointerReference" : format!("{:#X}", addr),
+                });
+                if let Some((path, line)) = self.symbols.as_ref().and_then(|symbols| symbols.source_line(addr)) {
+                    frame["source"] = json!({ "path" : path });
+                    frame["line"] = Value::from(line);
+                    frame["column"] = Value::from(1);
+                }
+                frame
+            })
+            .collect();
+        json!({ "stackFrames" : frames, "totalFrames" : addrs.len() })
+    }
+}
+
+fn variables<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &InterpretedCpu<D, A, I>, scope : i64) -> Vec<Value> {
+    let byte = |name : String, value : u8| json!({ "name" : name, "value" : format!("{:#04X}", value), "type" : "u8", "variablesReference" : 0 });
+    let address = |name : &str, value : u16| json!({
+        "name" : name,
+        "value" : format!("{:#05X}", value),
+        "type" : "u16",
+        "variablesReference" : 0,
+        "memoryReference" : format!("{:#X}", value),
+    });
+    match scope {
+        REGISTERS_SCOPE => {
+            let mut retval : Vec<Value> = cpu.registerV.iter().enumerate().map(|(idx, value)| byte(format!("V{:X}", idx), *value)).collect();
+            retval.push(address("I", cpu.I));
+            retval.push(address("PC", cpu.pc));
+            retval.push(byte("SP".to_owned(), cpu.sp as u8));
+            retval
+        },
+        TIMERS_SCOPE => vec![byte("DT".to_owned(), cpu.timers.delay()), byte("ST".to_owned(), cpu.timers.sound())],
+        _ => Vec::new(),
+    }
+}
+
+fn set_variable<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &mut InterpretedCpu<D, A, I>, args : &Value) -> Result<Value, String> {
+    let name = args["name"].as_str().ok_or("No variable name")?;
+    let value = args["value"].as_str().ok_or("No value")?;
+    let value = assembler::parse_number(value.trim()).filter(|value| *value >= 0).ok_or_else(|| format!("Bad value {}", value))?;
+    cli::set_register(cpu, name, value as u32)?;
+    let scope = if name == "DT" || name == "ST" { TIMERS_SCOPE } else { REGISTERS_SCOPE };
+    variables(cpu, scope).into_iter()
+        .find(|variable| variable["name"] == name)
+        .map(|variable| json!({ "value" : variable["value"] }))
+        .ok_or_else(|| format!("Unknown register {}", name))
+}
+
+///
+/// The start of a memory access, from a ```memoryReference``` and ```offset```.
+fn memory_address(args : &Value) -> Result<i64, String> {
+    let base = args["memoryReference"].as_str().and_then(assembler::parse_number).ok_or("Bad memory reference")?;
+    Ok((base as i64).saturating_add(args["offset"].as_i64().unwrap_or(0)))
+}
+
+fn read_memory<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &InterpretedCpu<D, A, I>, args : &Value) -> Result<Value, String> {
+    let addr = memory_address(args)?;
+    let count = args["count"].as_u64().unwrap_or(0) as usize;
+    let start = addr.max(0).min(cpu.memory.len() as i64) as usize;
+    let end = start.saturating_add(count).min(cpu.memory.len());
+    Ok(json!({
+        "address" : format!("{:#X}", addr),
+        "data" : base64_encode(&cpu.memory[start .. end]),
+        "unreadableBytes" : count - (end - start),
+    }))
+}
+
+fn write_memory<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &mut InterpretedCpu<D, A, I>, args : &Value) -> Result<Value, String> {
+    let addr = memory_address(args)?;
+    let data = args["data"].as_str().and_then(base64_decode).ok_or("Bad memory data")?;
+    if addr < 0 || (addr as usize).saturating_add(data.len()) > cpu.memory.len() {
+        return Err(format!("Write past the end of memory at {:#05X}", cpu.memory.len()));
+    }
+    cpu.memory[addr as usize .. addr as usize + data.len()].copy_from_slice(&data);
+    Ok(json!({ "bytesWritten" : data.len() }))
+}
+
+pub fn base64_encode(bytes : &[u8]) -> String {
+    let mut retval = String::new();
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (idx, byte)| word | (*byte as u32) << (16 - 8 * idx));
+        for idx in 0 .. 4 {
+            if idx <= chunk.len() {
+                retval.push(BASE64_CHARS[(word >> (18 - 6 * idx)) as usize & 0x3F] as char);
+            }
+            else {
+                retval.push('=');
+            }
+        }
+    }
+    retval
+}
+
+pub fn base64_decode(text : &str) -> Option<Vec<u8>> {
+    let mut retval = Vec::new();
+    let mut word = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|c| *c != b'=') {
+        let value = BASE64_CHARS.iter().position(|base| *base == c)? as u32;
+        word = word << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            retval.push((word >> bits) as u8);
+        }
+    }
+    Some(retval)
+}
+
+///
+/// A connection to an editor, or any other Debug Adapter Protocol client, over TCP.
+pub struct DapServer {
+    pub session : DapSession,
+    reader : BufReader<TcpStream>,
+    writer : TcpStream,
+}
+
+impl DapServer {
+
+    ///
+    /// Waits on ```port``` of the local machine for a client to connect.
+    pub fn listen(port : u16, symbols : Option<Symbols>) -> io::Result<DapServer> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let (stream, _) = listener.accept()?;
+        Ok(DapServer {
+            session : DapSession::new(symbols),
+            reader : BufReader::new(stream.try_clone()?),
+            writer : stream,
+        })
+    }
+
+    ///
+    /// Reads a message, framed by a ```Content-Length``` header.
+    fn read_message(&mut self) -> io::Result<Value> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.reader.read_line(&mut header)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The client disconnected"));
+            }
+            let header = header.trim();
+            if header.is_empty() && length.is_some() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let mut body = vec![0 ; length.unwrap_or(0)];
+        self.reader.read_exact(&mut body)?;
+        serde_json::from_slice(&body).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for message in self.session.outgoing.drain(..) {
+            let body = message.to_string();
+            write!(self.writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        }
+        self.writer.flush()
+    }
+
+    fn handle_next<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>) -> io::Result<Option<RemoteAction>> {
+        let request = self.read_message()?;
+        let action = self.session.handle_request(debugger, cpu, &request);
+        self.flush()?;
+        Ok(action)
+    }
+}
+
+impl <D : DisplayOutput, A : AudioOutput, I : InputReciever> RemoteDebugger<D, A, I> for DapServer {
+
+    fn stopped(&mut self, debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>, reason : Result<StopReason, Chip8Error>) -> io::Result<RemoteAction> {
+        self.session.stopped(debugger, reason);
+        self.flush()?;
+        loop {
+            if let Some(action) = self.handle_next(debugger, cpu)? {
+                return Ok(action);
+            }
+        }
+    }
+
+    ///
+    /// Handles requests that arrive while the program runs, such as ```pause```
+    /// or new breakpoints. Clients send each message whole, so once one has
+    /// started arriving it is read to the end.
+    fn poll(&mut self, debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>) -> io::Result<RemoteAction> {
+        loop {
+            self.writer.set_nonblocking(true)?;
+            let waiting = match self.reader.fill_buf() {
+                Ok(buf) => Ok(!buf.is_empty()),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return self.writer.set_nonblocking(false).map(|_| RemoteAction::Resume),
+                Err(err) => Err(err),
+            };
+            self.writer.set_nonblocking(false)?;
+            if !waiting? {
+                // The client hung up without disconnecting
+                return Ok(RemoteAction::Detach);
+            }
+            match self.handle_next(debugger, cpu)? {
+                Some(RemoteAction::Resume) | None => {},
+                Some(action) => return Ok(action),
+            }
+        }
+    }
+
+    fn exited(&mut self) -> io::Result<()> {
+        self.session.event("exited", json!({ "exitCode" : 0 }));
+        self.session.event("terminated", json!({}));
+        self.flush()
+    }
+}
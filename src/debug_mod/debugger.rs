@@ -0,0 +1,303 @@
+use chip8_mod::InterpretedCpu;
+use chip8_mod::display::DisplayOutput;
+use chip8_mod::audio::AudioOutput;
+use chip8_mod::input::InputReciever;
+use chip8_mod::instruction::Instruction;
+use chip8_mod::error::Chip8Error;
+use asm_mod::assembler::Assembly;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+///
+/// Somewhere the debugger stops before running an instruction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Breakpoint {
+
+    ///
+    /// Stops when the program counter reaches the address.
+    Address(u16),
+
+    ///
+    /// Stops before any opcode that matches ```pattern``` in the bits set in ```mask```.
+    Opcode { pattern : u16, mask : u16 },
+}
+
+impl Breakpoint {
+
+    ///
+    /// Parses an opcode class written like ```DXYN``` or ```FX0A```: hex digits
+    /// have to match, while ```X```, ```Y```, ```N``` and ```K``` match anything.
+    pub fn from_opcode_class(class : &str) -> Option<Breakpoint> {
+        if class.chars().count() != 4 {
+            return None;
+        }
+        let mut pattern = 0;
+        let mut mask = 0;
+        for c in class.chars() {
+            pattern <<= 4;
+            mask <<= 4;
+            match c.to_digit(16) {
+                Some(digit) => {
+                    pattern |= digit as u16;
+                    mask |= 0xF;
+                },
+                None if "xXyYnNkK".contains(c) => {},
+                None => return None,
+            }
+        }
+        Some(Breakpoint::Opcode { pattern, mask })
+    }
+
+    pub fn matches(&self, pc : u16, opcode : u16) -> bool {
+        match *self {
+            Breakpoint::Address(addr) => addr == pc,
+            Breakpoint::Opcode { pattern, mask } => opcode & mask == pattern,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Address(addr) => write!(f, "{:#05X}", addr),
+            Breakpoint::Opcode { pattern, mask } => {
+                for shift in [12, 8, 4, 0].iter() {
+                    if (mask >> shift) & 0xF == 0 {
+                        write!(f, "?")?;
+                    }
+                    else {
+                        write!(f, "{:X}", (pattern >> shift) & 0xF)?;
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+///
+/// Why the debugger stopped the program.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+
+    ///
+    /// The debugger was just started, or told to pause.
+    Paused,
+    Step,
+    Breakpoint(usize),
+
+    ///
+    /// A ```next``` stepped over a call, or a ```finish``` returned from one.
+    Returned,
+}
+
+///
+/// How the program runs until the debugger next stops it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RunMode {
+    Paused,
+
+    ///
+    /// Stops once this many more instructions have run.
+    Step(usize),
+    Continue,
+    StepOver { return_pc : u16, sp : usize },
+    Finish { sp : usize },
+}
+
+///
+/// Decides when to stop a running program. The debugger doesn't run anything
+/// itself; the frontend calls ```check``` before each instruction it runs,
+/// and stops to inspect the CPU whenever a reason is returned.
+pub struct Debugger {
+    pub breakpoints : Vec<Option<Breakpoint>>,
+    pub labels : BTreeMap<String, u16>,
+    mode : RunMode,
+
+    ///
+    /// Set right after resuming, so the instruction the program stopped at
+    /// runs instead of hitting its own breakpoint again.
+    resuming : bool,
+
+    ///
+    /// Where the program was resumed from, until it moves on. Instructions
+    /// such as ```FX0A``` run again until they finish, and shouldn't hit
+    /// their own breakpoint each time they do.
+    resume_pc : Option<u16>,
+}
+
+impl Debugger {
+
+    ///
+    /// Creates a debugger that stops before the first instruction.
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints : Vec::new(),
+            labels : BTreeMap::new(),
+            mode : RunMode::Paused,
+            resuming : false,
+            resume_pc : None,
+        }
+    }
+
+    ///
+    /// Uses the labels from an assembly or symbol file for addresses.
+    pub fn load_symbols(&mut self, symbols : &Assembly) {
+        self.labels = symbols.labels.clone();
+    }
+
+    ///
+    /// Adds a breakpoint, returning its number.
+    pub fn add_breakpoint(&mut self, breakpoint : Breakpoint) -> usize {
+        self.breakpoints.push(Some(breakpoint));
+        self.breakpoints.len() - 1
+    }
+
+    ///
+    /// Removes breakpoint number ```idx```, returning it if it existed.
+    pub fn remove_breakpoint(&mut self, idx : usize) -> Option<Breakpoint> {
+        self.breakpoints.get_mut(idx).and_then(|breakpoint| breakpoint.take())
+    }
+
+    ///
+    /// The name of the label at ```addr```, if there is one.
+    pub fn label_at(&self, addr : u16) -> Option<&str> {
+        self.labels.iter().find(|&(_, label_addr)| *label_addr == addr).map(|(name, _)| name.as_str())
+    }
+
+    ///
+    /// Checks if the program should stop before running the instruction at
+    /// the program counter.
+    pub fn check<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &InterpretedCpu<D, A, I>) -> Option<StopReason> {
+        let pc = cpu.pc;
+        if self.resume_pc != Some(pc) {
+            self.resume_pc = None;
+        }
+        if self.resuming {
+            self.resuming = false;
+            return None;
+        }
+        let opcode = instruction_word(cpu, pc);
+        let hit = self.breakpoints.iter().position(|breakpoint| breakpoint.map(|bp| bp.matches(pc, opcode)).unwrap_or(false));
+        if let (Some(idx), None) = (hit, self.resume_pc) {
+            self.mode = RunMode::Paused;
+            return Some(StopReason::Breakpoint(idx));
+        }
+        let reason = match self.mode {
+            RunMode::Paused => Some(StopReason::Paused),
+            RunMode::Step(remaining) if remaining <= 1 => Some(StopReason::Step),
+            RunMode::Step(remaining) => {
+                self.mode = RunMode::Step(remaining - 1);
+                None
+            },
+            RunMode::Continue => None,
+            RunMode::StepOver { return_pc, sp } if pc == return_pc && cpu.sp == sp => Some(StopReason::Returned),
+            RunMode::Finish { sp } if cpu.sp < sp => Some(StopReason::Returned),
+            RunMode::StepOver { .. } | RunMode::Finish { .. } => None,
+        };
+        if reason.is_some() {
+            self.mode = RunMode::Paused;
+        }
+        reason
+    }
+
+    ///
+    /// Stops before the next instruction, wherever the program is.
+    pub fn pause(&mut self) {
+        self.mode = RunMode::Paused;
+        self.resuming = false;
+    }
+
+    ///
+    /// Runs one instruction.
+    pub fn step<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &InterpretedCpu<D, A, I>) {
+        self.step_many(cpu, 1);
+    }
+
+    ///
+    /// Runs ```count``` instructions.
+    pub fn step_many<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &InterpretedCpu<D, A, I>, count : usize) {
+        self.resume(RunMode::Step(count), cpu.pc);
+    }
+
+    ///
+    /// Runs one instruction, running the whole subroutine if it is a call.
+    pub fn next<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &InterpretedCpu<D, A, I>) {
+        match Instruction::decode(instruction_word(cpu, cpu.pc)) {
+            Ok(Instruction::Call(_)) => self.resume(RunMode::StepOver { return_pc : cpu.pc.wrapping_add(2), sp : cpu.sp }, cpu.pc),
+            _ => self.step(cpu),
+        }
+    }
+
+    ///
+    /// Runs until the current subroutine returns.
+    pub fn finish<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &InterpretedCpu<D, A, I>) {
+        self.resume(RunMode::Finish { sp : cpu.sp }, cpu.pc);
+    }
+
+    ///
+    /// Runs until a breakpoint is hit.
+    pub fn cont<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &InterpretedCpu<D, A, I>) {
+        self.resume(RunMode::Continue, cpu.pc);
+    }
+
+    fn resume(&mut self, mode : RunMode, pc : u16) {
+        self.mode = mode;
+        self.resuming = true;
+        self.resume_pc = Some(pc);
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+///
+/// What a remote frontend wants done with the program once it hands it back.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RemoteAction {
+
+    ///
+    /// Keep running, stopping whenever the ```Debugger``` says to.
+    Resume,
+
+    ///
+    /// The frontend is gone, but the program should run on without it.
+    Detach,
+    Kill,
+}
+
+///
+/// A debugger frontend on the other end of a connection, such as GDB or an
+/// editor. The frontend only drives the ```Debugger```; the emulator still
+/// runs the instructions and asks it when to stop.
+pub trait RemoteDebugger<D, A, I> {
+
+    ///
+    /// Reports that the program stopped for ```reason```, or failed, then
+    /// answers requests until the frontend resumes, detaches, or kills it.
+    fn stopped(&mut self, debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>, reason : Result<StopReason, Chip8Error>) -> io::Result<RemoteAction>;
+
+    ///
+    /// Handles anything the frontend sent while the program was running,
+    /// without waiting for more. Pausing is done through the ```Debugger```.
+    fn poll(&mut self, debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>) -> io::Result<RemoteAction>;
+
+    ///
+    /// Tells the frontend the program has finished.
+    fn exited(&mut self) -> io::Result<()>;
+}
+
+///
+/// The 2 bytes at ```addr```, or 0 past the end of memory.
+pub fn instruction_word<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &InterpretedCpu<D, A, I>, addr : u16) -> u16 {
+    let addr = addr as usize;
+    match (cpu.memory.get(addr), cpu.memory.get(addr + 1)) {
+        (Some(&high), Some(&low)) => (high as u16) << 8 | low as u16,
+        _ => 0,
+    }
+}
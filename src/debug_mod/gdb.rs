@@ -0,0 +1,387 @@
+use chip8_mod::InterpretedCpu;
+use chip8_mod::display::DisplayOutput;
+use chip8_mod::audio::AudioOutput;
+use chip8_mod::input::InputReciever;
+use chip8_mod::error::Chip8Error;
+use debug_mod::debugger::{Debugger, Breakpoint, StopReason, RemoteDebugger, RemoteAction};
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+///
+/// The signal numbers sent to GDB in stop replies.
+const SIGINT : u8 = 2;
+const SIGILL : u8 = 4;
+const SIGTRAP : u8 = 5;
+const SIGSEGV : u8 = 11;
+
+///
+/// Registers, in the order GDB numbers them and ```g``` sends them.
+/// Each entry is a name and a size in bytes; multi-byte registers are little endian.
+pub const REGISTERS : [(&str, usize) ; 21] = [
+    ("v0", 1), ("v1", 1), ("v2", 1), ("v3", 1), ("v4", 1), ("v5", 1), ("v6", 1), ("v7", 1),
+    ("v8", 1), ("v9", 1), ("va", 1), ("vb", 1), ("vc", 1), ("vd", 1), ("ve", 1), ("vf", 1),
+    ("i", 2), ("pc", 2), ("sp", 1), ("dt", 1), ("st", 1),
+];
+
+const PC_REGISTER : usize = 17;
+
+///
+/// Builds the target description GDB asks for, describing ```REGISTERS```.
+pub fn target_xml() -> String {
+    let mut retval = String::from("<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n<feature name=\"org.chip8.core\">\n");
+    for (idx, &(name, size)) in REGISTERS.iter().enumerate() {
+        let kind = match name {
+            "pc" => "code_ptr",
+            "i" => "data_ptr",
+            _ => "uint8",
+        };
+        retval.push_str(&format!("<reg name=\"{}\" bitsize=\"{}\" regnum=\"{}\" type=\"{}\"/>\n", name, size * 8, idx, kind));
+    }
+    retval.push_str("</feature>\n</target>\n");
+    retval
+}
+
+///
+/// What the frontend should do after a packet.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GdbAction {
+
+    ///
+    /// Send this reply and wait for the next packet.
+    Reply(String),
+
+    ///
+    /// The debugger has been told how to run; go back to running instructions.
+    Resume,
+
+    ///
+    /// GDB is done with the program but it should keep running.
+    Detach,
+    Kill,
+}
+
+///
+/// The stop reply GDB expects when the program stops for ```reason```, or fails.
+pub fn stop_reply(reason : Result<StopReason, Chip8Error>) -> String {
+    let signal = match reason {
+        Ok(StopReason::Paused) => SIGINT,
+        Ok(StopReason::Step) | Ok(StopReason::Breakpoint(_)) | Ok(StopReason::Returned) => SIGTRAP,
+        Err(Chip8Error::BadOpcode { .. }) | Err(Chip8Error::UnsupportedOpcode { .. }) => SIGILL,
+        Err(_) => SIGSEGV,
+    };
+    format!("S{:02x}", signal)
+}
+
+///
+/// Wraps packet data in GDB's ```$data#checksum``` framing.
+pub fn frame_packet(data : &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+}
+
+///
+/// Answers a single packet from GDB, without its framing.
+pub fn handle_packet<D : DisplayOutput, A : AudioOutput, I : InputReciever>(debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>, packet : &str) -> GdbAction {
+    let reply = |text : &str| GdbAction::Reply(text.to_owned());
+    let (command, args) = packet.split_at(packet.chars().next().map(|c| c.len_utf8()).unwrap_or(0));
+    match command {
+        "?" => GdbAction::Reply(format!("S{:02x}", SIGTRAP)),
+        "g" => {
+            let values : Vec<String> = (0 .. REGISTERS.len()).map(|idx| to_hex(&read_register(cpu, idx))).collect();
+            GdbAction::Reply(values.concat())
+        },
+        "G" => {
+            let mut bytes = match from_hex(args) {
+                Some(bytes) => bytes,
+                None => return reply("E01"),
+            };
+            for (idx, &(_, size)) in REGISTERS.iter().enumerate() {
+                if bytes.len() < size {
+                    return reply("E01");
+                }
+                let rest = bytes.split_off(size);
+                write_register(cpu, idx, &bytes);
+                bytes = rest;
+            }
+            reply("OK")
+        },
+        "p" => match usize::from_str_radix(args, 16) {
+            Ok(idx) if idx < REGISTERS.len() => GdbAction::Reply(to_hex(&read_register(cpu, idx))),
+            _ => reply("E01"),
+        },
+        "P" => {
+            let mut parts = args.splitn(2, '=');
+            let idx = parts.next().and_then(|idx| usize::from_str_radix(idx, 16).ok());
+            let value = parts.next().and_then(from_hex);
+            match (idx, value) {
+                (Some(idx), Some(ref value)) if idx < REGISTERS.len() && value.len() == REGISTERS[idx].1 => {
+                    write_register(cpu, idx, value);
+                    reply("OK")
+                },
+                _ => reply("E01"),
+            }
+        },
+        "m" => match parse_range(args) {
+            Some((addr, len)) if addr <= cpu.memory.len() && len <= cpu.memory.len() - addr => GdbAction::Reply(to_hex(&cpu.memory[addr .. addr + len])),
+            _ => reply("E01"),
+        },
+        "M" => {
+            let mut parts = args.splitn(2, ':');
+            let range = parts.next().and_then(parse_range);
+            let data = parts.next().and_then(from_hex);
+            match (range, data) {
+                (Some((addr, len)), Some(data)) if data.len() == len && addr <= cpu.memory.len() && len <= cpu.memory.len() - addr => {
+                    cpu.memory[addr .. addr + len].copy_from_slice(&data);
+                    reply("OK")
+                },
+                _ => reply("E01"),
+            }
+        },
+        "Z" | "z" => {
+            let mut parts = args.split(',');
+            let kind = parts.next();
+            let addr = parts.next().and_then(|addr| u16::from_str_radix(addr, 16).ok());
+            match (kind, addr) {
+                (Some("0"), Some(addr)) | (Some("1"), Some(addr)) => {
+                    let existing = debugger.breakpoints.iter().position(|bp| *bp == Some(Breakpoint::Address(addr)));
+                    match (command, existing) {
+                        ("Z", None) => { debugger.add_breakpoint(Breakpoint::Address(addr)); },
+                        ("z", Some(idx)) => { debugger.remove_breakpoint(idx); },
+                        _ => {},
+                    }
+                    reply("OK")
+                },
+                // Watchpoints aren't supported
+                _ => reply(""),
+            }
+        },
+        "s" | "c" => {
+            if !args.is_empty() {
+                match u16::from_str_radix(args, 16) {
+                    Ok(addr) => cpu.pc = addr,
+                    Err(_) => return reply("E01"),
+                }
+            }
+            if command == "s" {
+                debugger.step(cpu);
+            }
+            else {
+                debugger.cont(cpu);
+            }
+            GdbAction::Resume
+        },
+        "D" => GdbAction::Detach,
+        "k" => GdbAction::Kill,
+        "H" => reply("OK"),
+        "q" | "Q" => handle_query(packet),
+        _ => reply(""),
+    }
+}
+
+///
+/// Answers the ```q``` and ```Q``` packets GDB uses to find out about the target.
+fn handle_query(packet : &str) -> GdbAction {
+    if packet.starts_with("qSupported") {
+        return GdbAction::Reply("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_owned());
+    }
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        let xml = target_xml();
+        let range = parse_range(range);
+        return match range {
+            Some((offset, _)) if offset >= xml.len() => GdbAction::Reply("l".to_owned()),
+            Some((offset, len)) if offset.saturating_add(len) >= xml.len() => GdbAction::Reply(format!("l{}", &xml[offset ..])),
+            Some((offset, len)) => GdbAction::Reply(format!("m{}", &xml[offset .. offset + len])),
+            None => GdbAction::Reply("E01".to_owned()),
+        };
+    }
+    let reply = match packet {
+        "QStartNoAckMode" => "OK",
+        "qAttached" => "1",
+        "qC" => "QC1",
+        "qfThreadInfo" => "m1",
+        "qsThreadInfo" => "l",
+        _ => "",
+    };
+    GdbAction::Reply(reply.to_owned())
+}
+
+fn read_register<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &InterpretedCpu<D, A, I>, idx : usize) -> Vec<u8> {
+    match idx {
+        0 ..= 15 => vec![cpu.registerV[idx]],
+        16 => vec![cpu.I as u8, (cpu.I >> 8) as u8],
+        PC_REGISTER => vec![cpu.pc as u8, (cpu.pc >> 8) as u8],
+        18 => vec![cpu.sp as u8],
+        19 => vec![cpu.timers.delay()],
+        _ => vec![cpu.timers.sound()],
+    }
+}
+
+fn write_register<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &mut InterpretedCpu<D, A, I>, idx : usize, value : &[u8]) {
+    let word = value[0] as u16 | (*value.get(1).unwrap_or(&0) as u16) << 8;
+    match idx {
+        0 ..= 15 => cpu.registerV[idx] = value[0],
+        16 => cpu.I = word,
+        PC_REGISTER => cpu.pc = word,
+        18 => cpu.sp = (value[0] as usize).min(cpu.stack.len()),
+        19 => cpu.timers.set_delay(value[0]),
+        _ => cpu.timers.set_sound(value[0]),
+    }
+}
+
+///
+/// Parses the ```addr,len``` hex pair used by memory and transfer packets.
+fn parse_range(text : &str) -> Option<(usize, usize)> {
+    let mut parts = text.splitn(2, ',');
+    let addr = parts.next().and_then(|addr| usize::from_str_radix(addr, 16).ok())?;
+    let len = parts.next().and_then(|len| usize::from_str_radix(len, 16).ok())?;
+    Some((addr, len))
+}
+
+fn to_hex(bytes : &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text : &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0 .. text.len()).step_by(2).map(|idx| u8::from_str_radix(&text[idx .. idx + 2], 16).ok()).collect()
+}
+
+///
+/// A connection to GDB over TCP.
+///
+/// Like the command-line debugger, the stub only answers packets while the
+/// program is stopped; the ```Debugger``` passed in decides when that is.
+pub struct GdbStub {
+    reader : BufReader<TcpStream>,
+    writer : TcpStream,
+    no_ack : bool,
+
+    ///
+    /// Whether GDB last resumed the program, and so is waiting for a stop reply.
+    running : bool,
+}
+
+impl GdbStub {
+
+    ///
+    /// Waits on ```port``` of the local machine for GDB to connect.
+    pub fn listen(port : u16) -> io::Result<GdbStub> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let (stream, _) = listener.accept()?;
+        GdbStub::new(stream)
+    }
+
+    pub fn new(stream : TcpStream) -> io::Result<GdbStub> {
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            reader : BufReader::new(stream.try_clone()?),
+            writer : stream,
+            no_ack : false,
+            running : false,
+        })
+    }
+
+    ///
+    /// Checks, without waiting, if GDB has asked to interrupt the running program.
+    fn check_interrupt(&mut self) -> bool {
+        if self.writer.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let interrupted = match self.reader.fill_buf() {
+            Ok(buf) => buf.first() == Some(&0x03),
+            Err(_) => false,
+        };
+        if interrupted {
+            self.reader.consume(1);
+        }
+        let _ = self.writer.set_nonblocking(false);
+        interrupted
+    }
+
+    ///
+    /// Reads the next packet, acknowledging it. Returns ```None``` for bare
+    /// acknowledgements and interrupts, which need no answer while stopped.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        let mut byte = [0u8];
+        loop {
+            self.reader.read_exact(&mut byte)?;
+            match byte[0] {
+                b'$' => break,
+                b'+' | b'-' | 0x03 => return Ok(None),
+                _ => {},
+            }
+        }
+        let mut data = Vec::new();
+        self.reader.read_until(b'#', &mut data)?;
+        data.pop();
+        let mut checksum = [0u8 ; 2];
+        self.reader.read_exact(&mut checksum)?;
+        let expected = String::from_utf8_lossy(&checksum);
+        let actual = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        if !self.no_ack {
+            let valid = u8::from_str_radix(&expected, 16).map(|expected| expected == actual).unwrap_or(false);
+            self.writer.write_all(if valid { b"+" } else { b"-" })?;
+            if !valid {
+                return Ok(None);
+            }
+        }
+        Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    fn send_packet(&mut self, data : &str) -> io::Result<()> {
+        self.writer.write_all(frame_packet(data).as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl <D : DisplayOutput, A : AudioOutput, I : InputReciever> RemoteDebugger<D, A, I> for GdbStub {
+
+    ///
+    /// Tells GDB why the program stopped, then answers packets until GDB
+    /// resumes, detaches from, or kills the program.
+    fn stopped(&mut self, debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>, reason : Result<StopReason, Chip8Error>) -> io::Result<RemoteAction> {
+        if self.running {
+            self.send_packet(&stop_reply(reason))?;
+            self.running = false;
+        }
+        loop {
+            let packet = match self.read_packet()? {
+                Some(packet) => packet,
+                None => continue,
+            };
+            match handle_packet(debugger, cpu, &packet) {
+                GdbAction::Reply(text) => {
+                    self.send_packet(&text)?;
+                    if packet == "QStartNoAckMode" {
+                        self.no_ack = true;
+                    }
+                },
+                GdbAction::Resume => {
+                    self.running = true;
+                    return Ok(RemoteAction::Resume);
+                },
+                GdbAction::Detach => {
+                    self.send_packet("OK")?;
+                    return Ok(RemoteAction::Detach);
+                },
+                GdbAction::Kill => return Ok(RemoteAction::Kill),
+            }
+        }
+    }
+
+    ///
+    /// GDB only sends a Ctrl-C while the program runs, which pauses it.
+    fn poll(&mut self, debugger : &mut Debugger, _cpu : &mut InterpretedCpu<D, A, I>) -> io::Result<RemoteAction> {
+        if self.check_interrupt() {
+            debugger.pause();
+        }
+        Ok(RemoteAction::Resume)
+    }
+
+    fn exited(&mut self) -> io::Result<()> {
+        self.send_packet("W00")
+    }
+}
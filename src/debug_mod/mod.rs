@@ -0,0 +1,313 @@
+//!
+//! Code related to debugging running ROMs.
+//!
+//! The ```Debugger``` decides when a running program should stop, while the
+//! ```cli``` module reads commands to inspect and change the stopped CPU.
+//! The ```gdb``` and ```dap``` modules do the same for GDB and for editors,
+//! over their own protocols. None of them run instructions themselves, so any
+//! frontend can drive them. The ```trace``` module records the instructions a
+//! frontend runs to a file.
+
+pub mod debugger;
+pub mod cli;
+pub mod gdb;
+pub mod dap;
+pub mod trace;
+
+#[cfg(test)]
+mod tests {
+    use super::debugger::{Debugger, Breakpoint, StopReason};
+    use super::cli::{self, CommandResult};
+    use super::gdb::{self, GdbAction};
+    use super::dap::{self, DapSession, Symbols};
+    use super::debugger::RemoteAction;
+    use super::trace::{self, TraceWriter, TraceOptions, TraceFormat};
+    use asm_mod::assembler;
+    use serde_json::Value;
+    use std::path::PathBuf;
+    use chip8_mod::InterpretedCpu;
+    use chip8_mod::cpu::OpcodeExecuter;
+    use chip8_mod::platform::Platform;
+    use chip8_mod::display::{DisplayOutput, ScreenBuffer};
+    use chip8_mod::audio::{AudioOutput, AudioTimer};
+    use chip8_mod::input::InputReciever;
+    use headless_mod::{self, HeadlessRunner, RunLimits};
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    ///
+    /// Runs instructions at 600 Hz until the debugger stops, returning why.
+    fn run_until_stop<D : DisplayOutput, A : AudioOutput, I : InputReciever>(debugger : &mut Debugger, cpu : &mut InterpretedCpu<D, A, I>) -> StopReason {
+        loop {
+            if let Some(reason) = debugger.check(cpu) {
+                return reason;
+            }
+            cpu.step().unwrap();
+            cpu.tick(1000 * 1000 * 1000 / 600);
+        }
+    }
+
+    #[test]
+    fn test_breakpoint_parsing() {
+        assert_eq!(Breakpoint::from_opcode_class("DXYN"), Some(Breakpoint::Opcode { pattern : 0xD000, mask : 0xF000 }));
+        assert_eq!(Breakpoint::from_opcode_class("fx0a"), Some(Breakpoint::Opcode { pattern : 0xF00A, mask : 0xF0FF }));
+        assert_eq!(Breakpoint::from_opcode_class("DXY"), None);
+        assert_eq!(Breakpoint::from_opcode_class("GXYN"), None);
+        assert!(Breakpoint::from_opcode_class("FX0A").unwrap().matches(0x200, 0xF30A));
+        assert!(!Breakpoint::from_opcode_class("FX0A").unwrap().matches(0x200, 0xF315));
+        assert_eq!(Breakpoint::from_opcode_class("FX0A").unwrap().to_string(), "F?0A");
+    }
+
+    #[test]
+    fn test_debugger() {
+        let mut runner = HeadlessRunner::new();
+        let mut cpu = InterpretedCpu::new(
+            Platform::CosmacVip,
+            ScreenBuffer::new(&mut runner.video),
+            AudioTimer::new(&mut runner.audio),
+            &mut runner.keys
+        );
+        cpu.load_rom(&chip8_asm! {
+                ld v0, 1;
+                call sub;
+                ld v2, 3;
+            halt:
+                jp halt;
+            sub:
+                ld v1, 2;
+                drw v0, v0, 1;
+                ret;
+        }).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.labels.insert("sub".to_owned(), 0x208);
+
+        assert_eq!(run_until_stop(&mut debugger, &mut cpu), StopReason::Paused);
+        assert_eq!(cpu.pc, 0x200);
+        debugger.step(&cpu);
+        assert_eq!(run_until_stop(&mut debugger, &mut cpu), StopReason::Step);
+        assert_eq!(cpu.pc, 0x202);
+
+        // Stepping over the call runs the whole subroutine.
+        debugger.next(&cpu);
+        assert_eq!(run_until_stop(&mut debugger, &mut cpu), StopReason::Returned);
+        assert_eq!((cpu.pc, cpu.sp, cpu.registerV[1]), (0x204, 0, 2));
+
+        // Opcode class and label breakpoints.
+        cpu.pc = 0x200;
+        assert_eq!(cli::execute(&mut debugger, &mut cpu, "break DXYN"), Ok(CommandResult::Output("Breakpoint 0 at D???".to_owned())));
+        assert_eq!(cli::execute(&mut debugger, &mut cpu, "c"), Ok(CommandResult::Resume));
+        assert_eq!(run_until_stop(&mut debugger, &mut cpu), StopReason::Breakpoint(0));
+        assert_eq!(cpu.pc, 0x20A);
+        assert!(cli::execute(&mut debugger, &mut cpu, "stack").unwrap() == CommandResult::Output("s0: called from 0x202 (sub)".to_owned()));
+        assert_eq!(cli::execute(&mut debugger, &mut cpu, "finish"), Ok(CommandResult::Resume));
+        assert_eq!(run_until_stop(&mut debugger, &mut cpu), StopReason::Returned);
+        assert_eq!(cpu.pc, 0x204);
+
+        cli::execute(&mut debugger, &mut cpu, "delete 0").unwrap();
+        cli::execute(&mut debugger, &mut cpu, "b sub").unwrap();
+        cpu.pc = 0x200;
+        debugger.cont(&cpu);
+        assert_eq!(run_until_stop(&mut debugger, &mut cpu), StopReason::Breakpoint(1));
+        assert_eq!(cpu.pc, 0x208);
+
+        // Editing registers and memory.
+        cli::execute(&mut debugger, &mut cpu, "set vA 0x42").unwrap();
+        cli::execute(&mut debugger, &mut cpu, "set i 0x300").unwrap();
+        assert_eq!((cpu.registerV[0xA], cpu.I), (0x42, 0x300));
+        assert!(cli::execute(&mut debugger, &mut cpu, "set v0 256").is_err());
+        assert_eq!(cli::execute(&mut debugger, &mut cpu, "write 0x300 1 0x2 0b11"), Ok(CommandResult::Output("0x300: 01 02 03".to_owned())));
+        assert_eq!(&cpu.memory[0x300 .. 0x303], &[1, 2, 3]);
+
+        let listing = match cli::execute(&mut debugger, &mut cpu, "dis sub 2") {
+            Ok(CommandResult::Output(text)) => text,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(listing.lines().next().unwrap(), "=> 0x208: 6102  v1 := 0x02  : sub");
+        assert!(cli::execute(&mut debugger, &mut cpu, "bogus").is_err());
+        assert_eq!(cli::execute(&mut debugger, &mut cpu, "quit"), Ok(CommandResult::Quit));
+    }
+
+    #[test]
+    fn test_gdb_packets() {
+        let mut runner = HeadlessRunner::new();
+        let mut cpu = InterpretedCpu::new(
+            Platform::CosmacVip,
+            ScreenBuffer::new(&mut runner.video),
+            AudioTimer::new(&mut runner.audio),
+            &mut runner.keys
+        );
+        cpu.load_rom(&chip8_asm! {
+                ld v3, 0x12;
+                ld i, 0x345;
+                add v3, 1;
+        }).unwrap();
+        let mut debugger = Debugger::new();
+        let reply = |text : &str| GdbAction::Reply(text.to_owned());
+
+        assert_eq!(gdb::frame_packet("OK"), "$OK#9a");
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "?"), reply("S05"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "Z0,202,2"), reply("OK"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "c"), GdbAction::Resume);
+        assert_eq!(run_until_stop(&mut debugger, &mut cpu), StopReason::Breakpoint(0));
+
+        // V0-VF, then I, PC, SP, DT and ST.
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "g"), reply("0000001200000000000000000000000000000202000000"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "p11"), reply("0202"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "P3=40"), reply("OK"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "s"), GdbAction::Resume);
+        assert_eq!(run_until_stop(&mut debugger, &mut cpu), StopReason::Step);
+        assert_eq!((cpu.registerV[3], cpu.I), (0x40, 0x345));
+
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "m200,4"), reply("6312a345"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "M300,2:beef"), reply("OK"));
+        assert_eq!(&cpu.memory[0x300 .. 0x302], &[0xBE, 0xEF]);
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "m fff,2"), reply("E01"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "mffffffffffffffff,1"), reply("E01"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "M1,ffffffffffffffff:00"), reply("E01"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "z0,202,2"), reply("OK"));
+        assert_eq!(debugger.breakpoints, vec![None]);
+
+        let xml = gdb::target_xml();
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" regnum=\"17\" type=\"code_ptr\"/>"));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "qXfer:features:read:target.xml:0,10"), GdbAction::Reply(format!("m{}", &xml[.. 0x10])));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "qXfer:features:read:target.xml:10,ffffffffffffffff"), GdbAction::Reply(format!("l{}", &xml[0x10 ..])));
+        assert_eq!(gdb::handle_packet(&mut debugger, &mut cpu, "k"), GdbAction::Kill);
+    }
+
+    #[test]
+    fn test_dap_session() {
+        let mut assembly = assembler::assemble_source(": main\n  v0 := 1\n\n  sub\n: again\n  jump again\n: sub\n  v1 := 2\n  return\n").unwrap();
+        assembly.files = vec!["game.8o".to_owned()];
+        let mut runner = HeadlessRunner::new();
+        let mut cpu = InterpretedCpu::new(
+            Platform::CosmacVip,
+            ScreenBuffer::new(&mut runner.video),
+            AudioTimer::new(&mut runner.audio),
+            &mut runner.keys
+        );
+        cpu.load_rom(&assembly.rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.load_symbols(&assembly);
+        let mut session = DapSession::new(Some(Symbols { assembly, dir : PathBuf::from("/nonexistent") }));
+        let request = |session : &mut DapSession, debugger : &mut Debugger, cpu : &mut InterpretedCpu<_, _, _>, command : &str, arguments : Value| {
+            let action = session.handle_request(debugger, cpu, &json!({ "seq" : 1, "type" : "request", "command" : command, "arguments" : arguments }));
+            (action, session.outgoing.drain(..).collect::<Vec<Value>>())
+        };
+
+        let (action, messages) = request(&mut session, &mut debugger, &mut cpu, "initialize", json!({}));
+        assert_eq!(action, None);
+        assert_eq!(messages[0]["body"]["supportsReadMemoryRequest"], true);
+        assert_eq!(messages[1]["event"], "initialized");
+
+        // Line 3 is blank, so the breakpoint moves to the call on line 4.
+        let (_, messages) = request(&mut session, &mut debugger, &mut cpu, "setBreakpoints", json!({ "source" : { "path" : "/elsewhere/game.8o" }, "breakpoints" : [{ "line" : 3 }, { "line" : 20 }] }));
+        assert_eq!(messages[0]["body"]["breakpoints"][0]["line"], 4);
+        assert_eq!(messages[0]["body"]["breakpoints"][0]["verified"], true);
+        assert_eq!(messages[0]["body"]["breakpoints"][1]["verified"], false);
+        assert_eq!(debugger.breakpoints, vec![Some(Breakpoint::Address(0x202))]);
+
+        let (action, _) = request(&mut session, &mut debugger, &mut cpu, "configurationDone", json!({}));
+        assert_eq!(action, Some(RemoteAction::Resume));
+        let reason = run_until_stop(&mut debugger, &mut cpu);
+        assert_eq!(reason, StopReason::Breakpoint(0));
+        session.stopped(&debugger, Ok(reason));
+        let stopped = session.outgoing.pop().unwrap();
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+        let (action, _) = request(&mut session, &mut debugger, &mut cpu, "stepIn", json!({ "threadId" : 1 }));
+        assert_eq!(action, Some(RemoteAction::Resume));
+        run_until_stop(&mut debugger, &mut cpu);
+        let (_, messages) = request(&mut session, &mut debugger, &mut cpu, "stackTrace", json!({ "threadId" : 1 }));
+        let frames = &messages[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "sub");
+        assert_eq!(frames[0]["line"], 8);
+        assert_eq!(frames[1]["name"], "main+0x2");
+        assert_eq!(frames[1]["line"], 4);
+
+        let (_, messages) = request(&mut session, &mut debugger, &mut cpu, "variables", json!({ "variablesReference" : 1 }));
+        assert_eq!(messages[0]["body"]["variables"][0], json!({ "name" : "V0", "value" : "0x01", "type" : "u8", "variablesReference" : 0 }));
+        let (_, messages) = request(&mut session, &mut debugger, &mut cpu, "setVariable", json!({ "variablesReference" : 1, "name" : "VA", "value" : "0x42" }));
+        assert_eq!(messages[0]["body"]["value"], "0x42");
+        assert_eq!(cpu.registerV[0xA], 0x42);
+
+        let (_, messages) = request(&mut session, &mut debugger, &mut cpu, "writeMemory", json!({ "memoryReference" : "0x300", "data" : dap::base64_encode(b"chip8") }));
+        assert_eq!(messages[0]["body"]["bytesWritten"], 5);
+        let (_, messages) = request(&mut session, &mut debugger, &mut cpu, "readMemory", json!({ "memoryReference" : "0x300", "offset" : 1, "count" : 3 }));
+        assert_eq!(messages[0]["body"]["data"], dap::base64_encode(b"hip"));
+        assert_eq!(dap::base64_decode("aGlw"), Some(b"hip".to_vec()));
+        let (_, messages) = request(&mut session, &mut debugger, &mut cpu, "readMemory", json!({ "memoryReference" : "0xFFF", "count" : u64::MAX }));
+        assert_eq!(messages[0]["body"]["data"], dap::base64_encode(&cpu.memory[0xFFF ..]));
+        let (_, messages) = request(&mut session, &mut debugger, &mut cpu, "writeMemory", json!({ "memoryReference" : "0x300", "offset" : i64::MAX, "data" : "AA==" }));
+        assert_eq!(messages[0]["success"], json!(false));
+
+        let (action, messages) = request(&mut session, &mut debugger, &mut cpu, "evaluate", json!({ "expression" : "v0" }));
+        assert_eq!((action, &messages[0]["success"]), (None, &json!(false)));
+        let (action, _) = request(&mut session, &mut debugger, &mut cpu, "disconnect", json!({}));
+        assert_eq!(action, Some(RemoteAction::Detach));
+    }
+
+    ///
+    /// A trace output the test can still read after handing it to the writer.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, data : &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    ///
+    /// Runs a short ROM to its halt loop, tracing it with ```options```.
+    fn trace_rom(options : TraceOptions) -> Vec<u8> {
+        let buffer = SharedBuffer::default();
+        let mut tracer = TraceWriter::new(Box::new(buffer.clone()), options).unwrap();
+        let mut runner = HeadlessRunner::new();
+        let mut cpu = InterpretedCpu::new(
+            Platform::CosmacVip,
+            ScreenBuffer::new(&mut runner.video),
+            AudioTimer::new(&mut runner.audio),
+            &mut runner.keys
+        );
+        cpu.load_rom(&chip8_asm! {
+                ld v0, 123;
+                ld i, 0x300;
+                ld b, v0;
+                ld dt, v0;
+            halt:
+                jp halt;
+        }).unwrap();
+        let limits = RunLimits { clock_hz : 600, until_pc : Some(0x208), ..RunLimits::default() };
+        headless_mod::run_traced(&mut cpu, &limits, Some(&mut tracer));
+        let bytes = buffer.0.borrow().clone();
+        bytes
+    }
+
+    #[test]
+    fn test_trace() {
+        let text = String::from_utf8(trace_rom(TraceOptions::default())).unwrap();
+        let lines : Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], json!({ "pc" : 0x200, "opcode" : 0x607B, "instruction" : "v0 := 0x7B", "registers" : { "v0" : 123 }, "memory" : [], "dt" : 0, "st" : 0 }));
+        assert_eq!(lines[1]["registers"], json!({ "i" : 0x300 }));
+        assert_eq!(lines[2]["memory"], json!([[0x300, 1], [0x301, 2], [0x302, 3]]));
+        assert_eq!(lines[3]["dt"], 123);
+
+        let options = TraceOptions { format : TraceFormat::Binary, range : trace::parse_range("0x204-0x206"), ..TraceOptions::default() };
+        let bytes = trace_rom(options);
+        assert_eq!(&bytes[.. 5], b"C8TR\x01");
+        assert_eq!(&bytes[5 .. 16], &[0x02, 0x04, 0xF0, 0x33, 0, 0, 0, 0, 3, 0x03, 0x00]);
+        assert_eq!(&bytes[bytes.len() - 9 ..], &[0x02, 0x06, 0xF0, 0x15, 123, 0, 0, 0, 0]);
+
+        let two_lines : u64 = text.lines().take(2).map(|line| line.len() as u64 + 1).sum();
+        let limited = trace_rom(TraceOptions { max_bytes : Some(two_lines + 1), ..TraceOptions::default() });
+        assert_eq!(limited.len() as u64, two_lines);
+        assert_eq!(trace::parse_range("0x300-0x200"), None);
+    }
+}
@@ -0,0 +1,270 @@
+use chip8_mod::InterpretedCpu;
+use chip8_mod::display::DisplayOutput;
+use chip8_mod::audio::AudioOutput;
+use chip8_mod::input::InputReciever;
+use chip8_mod::instruction::Instruction;
+use debug_mod::gdb::REGISTERS;
+use asm_mod::assembler::parse_number;
+use serde_json;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+///
+/// The bytes every binary trace starts with, followed by ```TRACE_VERSION```.
+pub const TRACE_MAGIC : &[u8 ; 4] = b"C8TR";
+pub const TRACE_VERSION : u8 = 1;
+
+///
+/// The register numbers used in binary traces; they follow ```gdb::REGISTERS```.
+const I_REGISTER : u8 = 16;
+const SP_REGISTER : u8 = 18;
+
+///
+/// How each traced instruction is written.
+///
+/// ## JSON LINES
+/// One object per line, such as
+/// ```{"pc":514,"opcode":41728,"instruction":"i := 0x300","registers":{"i":768},"memory":[],"dt":0,"st":0}```.
+/// ```registers``` holds the new value of every register the instruction changed,
+/// named as in ```gdb::REGISTERS```, and ```memory``` is a list of
+/// ```[address, value]``` pairs, one for each byte written.
+///
+/// ## BINARY
+/// ```TRACE_MAGIC``` and ```TRACE_VERSION```, then for each instruction, big endian:
+///
+/// * The PC and the opcode, 2 bytes each.
+/// * The delay and sound timers, 1 byte each.
+/// * The number of changed registers, 1 byte, then each one's number in
+///   ```gdb::REGISTERS``` (1 byte) and new value (2 bytes).
+/// * The number of bytes written to memory, 2 bytes, then each one's
+///   address (2 bytes) and value (1 byte).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraceFormat {
+    JsonLines,
+    Binary,
+}
+
+impl TraceFormat {
+    ///
+    /// Looks a format up by the name used on the command line.
+    pub fn from_name(name : &str) -> Option<TraceFormat> {
+        match name {
+            "json" => Some(TraceFormat::JsonLines),
+            "binary" => Some(TraceFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Which instructions get traced, and how.
+#[derive(Clone, Copy, Debug)]
+pub struct TraceOptions {
+    pub format : TraceFormat,
+
+    ///
+    /// Only instructions with a PC between these addresses, inclusive, are traced.
+    pub range : Option<(u16, u16)>,
+
+    ///
+    /// Tracing stops before the output grows past this many bytes.
+    pub max_bytes : Option<u64>,
+}
+
+impl Default for TraceOptions {
+    fn default() -> TraceOptions {
+        TraceOptions {
+            format : TraceFormat::JsonLines,
+            range : None,
+            max_bytes : None,
+        }
+    }
+}
+
+///
+/// The machine right before a traced instruction ran.
+struct PendingStep {
+    pc : u16,
+    opcode : u16,
+    registers : [u8 ; 16],
+    i : u16,
+    sp : usize,
+
+    ///
+    /// The first address and the length of the memory the instruction may write.
+    writes : (usize, usize),
+}
+
+///
+/// Records every instruction the CPU runs, along with what it changed.
+///
+/// Call ```before``` right before running an instruction and ```after``` once
+/// it has run; instructions outside the traced range are skipped.
+pub struct TraceWriter {
+    output : Box<dyn Write>,
+    options : TraceOptions,
+    pending : Option<PendingStep>,
+    written : u64,
+    full : bool,
+}
+
+impl TraceWriter {
+    pub fn new(mut output : Box<dyn Write>, options : TraceOptions) -> io::Result<TraceWriter> {
+        let mut written = 0;
+        if options.format == TraceFormat::Binary {
+            output.write_all(TRACE_MAGIC)?;
+            output.write_all(&[TRACE_VERSION])?;
+            written = TRACE_MAGIC.len() as u64 + 1;
+        }
+        Ok(TraceWriter {
+            output,
+            options,
+            pending : None,
+            written,
+            full : false,
+        })
+    }
+
+    ///
+    /// Creates a trace writing to the file at ```path```.
+    pub fn create<P : AsRef<Path>>(path : P, options : TraceOptions) -> io::Result<TraceWriter> {
+        let file = File::create(path)?;
+        TraceWriter::new(Box::new(BufWriter::new(file)), options)
+    }
+
+    ///
+    /// The number of bytes written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    ///
+    /// Whether the size limit has been reached, so nothing more will be written.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    ///
+    /// Remembers the state of the machine before the instruction at the PC runs.
+    pub fn before<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &InterpretedCpu<D, A, I>, opcode : u16) {
+        let in_range = self.options.range.map(|(start, end)| start <= cpu.pc && cpu.pc <= end).unwrap_or(true);
+        if self.full || !in_range {
+            self.pending = None;
+            return;
+        }
+        let writes = match Instruction::decode(opcode) {
+            Ok(Instruction::StoreDigits(_)) => (cpu.I as usize, 3),
+            Ok(Instruction::SaveRegisters(x)) => (cpu.I as usize, x + 1),
+            Ok(Instruction::SaveRegisterRange(x, y)) => (cpu.I as usize, if x > y { x - y + 1 } else { y - x + 1 }),
+            _ => (0, 0),
+        };
+        self.pending = Some(PendingStep {
+            pc : cpu.pc,
+            opcode,
+            registers : cpu.registerV,
+            i : cpu.I,
+            sp : cpu.sp,
+            writes,
+        });
+    }
+
+    ///
+    /// Writes out the instruction passed to ```before```, now that it has run.
+    pub fn after<D : DisplayOutput, A : AudioOutput, I : InputReciever>(&mut self, cpu : &InterpretedCpu<D, A, I>) -> io::Result<()> {
+        let step = match self.pending.take() {
+            Some(step) => step,
+            None => return Ok(()),
+        };
+
+        let mut registers : Vec<(u8, u16)> = (0 .. 16)
+            .filter(|idx| cpu.registerV[*idx] != step.registers[*idx])
+            .map(|idx| (idx as u8, cpu.registerV[idx] as u16))
+            .collect();
+        if cpu.I != step.i {
+            registers.push((I_REGISTER, cpu.I));
+        }
+        if cpu.sp != step.sp {
+            registers.push((SP_REGISTER, cpu.sp as u16));
+        }
+        let (start, length) = step.writes;
+        let end = (start + length).min(cpu.memory.len());
+        let memory : Vec<(u16, u8)> = (start.min(end) .. end).map(|addr| (addr as u16, cpu.memory[addr])).collect();
+        let (delay, sound) = (cpu.timers.delay(), cpu.timers.sound());
+
+        let record = match self.options.format {
+            TraceFormat::JsonLines => {
+                let text = match Instruction::decode(step.opcode) {
+                    Ok(instr) => instr.to_string(),
+                    Err(_) => format!("{:#04X} {:#04X}", step.opcode >> 8, step.opcode & 0xFF),
+                };
+                let mut changed = serde_json::Map::new();
+                for &(idx, value) in registers.iter() {
+                    changed.insert(REGISTERS[idx as usize].0.to_owned(), json!(value));
+                }
+                let writes : Vec<_> = memory.iter().map(|&(addr, value)| json!([addr, value])).collect();
+                let mut line = json!({
+                    "pc" : step.pc,
+                    "opcode" : step.opcode,
+                    "instruction" : text,
+                    "registers" : changed,
+                    "memory" : writes,
+                    "dt" : delay,
+                    "st" : sound,
+                }).to_string();
+                line.push('\n');
+                line.into_bytes()
+            },
+            TraceFormat::Binary => {
+                let mut bytes = Vec::with_capacity(9 + registers.len() * 3 + memory.len() * 3);
+                write_u16(&mut bytes, step.pc);
+                write_u16(&mut bytes, step.opcode);
+                bytes.push(delay);
+                bytes.push(sound);
+                bytes.push(registers.len() as u8);
+                for &(idx, value) in registers.iter() {
+                    bytes.push(idx);
+                    write_u16(&mut bytes, value);
+                }
+                write_u16(&mut bytes, memory.len() as u16);
+                for &(addr, value) in memory.iter() {
+                    write_u16(&mut bytes, addr);
+                    bytes.push(value);
+                }
+                bytes
+            },
+        };
+
+        if self.options.max_bytes.map(|max| self.written + record.len() as u64 > max).unwrap_or(false) {
+            self.full = true;
+            return self.output.flush();
+        }
+        self.output.write_all(&record)?;
+        self.written += record.len() as u64;
+        Ok(())
+    }
+
+    ///
+    /// Writes anything still buffered out to the file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+fn write_u16(bytes : &mut Vec<u8>, value : u16) {
+    bytes.push((value >> 8) as u8);
+    bytes.push((value & 0xFF) as u8);
+}
+
+///
+/// Parses an address range written as ```start-end```, such as ```0x200-0x2FF```.
+pub fn parse_range(text : &str) -> Option<(u16, u16)> {
+    let mut parts = text.splitn(2, '-');
+    let start = parse_number(parts.next()?.trim())?;
+    let end = parse_number(parts.next()?.trim())?;
+    if start < 0 || start > end || end > 0xFFFF {
+        return None;
+    }
+    Some((start as u16, end as u16))
+}
@@ -0,0 +1,322 @@
+//!
+//! Code related to running ROMs without a window, sound card, or keyboard.
+//!
+//! The null backends here stand in for the SDL ones, so ROMs can run on a
+//! headless machine such as a CI server. Rather than showing anything, the
+//! display keeps the last frame so it can be printed afterwards.
+
+use chip8_mod::InterpretedCpu;
+use chip8_mod::cpu::OpcodeExecuter;
+use chip8_mod::instruction::Instruction;
+use chip8_mod::error::Chip8Error;
+use chip8_mod::display::{DisplayOutput, Resolution, Palette};
+use chip8_mod::audio::{AudioOutput, PATTERN_SIZE};
+use chip8_mod::input::InputReciever;
+use chip8_mod::scheduler::{Scheduler, TIMER_HZ};
+use chip8_mod::clock::VirtualClock;
+use debug_mod::trace::TraceWriter;
+
+use std::fmt;
+
+///
+/// The characters used to print each palette index in ```NullDisplay::to_text```.
+const TEXT_PIXELS : [char ; 4] = ['.', '#', '+', '@'];
+
+pub struct NullDisplay {
+
+    ///
+    /// The palette index of each pixel in the last frame, row by row.
+    pub pixels : Vec<u8>,
+    pub resolution : Resolution,
+
+    ///
+    /// The number of frames drawn so far.
+    pub frames : usize,
+}
+
+pub struct NullAudio {
+    pub playing : bool,
+    pub pattern : [u8 ; PATTERN_SIZE],
+    pub pitch : u8,
+}
+
+///
+/// Input that holds down a fixed set of keys and never asks to quit.
+pub struct NullInput {
+    pub keys : [bool ; 0x10],
+}
+
+pub struct HeadlessRunner {
+    pub video : NullDisplay,
+    pub audio : NullAudio,
+    pub keys : NullInput,
+}
+
+impl HeadlessRunner {
+    pub fn new() -> HeadlessRunner {
+        HeadlessRunner {
+            video : NullDisplay {
+                pixels : vec![0 ; Resolution::Low.width() * Resolution::Low.height()],
+                resolution : Resolution::Low,
+                frames : 0,
+            },
+            audio : NullAudio {
+                playing : false,
+                pattern : [0 ; PATTERN_SIZE],
+                pitch : 0,
+            },
+            keys : NullInput {
+                keys : [false ; 0x10],
+            },
+        }
+    }
+}
+
+impl Default for HeadlessRunner {
+    fn default() -> HeadlessRunner {
+        HeadlessRunner::new()
+    }
+}
+
+impl NullDisplay {
+
+    ///
+    /// The last frame as text, one line per row, with ```.``` for the background
+    /// and ```#```, ```+``` or ```@``` for the pixels set in plane 1, plane 2, or both.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for row in self.pixels.chunks(self.resolution.width()) {
+            text.extend(row.iter().map(|pixel| TEXT_PIXELS[*pixel as usize]));
+            text.push('\n');
+        }
+        text
+    }
+
+    ///
+    /// The last frame as a plain PBM image, with every pixel not in the background
+    /// drawn black.
+    pub fn to_pbm(&self) -> String {
+        let mut pbm = format!("P1\n{} {}\n", self.resolution.width(), self.resolution.height());
+        for row in self.pixels.chunks(self.resolution.width()) {
+            let bits : Vec<&str> = row.iter().map(|pixel| if *pixel == 0 { "0" } else { "1" }).collect();
+            pbm.push_str(&bits.join(" "));
+            pbm.push('\n');
+        }
+        pbm
+    }
+}
+
+impl DisplayOutput for NullDisplay {
+    fn display_buffer (&mut self, pixels : &[u8], resolution : Resolution, _palette : &Palette) {
+        self.pixels = pixels.to_vec();
+        self.resolution = resolution;
+        self.frames += 1;
+    }
+}
+
+impl AudioOutput for NullAudio {
+    fn output_audio (&mut self) {
+        self.playing = true;
+    }
+
+    fn stop_audio(&mut self) {
+        self.playing = false;
+    }
+
+    fn set_pattern(&mut self, pattern : &[u8 ; PATTERN_SIZE]) {
+        self.pattern = *pattern;
+    }
+
+    fn set_pitch(&mut self, pitch : u8) {
+        self.pitch = pitch;
+    }
+}
+
+impl InputReciever for NullInput {
+    fn check_key(&mut self, key : u8) -> bool {
+        self.keys[key as usize]
+    }
+
+    fn check_any_key(&mut self) -> Option<u8> {
+        self.keys.iter().position(|pressed| *pressed).map(|key| key as u8)
+    }
+
+    fn check_should_die(&mut self) -> bool {
+        false
+    }
+}
+
+///
+/// When a headless run should stop. It also stops when the program halts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunLimits {
+
+    ///
+    /// The instructions per second the timers are ticked against.
+    pub clock_hz : u32,
+    pub max_instructions : Option<u64>,
+
+    ///
+    /// The most 60 Hz frames, each of ```clock_hz / 60``` instructions, to run.
+    pub max_frames : Option<u64>,
+
+    ///
+    /// Stops right before the instruction at this address would run.
+    pub until_pc : Option<u16>,
+}
+
+///
+/// Why a headless run stopped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    InstructionLimit,
+    FrameLimit,
+    ReachedPc(u16),
+
+    ///
+    /// The program jumped to the jump itself, the usual way of halting.
+    SpinLoop(u16),
+
+    ///
+    /// The program exited.
+    Died,
+
+    ///
+    /// The program counter ran off the end of memory.
+    OutOfMemory,
+
+    ///
+    /// The program asked for something the machine doesn't have.
+    Error(Chip8Error),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StopReason::InstructionLimit => write!(f, "reached the instruction limit"),
+            StopReason::FrameLimit => write!(f, "reached the frame limit"),
+            StopReason::ReachedPc(pc) => write!(f, "reached {:#X}", pc),
+            StopReason::SpinLoop(pc) => write!(f, "halted in a loop at {:#X}", pc),
+            StopReason::Died => write!(f, "died"),
+            StopReason::OutOfMemory => write!(f, "ran off the end of memory"),
+            StopReason::Error(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+///
+/// Runs the CPU until one of the limits is hit or the program halts, returning
+/// why it stopped and the number of instructions run.
+pub fn run<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &mut InterpretedCpu<D, A, I>, limits : &RunLimits) -> (StopReason, u64) {
+    run_traced(cpu, limits, None)
+}
+
+///
+/// Runs the CPU like ```run```, recording every instruction to ```trace```.
+pub fn run_traced<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &mut InterpretedCpu<D, A, I>, limits : &RunLimits, mut trace : Option<&mut TraceWriter>) -> (StopReason, u64) {
+    let mut scheduler = Scheduler::with_clock(limits.clock_hz / TIMER_HZ, Box::new(VirtualClock::new()));
+    let instructions_per_frame = scheduler.instructions_per_frame as u64;
+    let mut count : u64 = 0;
+    let reason = loop {
+        if cpu.has_died() {
+            break StopReason::Died;
+        }
+        if cpu.pc as usize + 1 >= cpu.memory.len() {
+            break StopReason::OutOfMemory;
+        }
+        if limits.until_pc == Some(cpu.pc) {
+            break StopReason::ReachedPc(cpu.pc);
+        }
+        if limits.max_instructions.map(|max| count >= max).unwrap_or(false) {
+            break StopReason::InstructionLimit;
+        }
+        if limits.max_frames.map(|max| count >= max * instructions_per_frame).unwrap_or(false) {
+            break StopReason::FrameLimit;
+        }
+
+        let next_instr = match cpu.get_next_instr() {
+            Ok(next_instr) => next_instr,
+            Err(e) => break StopReason::Error(e),
+        };
+        if Instruction::decode(next_instr) == Ok(Instruction::Jump(cpu.pc)) {
+            break StopReason::SpinLoop(cpu.pc);
+        }
+        if let Some(ref mut trace) = trace {
+            trace.before(cpu, next_instr);
+        }
+        let res = cpu.process_instruction(next_instr);
+        if let Some(ref mut trace) = trace {
+            if let Err(e) = trace.after(cpu) {
+                error!("Could not write the trace: {}", e);
+            }
+        }
+        if let Err(e) = res {
+            break StopReason::Error(e);
+        }
+        cpu.end_frame();
+        count += 1;
+        if count.is_multiple_of(instructions_per_frame) {
+            scheduler.finish_frame(cpu);
+            scheduler.wait_for_next_frame();
+        }
+        for event in cpu.timers.take_events() {
+            cpu.audio_output.handle_event(event);
+        }
+    };
+
+    // Show whatever was drawn since the last frame
+    cpu.display_output.present();
+    (reason, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_mod::platform::Platform;
+    use chip8_mod::display::ScreenBuffer;
+    use chip8_mod::audio::AudioTimer;
+
+    #[test]
+    fn test_headless_run() {
+        let mut runner = HeadlessRunner::new();
+        runner.keys.keys[5] = true;
+        let (reason, count) = {
+            let mut cpu = InterpretedCpu::new(
+                Platform::CosmacVip,
+                ScreenBuffer::new(&mut runner.video),
+                AudioTimer::new(&mut runner.audio),
+                &mut runner.keys
+            );
+            cpu.load_rom(&chip8_asm! {
+                    ld v0, k;
+                    ld f, v0;
+                    drw v1, v1, 5;
+                    ld st, v0;
+                halt:
+                    jp halt;
+            }).unwrap();
+            let limits = RunLimits { clock_hz : 600, ..RunLimits::default() };
+            run(&mut cpu, &limits)
+        };
+        assert_eq!(reason, StopReason::SpinLoop(0x208));
+        assert_eq!(count, 4);
+        assert!(runner.video.frames > 0);
+        assert_eq!(&runner.video.to_text()[.. 5], "####.");
+        assert!(runner.video.to_pbm().starts_with("P1\n64 32\n1 1 1 1 0 0"));
+
+        // The last instruction in memory still runs before the program runs off the end
+        let mut runner = HeadlessRunner::new();
+        let mut cpu = InterpretedCpu::new(
+            Platform::CosmacVip,
+            ScreenBuffer::new(&mut runner.video),
+            AudioTimer::new(&mut runner.audio),
+            &mut runner.keys
+        );
+        cpu.load_rom(&chip8_asm! {
+                jp 0xFFE;
+        }).unwrap();
+        cpu.memory[0xFFE .. 0x1000].copy_from_slice(&[0x60, 0x05]); // V0 = 5
+        assert_eq!(run(&mut cpu, &RunLimits::default()), (StopReason::OutOfMemory, 2));
+        assert_eq!(cpu.registerV[0], 5);
+    }
+}
@@ -1,3 +1,15 @@
+#[macro_use]
+extern crate serde_json;
+#[macro_use]
+extern crate log;
+
+pub mod utils_mod;
+
+#[macro_use]
+pub mod asm_mod;
 pub mod chip8_mod;
-pub mod sdl_mod;
-pub mod utils_mod;
\ No newline at end of file
+pub mod headless_mod;
+pub mod debug_mod;
+
+#[cfg(feature = "sdl")]
+pub mod sdl_mod;
\ No newline at end of file
@@ -1,19 +1,31 @@
 #[macro_use]
-mod utils_mod;
-
-mod chip8_mod;
-mod sdl_mod;
-
+extern crate chip8_emu;
+#[macro_use]
+extern crate log;
 
-use chip8_mod::*;
-use chip8_mod::cpu::{OpcodeExecuter, InstructionSet};
-use chip8_mod::audio::AudioTimer;
-use chip8_mod::display::ScreenBuffer;
+use chip8_emu::sdl_mod;
+use chip8_emu::chip8_mod::*;
+use chip8_emu::chip8_mod::cpu::OpcodeExecuter;
+use chip8_emu::chip8_mod::quirks::{self, Quirks};
+use chip8_emu::chip8_mod::platform::{self, Platform};
+use chip8_emu::chip8_mod::audio::{AudioOutput, AudioTimer};
+use chip8_emu::chip8_mod::display::{DisplayOutput, ScreenBuffer, DEFAULT_PALETTE};
+use chip8_emu::chip8_mod::input::{InputReciever, SaveSlotRequest};
+use chip8_emu::chip8_mod::rewind::{self, RewindBuffer};
+use chip8_emu::chip8_mod::scheduler::Scheduler;
+use chip8_emu::chip8_mod::random;
+use chip8_emu::chip8_mod::error::Chip8Error;
+use chip8_emu::debug_mod::debugger::{Debugger, StopReason, RemoteDebugger, RemoteAction};
+use chip8_emu::debug_mod::cli;
+use chip8_emu::debug_mod::gdb::GdbStub;
+use chip8_emu::debug_mod::dap::{DapServer, Symbols};
+use chip8_emu::debug_mod::trace::{self, TraceWriter, TraceOptions, TraceFormat};
+use chip8_emu::utils_mod::logger;
 
 use std::env;
 use std::fs::File;
-use std::io::{Read};
-use std::time::SystemTime;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
 
 ///
@@ -23,72 +35,157 @@ use std::time::SystemTime;
 /// 
 /// Should display an E while toggling the audio every half second.
 /// Quits after 10 seconds.
-const TEST_ROM_1 : [u8 ; 38] = [
+fn test_rom_1() -> Vec<u8> {
+    chip8_asm! {
+            ld v5, 10;      // Run for 10 seconds
 
-    0x65, 0x0A, //Run for 10 seconds
+            // Get the location of E
+            ld v0, 0x0E;
+            ld f, v0;
 
-    // Get the location of E
-    0x60, 0x0E,  // Store the argument in V0
-    0xF0, 0x29,  // Set I to the memory location of E
+        outer:
+            // Set the delay at 1 second
+            ld v0, 60;
+            ld dt, v0;
+            ld v0, 30;      // Play audio for half a second
+            ld st, v0;
 
-    //OUTER LOOP LABEL
-
-    // Set the delay at 1 second
-    0x60, 0x3c, // Load the value 60 into V0
-    0xF0, 0x15, // Set the delay timer = V0
-    0x60, 0x1e, // Load the value 30 into V0
-    0xF0, 0x18, // Play audio for half a second
-
-    //INNER LOOP LABEL
-    
-    0x00, 0xE0, // Clear the Screen
+        inner:
+            cls;
 
-    // Draw E
-    0x60, 0x00,   // Zero out reg 0
-    0x61, 0x00,   // Zero out reg 1
-    0xD0, 0x15,   // Draw the E at 0, 0
+            // Draw E at 0, 0
+            ld v0, 0;
+            ld v1, 0;
+            drw v0, v1, 5;
 
-    // Check the inner loop
-    0xF0, 0x07,  //Load the timer into V0
-    0x30, 0x00,  //Don't jump if V0 == 0
-    0x12, 0x0E,  // Jump to the clear screen instruction, 10 bytes after start
+            // Check the inner loop
+            ld v0, dt;
+            se v0, 0;
+            jp inner;
 
-    //Decrement V5
-    0x68, 0x01, //Store 1 into V8 since we dont have a SUB CONST instruciton
-    0x85, 0x85, // V5 = V5 - V8
+            // Decrement V5, since there's no SUB CONST instruction
+            ld v8, 1;
+            sub v5, v8;
 
+            se v5, 0;       // If we ran for the wanted number of seconds, end
+            jp outer;
 
-    0x35, 0x00,  // If we ran for the wanted number of seconds, end
-    0x12, 0x06,   // Otherwise, jump 
-
-    0xFF, 0xFF, //DIE
-];
+            db 0xFF, 0xFF   // DIE
+    }
+}
 
 fn main() {
-    error_log!("Error logging started!");
-    debug_log!("Debug logging started!");
-
-
     let args : Vec<String> = env::args().collect();
+
+    // Start logging first, so the rest of the arguments can be logged
+    let log_spec = args.iter().position(|arg| arg == "--log").and_then(|idx| args.get(idx + 1)).map(|spec| spec.as_str());
+    logger::init(log_spec).unwrap_or_else(|err| panic!("{}", err));
+    debug!("Logging started.");
     // Set the default command line argument values
-    let mut min_frame_time : u32 = 0; //default to maxing at the maximum FPS
+    let mut clock_hz : Option<u32> = None; //default to the platform's clock speed
+    let mut instructions_per_frame : Option<u32> = None;
     let mut rompath : &str = "";
-    let mut legacy = false;
+    let mut platform : Option<Platform> = None;
+    let mut quirks : Option<Quirks> = None;
+    let mut quirk_overrides : Vec<&str> = Vec::new();
+    let mut palette = DEFAULT_PALETTE;
+    let mut rewind_budget = rewind::DEFAULT_BUDGET;
+    let mut debugger : Option<Debugger> = None;
+    let mut gdb_port : Option<u16> = None;
+    let mut dap_port : Option<u16> = None;
+    let mut trace_path : Option<&str> = None;
+    let mut seed : Option<&str> = None;
+    let mut trace_options = TraceOptions::default();
     
     // Parse the command line arguments
     let mut arg_idx = 1;
     while arg_idx < args.len() {
         let to_proc = args[arg_idx].as_ref();
         match to_proc {
-            "--fps" => {
+            "--clock" | "--fps" => {
+                arg_idx += 1;
+                clock_hz = Some(args[arg_idx].parse::<u32>().unwrap());
+                debug!("Setting the clock to {:?} Hz.", clock_hz);
+            },
+            "--ipf" => {
+                arg_idx += 1;
+                instructions_per_frame = Some(args[arg_idx].parse::<u32>().unwrap());
+                debug!("Setting instructions per frame to {:?}.", instructions_per_frame);
+            },
+            "--platform" => {
                 arg_idx += 1;
-                let max_fps = args[arg_idx].parse::<u32>().unwrap();
-                min_frame_time = (1000 * 1000 * 1000) / max_fps; // Calculate frame time from fps
-                debug_log!("Setting max fps to {}, meaning min_dt = {}.", max_fps, min_frame_time);
+                platform = Some(Platform::from_name(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Unknown platform {}; expected one of {:?}.", args[arg_idx], platform::PLATFORM_NAMES)));
+                debug!("Set platform to {}.", args[arg_idx]);
+            },
+            "--palette" => {
+                arg_idx += 1;
+                for (idx, color) in args[arg_idx].split(',').take(palette.len()).enumerate() {
+                    palette[idx] = u32::from_str_radix(color.trim_start_matches('#'), 16).unwrap();
+                }
+                debug!("Set palette to {:?}.", palette);
             },
             "--legacy" => {
-                legacy = true;
-                debug_log!("Set legacy to ON.");
+                quirks = Some(Quirks::vip());
+                debug!("Set legacy to ON.");
+            },
+            "--quirks" => {
+                arg_idx += 1;
+                quirks = Some(Quirks::from_name(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Unknown quirks preset {}; expected one of {:?}.", args[arg_idx], quirks::PRESET_NAMES)));
+                debug!("Set quirks preset to {}.", args[arg_idx]);
+            },
+            "--quirk" => {
+                arg_idx += 1;
+                quirk_overrides.push(&args[arg_idx]);
+            },
+            "--rewind-memory" => {
+                arg_idx += 1;
+                rewind_budget = args[arg_idx].parse::<usize>().unwrap() * 1024 * 1024;
+                debug!("Set rewind memory to {} bytes.", rewind_budget);
+            },
+            "--debug" => {
+                debugger = Some(Debugger::new());
+                debug!("Set debugger to ON.");
+            },
+            "--gdb" => {
+                arg_idx += 1;
+                gdb_port = Some(args[arg_idx].parse::<u16>().unwrap());
+                debugger = Some(Debugger::new());
+                debug!("Set GDB port to {:?}.", gdb_port);
+            },
+            "--dap" => {
+                arg_idx += 1;
+                dap_port = Some(args[arg_idx].parse::<u16>().unwrap());
+                debugger = Some(Debugger::new());
+                debug!("Set DAP port to {:?}.", dap_port);
+            },
+            "--seed" => {
+                arg_idx += 1;
+                seed = Some(&args[arg_idx]);
+                debug!("Set random seed to {}.", args[arg_idx]);
+            },
+            "--log" => {
+                arg_idx += 1;
+            },
+            "--trace" => {
+                arg_idx += 1;
+                trace_path = Some(&args[arg_idx]);
+                debug!("Set trace file to {}.", args[arg_idx]);
+            },
+            "--trace-format" => {
+                arg_idx += 1;
+                trace_options.format = TraceFormat::from_name(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Unknown trace format {}; expected json or binary.", args[arg_idx]));
+            },
+            "--trace-range" => {
+                arg_idx += 1;
+                trace_options.range = Some(trace::parse_range(&args[arg_idx])
+                    .unwrap_or_else(|| panic!("Bad trace range {}; expected start-end.", args[arg_idx])));
+            },
+            "--trace-limit" => {
+                arg_idx += 1;
+                trace_options.max_bytes = Some(args[arg_idx].parse::<u64>().unwrap() * 1024 * 1024);
             },
             _ => {
                 rompath = &args[arg_idx];
@@ -97,60 +194,254 @@ fn main() {
         arg_idx += 1;
     } 
 
+    // Unless a preset is given, follow the platform's own quirks.
+    let platform = platform.unwrap_or_default();
+    let mut quirks = quirks.unwrap_or_else(|| platform.quirks());
+    for spec in quirk_overrides {
+        quirks.apply_override(spec).unwrap();
+    }
+    debug!("Using platform {:?} with quirks {:?}.", platform, quirks);
+
     // Set up the SDL environment
-    let mut window = sdl_mod::SdlRunner::new();
+    let window = sdl_mod::SdlRunner::new();
     let mut cpu = InterpretedCpu::new(
-        if legacy { InstructionSet::LEGACY } else { InstructionSet::COWGOD },
-        ScreenBuffer::new(&mut window.video), 
-        AudioTimer::new(&mut window.audio),
-        &mut window.keys
+        platform,
+        ScreenBuffer::new(window.video), 
+        AudioTimer::new(window.audio),
+        window.keys
     );
+    cpu.set_quirks(quirks);
+    cpu.display_output.palette = palette;
+    if let Some(seed) = seed {
+        cpu.random = random::from_spec(seed).unwrap_or_else(|err| panic!("{}", err));
+    }
 
     // Load the ROM
+    let mut symbols : Option<Symbols> = None;
     if !rompath.is_empty() {
-        debug_log!("USING ROMPATH: {}", rompath);
+        debug!("USING ROMPATH: {}", rompath);
         let mut buffer = Vec::new();
-        let mut file = File::open(rompath).unwrap();
-        file.read_to_end(&mut buffer).unwrap();
-        debug_log!("FINISHED READING FILE");
-        cpu.load_rom(&buffer);
+        if rompath.ends_with(".8o") {
+            // Assembly source is assembled on the fly
+            let assembled = Symbols::load(Path::new(rompath))
+                .unwrap_or_else(|err| panic!("{}", err));
+            buffer = assembled.assembly.rom.clone();
+            symbols = Some(assembled);
+        }
+        else {
+            let mut file = File::open(rompath).unwrap();
+            file.read_to_end(&mut buffer).unwrap();
+
+            // Pick up the labels of a ROM assembled with a symbol file
+            let sympath = Path::new(rompath).with_extension("sym");
+            if debugger.is_some() && sympath.exists() {
+                match Symbols::load(&sympath) {
+                    Ok(loaded) => symbols = Some(loaded),
+                    Err(e) => error!("{}", e),
+                }
+            }
+        }
+        debug!("FINISHED READING FILE");
+        cpu.load_rom(&buffer).unwrap_or_else(|err| panic!("{}", err));
+        cpu.set_rpl_file(Path::new(rompath).with_extension("rpl"));
     }
     else {
-        cpu.load_rom(&TEST_ROM_1);
+        cpu.load_rom(&test_rom_1()).unwrap();
     }
 
+    if let (Some(debugger), Some(symbols)) = (debugger.as_mut(), symbols.as_ref()) {
+        debugger.load_symbols(&symbols.assembly);
+    }
+
+    // Wait for GDB or the editor before running anything
+    let mut remote : Option<Box<dyn RemoteDebugger<_, _, _>>> = match (gdb_port, dap_port) {
+        (Some(port), _) => {
+            println!("Waiting for GDB on port {}.", port);
+            Some(Box::new(GdbStub::listen(port).unwrap_or_else(|err| panic!("Could not listen for GDB: {}", err))))
+        },
+        (None, Some(port)) => {
+            println!("Waiting for a debug adapter client on port {}.", port);
+            Some(Box::new(DapServer::listen(port, symbols.take()).unwrap_or_else(|err| panic!("Could not listen for a debug adapter client: {}", err))))
+        },
+        (None, None) => None,
+    };
+
+    // Open the trace file
+    let mut tracer = trace_path.map(|path| TraceWriter::create(path, trace_options)
+        .unwrap_or_else(|err| panic!("Could not create the trace file {}: {}", path, err)));
+
     // The main execution loop
-    let mut prevtime = SystemTime::now();
-    while !cpu.has_died() && cpu.pc < 4094{
-        debug_log!("STARTING FRAME");
-
-        //Run the next instruction
-        let next_instr = cpu.get_next_instr();
-        debug_log!("OP: {:#X}", next_instr);
-        debug_log!("CPU: {}", cpu);
-        cpu.process_instruction(next_instr);
-
-        //Update the timing
-        let mut curtime = SystemTime::now();
-        let mut nsecs = curtime.duration_since(prevtime).unwrap().subsec_nanos();
-        while nsecs < min_frame_time { // The code to limit CPU HZ 
-            curtime = SystemTime::now();
-            nsecs = curtime.duration_since(prevtime).unwrap().subsec_nanos();
+    let mut scheduler = match instructions_per_frame {
+        Some(instructions_per_frame) => Scheduler::new(instructions_per_frame),
+        None => Scheduler::from_clock_hz(clock_hz.unwrap_or(platform.default_clock_hz())),
+    };
+    debug!(target : "timing", "Running {} instructions per frame.", scheduler.instructions_per_frame);
+    let mut rewind = RewindBuffer::new(rewind_budget);
+    rewind.push(&cpu.save_state());
+    'running: while !cpu.has_died() && (cpu.pc as usize) < cpu.memory.len() - 2 {
+        trace!(target : "timing", "STARTING FRAME");
+        play_timer_events(&mut cpu);
+
+        //Step backwards a frame at a time while the rewind key is held
+        if cpu.keyboard_input.check_rewind() {
+            if let Some(state) = rewind.pop() {
+                cpu.load_state(&state).unwrap();
+            }
+            if cpu.keyboard_input.check_should_die() {
+                break;
+            }
+            scheduler.wait_for_next_frame();
+            continue;
         }
-        if cfg!(feature="log_fps") {
-            println!("FPS: {}", (1000 * 1000 * 1000)/nsecs);
+
+        for _ in 0 .. scheduler.instructions_per_frame {
+            if cpu.has_died() || (cpu.pc as usize) >= cpu.memory.len() - 2 {
+                break 'running;
+            }
+
+            //Hand control to the debugger if it wants to stop here
+            if let Some(reason) = debugger.as_mut().and_then(|debugger| debugger.check(&cpu)) {
+                cpu.display_output.present();
+                if !debug_stopped(&mut debugger, &mut remote, &mut cpu, Ok(reason)) {
+                    break 'running;
+                }
+                scheduler.restart();
+                continue;
+            }
+
+            //Run the next instruction
+            trace!(target : "cpu", "CPU: {}", cpu);
+            let res = cpu.get_next_instr().and_then(|next_instr| {
+                trace!(target : "cpu", "OP: {:#X}", next_instr);
+                if let Some(ref mut tracer) = tracer {
+                    tracer.before(&cpu, next_instr);
+                }
+                cpu.process_instruction(next_instr)
+            });
+            if let Some(ref mut tracer) = tracer {
+                if let Err(e) = tracer.after(&cpu) {
+                    error!("Could not write the trace: {}", e);
+                }
+            }
+            if let Err(e) = res {
+                eprintln!("Stopped: {}", e);
+                cpu.display_output.present();
+                if let Some(ref mut debugger) = debugger {
+                    // Leave the CPU as it failed for inspection
+                    debugger.pause();
+                }
+                debug_stopped(&mut debugger, &mut remote, &mut cpu, Err(e));
+                break 'running;
+            }
+            cpu.end_frame();
+            play_timer_events(&mut cpu);
         }
-        prevtime = curtime;
-        cpu.tick(nsecs as u64);
 
-        //End the frame
-        cpu.end_frame();
-        debug_log!("ENDING FRAME");
+        //Tick the timers, draw the screen, and take the snapshot for this frame
+        scheduler.finish_frame(&mut cpu);
+        rewind.push(&cpu.save_state());
+
+        //Let the remote debugger pause the program or change breakpoints
+        let action = match (remote.as_mut(), debugger.as_mut()) {
+            (Some(remote), Some(debugger)) => remote.poll(debugger, &mut cpu),
+            _ => Ok(RemoteAction::Resume),
+        };
+        if !apply_remote_action(action, &mut debugger, &mut remote) {
+            break;
+        }
+        trace!(target : "timing", "ENDING FRAME");
 
         //Check for emulation end
         let die = cpu.keyboard_input.check_should_die();
         if die {
             break;
         }
+
+        //Save or load a state slot
+        match cpu.keyboard_input.check_save_slot_request() {
+            Some(SaveSlotRequest::Save(slot)) => {
+                let path = save_slot_path(rompath, slot);
+                let res = File::create(&path).and_then(|mut file| file.write_all(&cpu.save_state()));
+                match res {
+                    Ok(_) => debug!("Saved state to {}.", path.display()),
+                    Err(e) => error!("Could not save state to {}: {}", path.display(), e),
+                }
+            },
+            Some(SaveSlotRequest::Load(slot)) => {
+                let path = save_slot_path(rompath, slot);
+                let mut state = Vec::new();
+                match File::open(&path).and_then(|mut file| file.read_to_end(&mut state)) {
+                    Ok(_) => {
+                        if let Err(e) = cpu.load_state(&state) {
+                            error!("Could not load state from {}: {}", path.display(), e);
+                        }
+                    },
+                    Err(e) => error!("Could not read state from {}: {}", path.display(), e),
+                }
+            },
+            None => {},
+        }
+
+        scheduler.wait_for_next_frame();
+    }
+
+    // Write out the rest of the trace
+    if let Some(ref mut tracer) = tracer {
+        if let Err(e) = tracer.flush() {
+            error!("Could not write the trace: {}", e);
+        }
+    }
+
+    // Let the remote debugger know the program is done
+    if let Some(ref mut remote) = remote {
+        let _ = remote.exited();
+    }
+}
+
+///
+/// Starts and stops the tone as the sound timer turns on and off.
+fn play_timer_events<D : DisplayOutput, A : AudioOutput, I : InputReciever>(cpu : &mut InterpretedCpu<D, A, I>) {
+    for event in cpu.timers.take_events() {
+        cpu.audio_output.handle_event(event);
     }
 }
+
+///
+/// Hands the stopped program to the remote debugger if one is attached, or
+/// to the debugger prompt otherwise. Returns whether the program should keep running.
+fn debug_stopped<D : DisplayOutput, A : AudioOutput, I : InputReciever>(debugger : &mut Option<Debugger>, remote : &mut Option<Box<dyn RemoteDebugger<D, A, I>>>, cpu : &mut InterpretedCpu<D, A, I>, reason : Result<StopReason, Chip8Error>) -> bool {
+    let action = match (debugger.as_mut(), remote.as_mut()) {
+        (Some(debugger), Some(remote)) => remote.stopped(debugger, cpu, reason),
+        (Some(debugger), None) => {
+            if let Ok(reason) = reason {
+                println!("{}", cli::describe_stop(debugger, cpu, reason));
+            }
+            return cli::prompt(debugger, cpu);
+        },
+        (None, _) => return true,
+    };
+    apply_remote_action(action, debugger, remote)
+}
+
+///
+/// Returns whether the program should keep running after the remote debugger
+/// asked for ```action```.
+fn apply_remote_action<D, A, I>(action : io::Result<RemoteAction>, debugger : &mut Option<Debugger>, remote : &mut Option<Box<dyn RemoteDebugger<D, A, I>>>) -> bool {
+    match action {
+        Ok(RemoteAction::Resume) => true,
+        Ok(RemoteAction::Kill) => false,
+        Ok(RemoteAction::Detach) | Err(_) => {
+            // Once the remote debugger is gone, the program runs on by itself
+            *debugger = None;
+            *remote = None;
+            true
+        },
+    }
+}
+
+///
+/// The file save state slot ```slot``` is kept in, next to the ROM.
+fn save_slot_path(rompath : &str, slot : usize) -> PathBuf {
+    let rompath = if rompath.is_empty() { "test_rom" } else { rompath };
+    Path::new(rompath).with_extension(format!("state{}", slot))
+}
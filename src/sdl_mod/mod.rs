@@ -1,5 +1,5 @@
 extern crate sdl2;
-use sdl_mod::sdl2::keyboard::Keycode;
+use sdl_mod::sdl2::keyboard::{Keycode, LSHIFTMOD, RSHIFTMOD};
 use sdl_mod::sdl2::video::Window;
 use sdl_mod::sdl2::render::Canvas;
 use sdl_mod::sdl2::EventPump;
@@ -8,9 +8,9 @@ use sdl_mod::sdl2::rect::Rect;
 use sdl_mod::sdl2::event::Event;
 use sdl_mod::sdl2::audio::{AudioDevice, AudioSpecDesired, AudioSpec, AudioCallback};
 
-use chip8_mod::display::{DisplayOutput, SCREEN_HEIGHT, SCREEN_WIDTH};
-use chip8_mod::audio::AudioOutput;
-use chip8_mod::input::InputReciever;
+use chip8_mod::display::{DisplayOutput, Resolution, Palette};
+use chip8_mod::audio::{AudioOutput, PATTERN_SIZE, DEFAULT_PATTERN, DEFAULT_PITCH, pitch_to_rate};
+use chip8_mod::input::{InputReciever, SaveSlotRequest};
 
 const DEFAULT_KEY_CONFIG : [Keycode; 0x10] = [
     Keycode::X, 
@@ -21,8 +21,15 @@ const DEFAULT_KEY_CONFIG : [Keycode; 0x10] = [
     Keycode::Num4, Keycode::R, Keycode::F, Keycode::V,
 ];
 
+///
+/// The keys that save to slots 1 to 4; holding shift with them loads the slot instead.
+const SAVE_SLOT_KEYS : [Keycode ; 4] = [Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4];
+
+///
+/// The key held to rewind.
+const REWIND_KEY : Keycode = Keycode::Backspace;
+
 pub struct Config {
-    audio_frequency : f32,
     chip8_keys : [Keycode ; 0x10],
 }
 
@@ -30,6 +37,8 @@ pub struct SdlKeyProcessor {
     key_map : [Keycode ; 0x10],
     key_buffer : [bool ; 0x10], 
     event_pump : EventPump,
+    save_slot_request : Option<SaveSlotRequest>,
+    rewinding : bool,
     pub has_quit : bool,
 }
 
@@ -38,8 +47,7 @@ pub struct SdlDisplayProcessor {
 }
 
 pub struct SdlAudioProcessor {
-    audio_frequency : f32,
-    audio_device : AudioDevice<SquareWaveCallback>,
+    audio_device : AudioDevice<PatternCallback>,
 }
 
 pub struct SdlRunner {
@@ -68,7 +76,7 @@ impl SdlRunner {
                 },
                 | spec | {
                     let actualfreq = spec.freq as f32;
-                    SquareWaveCallback::new(0.5, 440.0, actualfreq)
+                    PatternCallback::new(0.5, actualfreq)
                 }
             )
             .unwrap();
@@ -76,19 +84,19 @@ impl SdlRunner {
         SdlRunner {
             conf : Config {
                 chip8_keys : DEFAULT_KEY_CONFIG,
-                audio_frequency : 440.0,
             },
             video : SdlDisplayProcessor {
                 canvas : canvas_obj, 
             },
             audio : SdlAudioProcessor {
-                audio_frequency : 440.0, 
                 audio_device : audio_obj,
             },
             keys : SdlKeyProcessor {
                 key_map : DEFAULT_KEY_CONFIG,
                 key_buffer : [false ; 0x10],
                 has_quit : false, 
+                save_slot_request : None,
+                rewinding : false,
                 event_pump : sdl_context.event_pump().unwrap()
             },
 
@@ -98,43 +106,33 @@ impl SdlRunner {
 }
 
 impl DisplayOutput for SdlDisplayProcessor {
-    fn display_buffer (&mut self, buffer : &[u8 ; SCREEN_WIDTH/8 * SCREEN_HEIGHT]) {
-        self.canvas.set_draw_color(Color::RGB(255,255,255));
+    fn display_buffer (&mut self, pixels : &[u8], resolution : Resolution, palette : &Palette) {
+        self.canvas.set_draw_color(palette_color(palette[0]));
         self.canvas.clear();
-        self.canvas.set_draw_color(Color::RGB(0,0,0));
 
         let (width, height) = self.canvas.output_size().unwrap();
-        let pixel_width = width/SCREEN_WIDTH as u32;
-        let pixel_height = height/SCREEN_HEIGHT as u32;
-        
-
-        let mut x = 0;
-        let mut y = 0;
-
-        for (idx, byte) in buffer.into_iter().enumerate() {
-            if idx == 0 { }
-            else if idx %(SCREEN_WIDTH/8) == 0 {
-                y += pixel_height;
-                x = 0;
-            }
-            else {
-                x += pixel_width * 8;
-            }
-            for mask_num in 0 .. 8 {
-                let mask = 1 << (7 - mask_num);
-                let pval = byte & mask != 0;
-                if pval {
-                    let offset : i32 = (mask_num * pixel_width) as i32;
-                    let _res = self.canvas.fill_rect(
-                        Rect::from((offset + x as i32, y as i32, pixel_width, pixel_height))
-                    );
-                }
+        let pixel_width = width/resolution.width() as u32;
+        let pixel_height = height/resolution.height() as u32;
+
+        for (idx, color_idx) in pixels.iter().enumerate() {
+            if *color_idx == 0 {
+                continue;
             }
+            let x = (idx % resolution.width()) as u32 * pixel_width;
+            let y = (idx / resolution.width()) as u32 * pixel_height;
+            self.canvas.set_draw_color(palette_color(palette[*color_idx as usize]));
+            let _res = self.canvas.fill_rect(
+                Rect::from((x as i32, y as i32, pixel_width, pixel_height))
+            );
         }
         self.canvas.present();
     }
 }
 
+fn palette_color(rgb : u32) -> Color {
+    Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
+
 impl SdlKeyProcessor {
     fn process_events(&mut self) {
         for event in self.event_pump.poll_iter() {
@@ -142,12 +140,28 @@ impl SdlKeyProcessor {
                 Event::Quit { .. } | 
                 Event::MouseButtonDown { .. } | 
                 Event::AppTerminating{ .. }  => {
-                    debug_log!("DYING!");
+                    debug!(target : "input", "DYING!");
                     self.has_quit = true;
                 },
+                Event::KeyDown { keycode : Some(code), keymod, repeat : false, .. } if SAVE_SLOT_KEYS.contains(&code) => {
+                    let slot = SAVE_SLOT_KEYS.iter().position(|key| *key == code).unwrap() + 1;
+                    self.save_slot_request = if keymod.intersects(LSHIFTMOD | RSHIFTMOD) {
+                        Some(SaveSlotRequest::Load(slot))
+                    }
+                    else {
+                        Some(SaveSlotRequest::Save(slot))
+                    };
+                },
+                Event::KeyDown { keycode : Some(REWIND_KEY), .. } => {
+                    self.rewinding = true;
+                },
+                Event::KeyUp { keycode : Some(REWIND_KEY), .. } => {
+                    self.rewinding = false;
+                },
                 Event::KeyDown { keycode : Some(code), ..} => {
                     for buffer_idx in 0 .. 0x10 {
                         if self.key_map[buffer_idx] == code {
+                            debug!(target : "input", "Key {:X} pressed.", buffer_idx);
                             self.key_buffer[buffer_idx] = true;
                         }
                     }
@@ -155,6 +169,7 @@ impl SdlKeyProcessor {
                 Event::KeyUp { keycode : Some(code), .. } => {
                     for buffer_idx in 0 .. 0x10 {
                         if self.key_map[buffer_idx] == code {
+                            debug!(target : "input", "Key {:X} released.", buffer_idx);
                             self.key_buffer[buffer_idx] = false;
                         }
                     }
@@ -184,6 +199,16 @@ impl InputReciever for SdlKeyProcessor {
         self.process_events();
         self.has_quit
     }
+
+    fn check_save_slot_request(&mut self) -> Option<SaveSlotRequest> {
+        self.process_events();
+        self.save_slot_request.take()
+    }
+
+    fn check_rewind(&mut self) -> bool {
+        self.process_events();
+        self.rewinding
+    }
 }
 
 impl AudioOutput for SdlAudioProcessor {
@@ -194,35 +219,57 @@ impl AudioOutput for SdlAudioProcessor {
     fn stop_audio(&mut self) {
         self.audio_device.pause();
     }
+
+    fn set_pattern(&mut self, pattern : &[u8 ; PATTERN_SIZE]) {
+        self.audio_device.lock().pattern = *pattern;
+    }
+
+    fn set_pitch(&mut self, pitch : u8) {
+        self.audio_device.lock().set_pitch(pitch);
+    }
 }
 
-struct SquareWaveCallback {
+///
+/// Plays back the XO-CHIP 1-bit sample pattern, looping it for as long
+/// as the device is resumed.
+struct PatternCallback {
+    pattern : [u8 ; PATTERN_SIZE],
     offset : f32, 
     step : f32, 
+    sample_freq : f32,
     volume : f32,
 }
 
-impl SquareWaveCallback {
-    fn new(volume : f32, desired_freq : f32, sample_freq : f32) -> SquareWaveCallback {
-        SquareWaveCallback {
+impl PatternCallback {
+    fn new(volume : f32, sample_freq : f32) -> PatternCallback {
+        PatternCallback {
+            pattern : DEFAULT_PATTERN,
             offset : 0.0,
-            volume : volume,
-            step : desired_freq/sample_freq
+            volume,
+            sample_freq,
+            step : pitch_to_rate(DEFAULT_PITCH)/sample_freq
         }
     }
+
+    fn set_pitch(&mut self, pitch : u8) {
+        self.step = pitch_to_rate(pitch)/self.sample_freq;
+    }
 }
 
-impl AudioCallback for SquareWaveCallback {
+impl AudioCallback for PatternCallback {
     type Channel = f32;
     fn callback(&mut self, output_buffer : &mut [f32]) {
+        let bit_count = (PATTERN_SIZE * 8) as f32;
         for x in output_buffer.iter_mut() {
-            if self.offset <= 0.5 {
+            let bit = self.offset as usize;
+            let byte = self.pattern[bit / 8];
+            if byte & (0x80 >> (bit % 8)) != 0 {
                 *x = self.volume;
             }
             else {
                 *x = -self.volume;
             }
-            self.offset = (self.offset + self.step) % 1.0;
+            self.offset = (self.offset + self.step) % bit_count;
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,117 @@
+use log::{self, Log, LevelFilter, Metadata, Record};
+
+use std::env;
+use std::io::{self, Write};
+
+///
+/// The environment variable read for the log settings when none are passed in.
+pub const LOG_ENV_VAR : &str = "CHIP8_LOG";
+
+///
+/// The log targets of the emulator's subsystems.
+///
+/// * ```cpu```: the instructions run and the errors they hit.
+/// * ```display```: sprites drawn and, at ```trace```, every frame.
+/// * ```audio```: the tone starting and stopping.
+/// * ```input```: keys pressed and released.
+/// * ```timing```: timer ticks and the instructions run per second.
+pub const SUBSYSTEMS : [&str ; 5] = ["cpu", "display", "audio", "input", "timing"];
+
+///
+/// The level each log target is shown at.
+///
+/// Settings are written like ```warn,cpu=debug,display=trace```: a bare level
+/// sets the level for every target, and ```target=level``` overrides it for one
+/// target and anything under it, so ```chip8_emu=debug``` covers every module.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogConfig {
+    pub default : LevelFilter,
+    pub targets : Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogConfig {
+    fn default() -> LogConfig {
+        LogConfig {
+            default : LevelFilter::Error,
+            targets : Vec::new(),
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn parse(spec : &str) -> Result<LogConfig, String> {
+        let mut config = LogConfig::default();
+        for part in spec.split(',').map(|part| part.trim()).filter(|part| !part.is_empty()) {
+            let mut pieces = part.splitn(2, '=');
+            let first = pieces.next().unwrap_or("");
+            match pieces.next() {
+                Some(level) => {
+                    let level = parse_level(level)?;
+                    config.targets.push((first.to_owned(), level));
+                },
+                None => config.default = parse_level(first)?,
+            }
+        }
+        Ok(config)
+    }
+
+    ///
+    /// The level logs for ```target``` are shown at. Later settings win.
+    pub fn level_for(&self, target : &str) -> LevelFilter {
+        self.targets.iter().rev()
+            .find(|(name, _)| target == name || (target.starts_with(name.as_str()) && target[name.len() ..].starts_with("::")))
+            .map(|&(_, level)| level)
+            .unwrap_or(self.default)
+    }
+
+    ///
+    /// The most verbose level any target is shown at.
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|&(_, level)| level).fold(self.default, |max, level| max.max(level))
+    }
+}
+
+fn parse_level(name : &str) -> Result<LevelFilter, String> {
+    name.trim().parse::<LevelFilter>()
+        .map_err(|_| format!("Unknown log level {}; expected off, error, warn, info, debug, or trace.", name))
+}
+
+///
+/// Writes every log shown by its ```LogConfig``` to STDERR, prefixed by its level and target.
+pub struct Logger {
+    config : LogConfig,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata : &Metadata) -> bool {
+        metadata.level() <= self.config.level_for(metadata.target())
+    }
+
+    fn log(&self, record : &Record) {
+        if self.enabled(record.metadata()) {
+            let stderr = io::stderr();
+            let _ = writeln!(stderr.lock(), "[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+///
+/// Starts logging with the settings in ```spec```, or in the ```CHIP8_LOG```
+/// environment variable if there are none. Without either, only errors are shown.
+pub fn init(spec : Option<&str>) -> Result<(), String> {
+    let config = match spec {
+        Some(spec) => LogConfig::parse(spec)?,
+        None => match env::var(LOG_ENV_VAR) {
+            Ok(spec) => LogConfig::parse(&spec)?,
+            Err(_) => LogConfig::default(),
+        },
+    };
+    log::set_max_level(config.max_level());
+    log::set_boxed_logger(Box::new(Logger { config }))
+        .map_err(|err| err.to_string())
+}
+
@@ -1,2 +1,20 @@
-#[macro_use]
-pub mod debug_utils;
\ No newline at end of file
+pub mod logger;
+
+#[cfg(test)]
+mod tests {
+    use super::logger::LogConfig;
+    use log::LevelFilter;
+
+    #[test]
+    fn test_log_config() {
+        assert_eq!(LogConfig::parse("").unwrap(), LogConfig::default());
+        let config = LogConfig::parse("warn, cpu=trace,display=off,chip8_emu=debug").unwrap();
+        assert_eq!(config.level_for("audio"), LevelFilter::Warn);
+        assert_eq!(config.level_for("cpu"), LevelFilter::Trace);
+        assert_eq!(config.level_for("display"), LevelFilter::Off);
+        assert_eq!(config.level_for("chip8_emu::debug_mod::dap"), LevelFilter::Debug);
+        assert_eq!(config.level_for("chip8_emulator"), LevelFilter::Warn);
+        assert_eq!(config.max_level(), LevelFilter::Trace);
+        assert!(LogConfig::parse("cpu=loud").is_err());
+    }
+}